@@ -7,17 +7,25 @@ declaration    : classDecl
                | varDecl
                | statement ;
                
-varDecl        : 'var' typedVar ( '=' expression )? ';' ;
+varDecl        : 'var' IDENTIFIER ( ':' type )? ( '=' expression )? ';' ;
 typedVar       : IDENTIFIER ':' type ;
-type           : IDENTIFIER ( '.' IDENTIFIER )* ;
-
-funDecl        : 'fun' IDENTIFIER? function ;
+type           : postfixType ( '|' postfixType )* ;
+postfixType    : primaryType ( '[' ']' | '?' )* ;
+primaryType    : IDENTIFIER ( '.' IDENTIFIER )* typeArguments? | 'null'
+               | '(' ( type ( ',' type )* )? ')' '->' type
+               | '(' type ')' ;
+typeArguments  : '<' type ( ',' type )* '>' ;
+typeParameters : '<' typeParameter ( ',' typeParameter )* '>' ;
+typeParameter  : IDENTIFIER ( 'extends' type )? ;
+
+funDecl        : 'fun' IDENTIFIER typeParameters? function ;
 function       : '(' parameters? ')' ( ':' type )? block ;
 parameters     : typedVar ( ',' typedVar )* ;
 
-classDecl      : 'class' IDENTIFIER ( 'extends' type )?
-                 '{' ( method | field )* '}' ;
-method         : IDENTIFIER function ;
+classDecl      : 'class' IDENTIFIER typeParameters? ( 'extends' type )?
+                 '{' ( constructor | method | field )* '}' ;
+constructor    : IDENTIFIER '(' parameters? ')' block ;
+method         : IDENTIFIER typeParameters? function ;
 field          : typedVar ( '=' expression )? ';' ;
 
 statement      : exprStmt
@@ -52,22 +60,39 @@ block          : '{' declaration* '}' ;
 exprStmt       : expression ';' ;
 
 expression     : assignment ;
-assignment     : ( call '.' )? IDENTIFIER '=' assignment
-               | logic_or ;
+assignment     : ( call '.' )? IDENTIFIER assignOp assignment
+               | conditional ;
+conditional    : coalesce ( '?' expression ':' assignment )? ;
+coalesce       : logic_or ( '??' logic_or )* ;
+assignOp       : '=' | '+=' | '-=' | '*=' | '/=' | '%=' | '**='
+               | '&=' | '|=' | '^=' | '<<=' | '>>=' | '??=' ;
 logic_or       : logic_and ( 'or' logic_and )* ;
 logic_and      : equality ( 'and' equality )* ;
 equality       : comparison ( ( '!=' | '==' ) comparison )* ;
-comparison     : term ( ( '>' | '>=' | '<' | '<=' ) term )* ;
+comparison     : bit_or ( ( '>' | '>=' | '<' | '<=' ) bit_or )* ;
+bit_or         : bit_xor ( '|' bit_xor )* ;
+bit_xor        : bit_and ( '^' bit_and )* ;
+bit_and        : shift ( '&' shift )* ;
+shift          : term ( ( '<<' | '>>' ) term )* ;
 term           : factor ( ( '-' | '+' ) factor )* ;
-factor         : unary ( ( '/' | '*' ) unary )* ;
-unary          : ( '!' | '-' ) unary | update ;
+factor         : unary ( ( '/' | '*' | '%' ) unary )* ;
+unary          : ( '!' | '-' | '~' ) unary | power ;
+power          : update ( '**' unary )? ;
 update         : call ( '++' | '--' )? | ( '++' | '--' ) call ;
-arguments      : expression ( ',' expression )* ;
+arguments      : element ( ',' element )* ','? ;
+element        : '...'? expression ;
 call           : primary ( '(' arguments? ')' | '.' IDENTIFIER
-               | '[' expression ']' )* ;
+               | '[' expression ']' | '?.' IDENTIFIER
+               | '?.' '(' arguments? ')' | '?.' '[' expression ']' )* ;
 primary        : 'true' | 'false' | 'null' | 'this' | 'super'
                | NUMBER | STRING | IDENTIFIER | '(' expression ')'
-               | 'fun' function ;
+               | 'fun' function
+               | '(' ')' | '(' element ',' arguments? ')' | '(' '...' expression ')'
+               | '[' arguments? ']'
+               | '{' ( entry ( ',' entry )* ','? )? '}' ;
+// A '{' at the start of a statement always opens a block, map literals are
+// only recognized in expression position.
+entry          : expression ':' expression | '...' expression ;
 
 NUMBER: DIGIT ('.' DIGIT)?;
 STRING: ('"' (~('"'))*  '"');
@@ -0,0 +1,392 @@
+use crate::parser::expr::*;
+use crate::parser::stmt::*;
+use crate::resolver::{node_id, Access, Location, NodeId, Resolution};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+/// Variable a function captures from an enclosing function.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Captured {
+    /// Local declaration, index into `Resolution::declarations`.
+    Declaration(usize),
+    /// The receiver of the enclosing method, `this` and `super` inside
+    /// closures refer to it.
+    This,
+}
+
+/// Entry of a function's upvalue list.
+#[derive(Debug, Clone, Copy)]
+pub struct Upvalue {
+    pub captured: Captured,
+    /// Slot of the variable in the directly enclosing function when
+    /// `is_local`, otherwise index into the enclosing function's upvalues.
+    pub index: usize,
+    pub is_local: bool,
+}
+
+/// Result of the capture analysis consumed by the bytecode compiler.
+#[derive(Debug, Default)]
+pub struct Captures {
+    /// Upvalue lists of functions capturing variables, keyed by the
+    /// `FunctionStatement`, `LambdaFunctionExpression` or `ClassMember` (for
+    /// methods and field initializers) node. Functions capturing nothing are
+    /// left out.
+    pub functions: HashMap<NodeId, Vec<Upvalue>>,
+    /// `IdentifierExpression`, `ThisExpression`, `SuperExpression` and
+    /// superclass `TypeReference` nodes using a captured variable to the upvalue index in the function
+    /// containing them.
+    pub uses: HashMap<NodeId, usize>,
+    /// Declarations captured by at least one closure.
+    pub captured: HashSet<usize>,
+    /// Captured declarations that may change once captured. Their slots have
+    /// to be moved to the heap when they go out of scope, the others are
+    /// copied into the closure when it's created.
+    pub mutated: HashSet<usize>,
+}
+
+struct Frame {
+    node: NodeId,
+    upvalues: Vec<Upvalue>,
+    /// Whether slot 0 of the frame holds `this`.
+    method: bool,
+}
+
+/// Use or write of a local variable, in source order.
+struct VariableAccess {
+    declaration: usize,
+    order: usize,
+    /// Loops enclosing the access.
+    loops: Vec<NodeId>,
+}
+
+/// Finds the local variables closures capture and assigns upvalue indices to
+/// them, following the resolver's bindings.
+///
+/// A capture inside a function nested several levels deep is threaded
+/// through the upvalue list of every function in between.
+pub struct CaptureAnalyzer<'a> {
+    resolution: &'a Resolution,
+    captures: Captures,
+    frames: Vec<Frame>,
+    loops: Vec<NodeId>,
+    /// Number of loops enclosing each declaration.
+    declaration_loops: HashMap<usize, usize>,
+    order: usize,
+    /// Reads of captured declarations from nested functions.
+    capture_points: Vec<VariableAccess>,
+    /// Definitions and writes made by the declaring function itself.
+    local_writes: Vec<VariableAccess>,
+}
+
+impl<'a> CaptureAnalyzer<'a> {
+    pub fn new(resolution: &'a Resolution) -> Self {
+        CaptureAnalyzer {
+            resolution,
+            captures: Captures::default(),
+            frames: vec![Frame {
+                node: 0,
+                upvalues: Vec::new(),
+                method: false,
+            }],
+            loops: Vec::new(),
+            declaration_loops: HashMap::new(),
+            order: 0,
+            capture_points: Vec::new(),
+            local_writes: Vec::new(),
+        }
+    }
+
+    pub fn analyze(mut self, program: &Statement) -> Captures {
+        self.statement(program);
+
+        for write in &self.local_writes {
+            let loops = self.declaration_loops[&write.declaration];
+            let mutated = self.capture_points.iter().any(|capture| {
+                capture.declaration == write.declaration
+                    && (write.order > capture.order
+                        // A loop around both runs the write again after
+                        // the capture
+                        || (write.loops.len() > loops
+                            && capture.loops.len() > loops
+                            && write.loops[loops] == capture.loops[loops]))
+            });
+            if mutated {
+                self.captures.mutated.insert(write.declaration);
+            }
+        }
+
+        self.captures
+    }
+
+    fn statements(&mut self, statements: &[Rc<Statement>]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Block(stmt) => self.statements(&stmt.declarations),
+            Statement::Break(_) | Statement::Continue(_) => {}
+            Statement::Class(stmt) => {
+                if let Some(extends) = &stmt.extends {
+                    self.variable(node_id(extends));
+                }
+                for member in &stmt.members {
+                    match member {
+                        ClassMember::Field { value, .. } => {
+                            if let Some(value) = value {
+                                self.begin_function(node_id(member), true);
+                                self.expression(value);
+                                self.end_function();
+                            }
+                        }
+                        ClassMember::Method {
+                            parameters, body, ..
+                        }
+                        | ClassMember::Constructor {
+                            parameters, body, ..
+                        } => self.function(node_id(member), true, parameters, body),
+                    }
+                }
+                self.declared(stmt);
+            }
+            Statement::Expression(stmt) => self.expression(&stmt.expression),
+            Statement::For(stmt) => {
+                if let Some(initializer) = &stmt.initializer {
+                    self.statement(initializer);
+                }
+                self.loops.push(node_id(stmt));
+                if let Some(condition) = &stmt.condition {
+                    self.expression(condition);
+                }
+                self.statement(&stmt.body);
+                if let Some(update) = &stmt.update {
+                    self.expression(update);
+                }
+                self.loops.pop();
+            }
+            Statement::Function(stmt) => {
+                self.function(node_id(stmt), false, &stmt.parameters, &stmt.body);
+                self.declared(stmt);
+            }
+            Statement::If(stmt) => {
+                self.expression(&stmt.condition);
+                self.statement(&stmt.then_branch);
+                if let Some(else_branch) = &stmt.else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Statement::Program(stmt) => self.statements(&stmt.declarations),
+            Statement::Return(stmt) => {
+                if let Some(value) = &stmt.value {
+                    self.expression(value);
+                }
+            }
+            Statement::Var(stmt) => {
+                if let Some(value) = &stmt.value {
+                    self.expression(value);
+                }
+                self.declared(stmt);
+            }
+            Statement::While(stmt) => {
+                self.loops.push(node_id(stmt));
+                self.expression(&stmt.condition);
+                self.statement(&stmt.body);
+                self.loops.pop();
+            }
+        }
+    }
+
+    /// Records the point where the declaration of `node` gets its value.
+    /// Functions and classes are only defined once created, so a closure
+    /// created in the meantime sees the variable change.
+    fn declared<T>(&mut self, node: &T) {
+        if let Some(&declaration) = self.resolution.declared.get(&node_id(node)) {
+            self.declaration_loops.insert(declaration, self.loops.len());
+            self.order += 1;
+            self.local_writes.push(VariableAccess {
+                declaration,
+                order: self.order,
+                loops: self.loops.clone(),
+            });
+        }
+    }
+
+    fn begin_function(&mut self, node: NodeId, method: bool) {
+        self.frames.push(Frame {
+            node,
+            upvalues: Vec::new(),
+            method,
+        });
+    }
+
+    fn end_function(&mut self) {
+        let frame = self.frames.pop().unwrap();
+        if !frame.upvalues.is_empty() {
+            self.captures.functions.insert(frame.node, frame.upvalues);
+        }
+    }
+
+    fn function(
+        &mut self,
+        node: NodeId,
+        method: bool,
+        parameters: &[FunctionParameter],
+        body: &[Rc<Statement>],
+    ) {
+        self.begin_function(node, method);
+        for parameter in parameters {
+            self.declared(parameter);
+        }
+        self.statements(body);
+        self.end_function();
+    }
+
+    /// Adds `captured` to the upvalues of `frame` and of every function
+    /// between it and `declaring_frame`. Returns its upvalue index in `frame`.
+    fn capture(
+        &mut self,
+        frame: usize,
+        declaring_frame: usize,
+        captured: Captured,
+        slot: usize,
+    ) -> usize {
+        let existing = self.frames[frame]
+            .upvalues
+            .iter()
+            .position(|upvalue| upvalue.captured == captured);
+        if let Some(index) = existing {
+            return index;
+        }
+
+        let upvalue = if frame - 1 == declaring_frame {
+            Upvalue {
+                captured,
+                index: slot,
+                is_local: true,
+            }
+        } else {
+            Upvalue {
+                captured,
+                index: self.capture(frame - 1, declaring_frame, captured, slot),
+                is_local: false,
+            }
+        };
+        let upvalues = &mut self.frames[frame].upvalues;
+        upvalues.push(upvalue);
+        upvalues.len() - 1
+    }
+
+    /// Records a use of the variable bound to `node`.
+    fn variable(&mut self, node: NodeId) {
+        let binding = match self.resolution.bindings.get(&node) {
+            Some(binding) => *binding,
+            None => return,
+        };
+        let slot = match self.resolution.declarations[binding.declaration].location {
+            Location::Local { slot } => slot,
+            Location::Global => return,
+        };
+
+        self.order += 1;
+        let access = VariableAccess {
+            declaration: binding.declaration,
+            order: self.order,
+            loops: self.loops.clone(),
+        };
+
+        if binding.hops == 0 {
+            if binding.access != Access::Read {
+                self.local_writes.push(access);
+            }
+            return;
+        }
+
+        let frame = self.frames.len() - 1;
+        let captured = Captured::Declaration(binding.declaration);
+        let index = self.capture(frame, frame - binding.hops, captured, slot);
+        self.captures.uses.insert(node, index);
+        self.captures.captured.insert(binding.declaration);
+        // Writing a variable from a closure always counts as mutating it
+        if binding.access != Access::Read {
+            self.captures.mutated.insert(binding.declaration);
+        }
+        self.capture_points.push(access);
+    }
+
+    /// Captures the method receiver for `this` or `super` used in a closure.
+    fn receiver(&mut self, node: NodeId) {
+        let frame = self.frames.len() - 1;
+        let method = match self.frames.iter().rposition(|frame| frame.method) {
+            Some(method) => method,
+            None => return,
+        };
+        if method != frame {
+            let index = self.capture(frame, method, Captured::This, 0);
+            self.captures.uses.insert(node, index);
+        }
+    }
+
+    fn expressions(&mut self, expressions: &[Rc<Expression>]) {
+        for expression in expressions {
+            self.expression(expression);
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Array(expr) => self.expressions(&expr.elements),
+            Expression::Assignment(expr) => {
+                self.expression(&expr.right);
+                self.expression(&expr.left);
+            }
+            Expression::Binary(expr) => {
+                self.expression(&expr.left);
+                self.expression(&expr.right);
+            }
+            Expression::Call(expr) => {
+                self.expression(&expr.callee);
+                self.expressions(&expr.arguments);
+            }
+            Expression::Conditional(expr) => {
+                self.expression(&expr.condition);
+                self.expression(&expr.then_branch);
+                self.expression(&expr.else_branch);
+            }
+            Expression::Grouping(expr) => self.expression(&expr.expression),
+            Expression::Identifier(expr) => self.variable(node_id(expr)),
+            Expression::Index(expr) => {
+                self.expression(&expr.object);
+                self.expression(&expr.index);
+            }
+            Expression::LambdaFunction(expr) => {
+                self.function(node_id(expr), false, &expr.parameters, &expr.body)
+            }
+            Expression::Literal(_) => {}
+            Expression::Logical(expr) => {
+                self.expression(&expr.left);
+                self.expression(&expr.right);
+            }
+            Expression::Map(expr) => {
+                for entry in &expr.entries {
+                    match entry {
+                        MapEntry::Pair { key, value } => {
+                            self.expression(key);
+                            self.expression(value);
+                        }
+                        MapEntry::Spread { expression } => self.expression(expression),
+                    }
+                }
+            }
+            Expression::Member(expr) => self.expression(&expr.object),
+            Expression::OptionalChain(expr) => self.expression(&expr.expression),
+            Expression::Spread(expr) => self.expression(&expr.expression),
+            Expression::Super(expr) => self.receiver(node_id(expr)),
+            Expression::This(expr) => self.receiver(node_id(expr)),
+            Expression::Tuple(expr) => self.expressions(&expr.elements),
+            Expression::Unary(expr) => self.expression(&expr.right),
+            Expression::Update(expr) => self.expression(&expr.expression),
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use crate::diagnostic::Diagnostic;
+use crate::parser::expr::*;
+use crate::parser::stmt::*;
+use crate::parser::types::{TypeName, TypeReference};
+use crate::scanner::token::{Literal, Token};
+use std::rc::Rc;
+
+/// Loop enclosing the statement being analyzed.
+struct Loop {
+    /// Whether a `break` can leave the loop.
+    broken: bool,
+}
+
+/// Control flow pass. Reports non-void functions that can finish without
+/// returning a value, `break` and `continue` outside of loops, `return` in
+/// top level code and warns about statements that never run.
+pub struct FlowAnalyzer {
+    /// Loops of the innermost function, `break` can't cross function bodies.
+    loops: Vec<Loop>,
+    function_depth: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl FlowAnalyzer {
+    pub fn new() -> Self {
+        FlowAnalyzer {
+            loops: Vec::new(),
+            function_depth: 0,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn analyze(mut self, program: &Statement) -> Vec<Diagnostic> {
+        self.statement(program);
+        self.diagnostics
+    }
+
+    fn error(&mut self, message: String, token: &Token) {
+        self.diagnostics.push(Diagnostic::error(message, token));
+    }
+
+    /// Analyzes the statements of a block and returns whether execution can
+    /// continue after the last one.
+    fn statements(&mut self, statements: &[Rc<Statement>]) -> bool {
+        let mut completes = true;
+        let mut reported = false;
+        for statement in statements {
+            // Only the first unreachable statement of a block is reported
+            if !completes && !reported {
+                self.diagnostics.push(Diagnostic::warning_at(
+                    String::from("Unreachable code"),
+                    statement.range(),
+                ));
+                reported = true;
+            }
+            completes = self.statement(statement) && completes;
+        }
+        completes
+    }
+
+    /// Analyzes the statement and returns whether execution can continue
+    /// after it.
+    fn statement(&mut self, statement: &Statement) -> bool {
+        match statement {
+            Statement::Block(stmt) => self.statements(&stmt.declarations),
+            Statement::Break(stmt) => {
+                // Misplaced jumps are errors already, don't mark code after
+                // them as unreachable too
+                match self.loops.last_mut() {
+                    Some(current) => {
+                        current.broken = true;
+                        false
+                    }
+                    None => {
+                        self.error(
+                            String::from("Can't use 'break' outside of a loop"),
+                            &stmt.keyword,
+                        );
+                        true
+                    }
+                }
+            }
+            Statement::Class(stmt) => {
+                for member in &stmt.members {
+                    match member {
+                        ClassMember::Field { value, .. } => {
+                            if let Some(value) = value {
+                                self.expression(value);
+                            }
+                        }
+                        ClassMember::Method {
+                            name,
+                            return_type,
+                            body,
+                            ..
+                        } => self.function(name, false, return_type, body),
+                        ClassMember::Constructor { name, body, .. } => {
+                            self.function(name, false, &None, body)
+                        }
+                    }
+                }
+                true
+            }
+            Statement::Continue(stmt) => {
+                if self.loops.is_empty() {
+                    self.error(
+                        String::from("Can't use 'continue' outside of a loop"),
+                        &stmt.keyword,
+                    );
+                    return true;
+                }
+                false
+            }
+            Statement::Expression(stmt) => {
+                self.expression(&stmt.expression);
+                true
+            }
+            Statement::For(stmt) => {
+                if let Some(initializer) = &stmt.initializer {
+                    self.statement(initializer);
+                }
+                if let Some(condition) = &stmt.condition {
+                    self.expression(condition);
+                }
+                if let Some(update) = &stmt.update {
+                    self.expression(update);
+                }
+                let infinite = stmt.condition.as_ref().is_none_or(is_true);
+                self.loop_body(infinite, &stmt.body)
+            }
+            Statement::Function(stmt) => {
+                self.function(&stmt.name, false, &stmt.return_type, &stmt.body);
+                true
+            }
+            Statement::If(stmt) => {
+                self.expression(&stmt.condition);
+                let then_completes = self.statement(&stmt.then_branch);
+                let else_completes = match &stmt.else_branch {
+                    Some(else_branch) => self.statement(else_branch),
+                    None => true,
+                };
+                then_completes || else_completes
+            }
+            Statement::Program(stmt) => self.statements(&stmt.declarations),
+            Statement::Return(stmt) => {
+                if let Some(value) = &stmt.value {
+                    self.expression(value);
+                }
+                if self.function_depth == 0 {
+                    self.error(
+                        String::from("Can't return from top-level code"),
+                        &stmt.keyword,
+                    );
+                    return true;
+                }
+                false
+            }
+            Statement::Var(stmt) => {
+                if let Some(value) = &stmt.value {
+                    self.expression(value);
+                }
+                true
+            }
+            Statement::While(stmt) => {
+                self.expression(&stmt.condition);
+                self.loop_body(is_true(&stmt.condition), &stmt.body)
+            }
+        }
+    }
+
+    /// Analyzes a loop body and returns whether execution can continue after
+    /// the loop. Loops without a condition only end through `break`.
+    fn loop_body(&mut self, infinite: bool, body: &Statement) -> bool {
+        self.loops.push(Loop { broken: false });
+        self.statement(body);
+        let current = self.loops.pop().unwrap();
+        !infinite || current.broken
+    }
+
+    /// Analyzes a function body. Lambdas pass their `fun` keyword as `token`
+    /// since they have no name.
+    fn function(
+        &mut self,
+        token: &Token,
+        lambda: bool,
+        return_type: &Option<TypeReference>,
+        body: &[Rc<Statement>],
+    ) {
+        let loops = std::mem::take(&mut self.loops);
+        self.function_depth += 1;
+        let completes = self.statements(body);
+        self.function_depth -= 1;
+        self.loops = loops;
+
+        if completes && returns_value(return_type) {
+            let message = if lambda {
+                String::from("Lambda can finish without returning a value")
+            } else {
+                format!(
+                    "Function '{}' can finish without returning a value",
+                    token.lexme
+                )
+            };
+            self.error(message, token);
+        }
+    }
+
+    /// Visits sub-expressions looking for lambda bodies.
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Array(expr) => self.expressions(&expr.elements),
+            Expression::Assignment(expr) => {
+                self.expression(&expr.left);
+                self.expression(&expr.right);
+            }
+            Expression::Binary(expr) => {
+                self.expression(&expr.left);
+                self.expression(&expr.right);
+            }
+            Expression::Call(expr) => {
+                self.expression(&expr.callee);
+                self.expressions(&expr.arguments);
+            }
+            Expression::Conditional(expr) => {
+                self.expression(&expr.condition);
+                self.expression(&expr.then_branch);
+                self.expression(&expr.else_branch);
+            }
+            Expression::Grouping(expr) => self.expression(&expr.expression),
+            Expression::Index(expr) => {
+                self.expression(&expr.object);
+                self.expression(&expr.index);
+            }
+            Expression::LambdaFunction(expr) => {
+                self.function(&expr.keyword, true, &expr.return_type, &expr.body);
+            }
+            Expression::Logical(expr) => {
+                self.expression(&expr.left);
+                self.expression(&expr.right);
+            }
+            Expression::Map(expr) => {
+                for entry in &expr.entries {
+                    match entry {
+                        MapEntry::Pair { key, value } => {
+                            self.expression(key);
+                            self.expression(value);
+                        }
+                        MapEntry::Spread { expression } => self.expression(expression),
+                    }
+                }
+            }
+            Expression::Member(expr) => self.expression(&expr.object),
+            Expression::OptionalChain(expr) => self.expression(&expr.expression),
+            Expression::Spread(expr) => self.expression(&expr.expression),
+            Expression::Tuple(expr) => self.expressions(&expr.elements),
+            Expression::Unary(expr) => self.expression(&expr.right),
+            Expression::Update(expr) => self.expression(&expr.expression),
+            Expression::Identifier(_)
+            | Expression::Literal(_)
+            | Expression::Super(_)
+            | Expression::This(_) => {}
+        }
+    }
+
+    fn expressions(&mut self, expressions: &[Rc<Expression>]) {
+        for expression in expressions {
+            self.expression(expression);
+        }
+    }
+}
+
+/// Whether execution can continue after `statement` when it runs inside a
+/// function body. `break` and `continue` leaving the statement count as
+/// jumping away from it.
+pub fn can_complete(statement: &Statement) -> bool {
+    let mut analyzer = FlowAnalyzer {
+        loops: vec![Loop { broken: false }],
+        function_depth: 1,
+        diagnostics: Vec::new(),
+    };
+    analyzer.statement(statement)
+}
+
+/// Whether the condition is the `true` literal, making the loop infinite.
+fn is_true(condition: &Expression) -> bool {
+    match condition {
+        Expression::Literal(LiteralExpression {
+            value: Literal::Boolean(value),
+            ..
+        }) => *value,
+        Expression::Grouping(expr) => is_true(&expr.expression),
+        _ => false,
+    }
+}
+
+fn returns_value(return_type: &Option<TypeReference>) -> bool {
+    match return_type {
+        Some(TypeReference {
+            type_name: TypeName::Identifier { name },
+            ..
+        }) => name.lexme != "void",
+        Some(_) => true,
+        None => false,
+    }
+}
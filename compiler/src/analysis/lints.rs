@@ -0,0 +1,61 @@
+use crate::diagnostic::Diagnostic;
+use crate::resolver::{Access, DeclarationKind, Resolution};
+
+/// Reports unused variables and parameters, and variables hiding another
+/// declaration. Names starting with `_` are exempt, e.g. a parameter only
+/// kept to match a callback signature.
+pub struct Linter<'a> {
+    resolution: &'a Resolution,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    pub fn new(resolution: &'a Resolution) -> Self {
+        Linter {
+            resolution,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn lint(mut self) -> Vec<Diagnostic> {
+        let mut reads = vec![0; self.resolution.declarations.len()];
+        let mut writes = vec![0; self.resolution.declarations.len()];
+        for binding in self.resolution.bindings.values() {
+            match binding.access {
+                Access::Read | Access::ReadWrite => reads[binding.declaration] += 1,
+                Access::Write => writes[binding.declaration] += 1,
+            }
+        }
+
+        for (index, declaration) in self.resolution.declarations.iter().enumerate() {
+            let name = &declaration.name;
+            if name.lexme.starts_with('_') {
+                continue;
+            }
+
+            let variable = matches!(
+                declaration.kind,
+                DeclarationKind::Variable | DeclarationKind::Parameter
+            );
+            if variable && self.resolution.shadowed.contains_key(&index) {
+                self.diagnostics.push(Diagnostic::warning(
+                    format!("'{}' shadows a declaration of an outer scope", name.lexme),
+                    name,
+                ));
+            }
+
+            let message = match declaration.kind {
+                _ if reads[index] > 0 => continue,
+                DeclarationKind::Variable if writes[index] > 0 => {
+                    format!("Variable '{}' is assigned but never read", name.lexme)
+                }
+                DeclarationKind::Variable => format!("Unused variable '{}'", name.lexme),
+                DeclarationKind::Parameter => format!("Unused parameter '{}'", name.lexme),
+                DeclarationKind::Function | DeclarationKind::Class => continue,
+            };
+            self.diagnostics.push(Diagnostic::warning(message, name));
+        }
+
+        self.diagnostics
+    }
+}
@@ -0,0 +1,3 @@
+pub mod captures;
+pub mod flow;
+pub mod lints;
@@ -0,0 +1,355 @@
+use crate::scanner::pos::Pos;
+use crate::scanner::token::Literal;
+use crate::symbol::Symbol;
+use std::rc::Rc;
+
+/// Instructions of the virtual machine. Operands follow the opcode byte,
+/// `u16` operands are stored big endian.
+///
+/// Conditional jumps peek at the top value instead of popping it, and
+/// `Set*` instructions leave the assigned value on the stack since
+/// assignments are expressions.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[repr(u8)]
+pub enum OpCode {
+    /// `index: u16`, pushes a constant of the pool.
+    Constant,
+    Null,
+    True,
+    False,
+    Pop,
+    /// Duplicates the top value.
+    Dup,
+    /// Duplicates the two top values, keeping their order.
+    Dup2,
+    /// Swaps the two top values.
+    Swap,
+    /// `slot: u8`
+    GetLocal,
+    /// `slot: u8`
+    SetLocal,
+    /// `name: u16`
+    GetGlobal,
+    /// `name: u16`
+    SetGlobal,
+    /// `name: u16`, pops the value into a new global.
+    DefineGlobal,
+    /// `index: u8`
+    GetUpvalue,
+    /// `index: u8`
+    SetUpvalue,
+    /// Moves the local on top of the stack to the heap and pops it.
+    CloseUpvalue,
+    /// `name: u16`
+    GetProperty,
+    /// `name: u16`, pops the value and the object.
+    SetProperty,
+    /// `name: u16`, replaces `this` on top of the stack with the superclass
+    /// method bound to it.
+    GetSuper,
+    GetIndex,
+    /// Pops the value, the index and the object.
+    SetIndex,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    Negate,
+    Not,
+    BitNot,
+    /// `offset: u16`
+    Jump,
+    /// `offset: u16`
+    JumpIfFalse,
+    /// `offset: u16`
+    JumpIfNull,
+    /// `offset: u16`
+    JumpIfNotNull,
+    /// `offset: u16`, jumps backwards.
+    Loop,
+    /// `arguments: u8`
+    Call,
+    /// Calls the callee below an array holding the arguments.
+    CallSpread,
+    /// `name: u16, arguments: u8`, calls a method of the object below the
+    /// arguments.
+    Invoke,
+    /// `name: u16, arguments: u8`, calls a superclass method on `this`
+    /// below the arguments.
+    SuperInvoke,
+    /// `arguments: u8`, calls the superclass constructor on `this` below
+    /// the arguments.
+    SuperCall,
+    /// `function: u16`, followed by `kind: u8, index: u8` for every upvalue
+    /// of the function, see `UpvalueKind`.
+    Closure,
+    Return,
+    /// `length: u16`
+    Array,
+    /// Pops a value and appends it to the array below.
+    ArrayPush,
+    /// Pops an array and appends its elements to the array below.
+    ArrayExtend,
+    /// `entries: u16`, pops keys and values pushed in pairs.
+    Map,
+    /// Pops a key and a value and inserts them into the map below.
+    MapInsert,
+    /// Pops a map and inserts its entries into the map below.
+    MapExtend,
+    /// `length: u16`
+    Tuple,
+    /// `name: u16`
+    Class,
+    /// Pops the superclass and makes the class below inherit from it.
+    Inherit,
+    /// `name: u16`, pops a closure into a method of the class below.
+    Method,
+    /// Pops a closure into the constructor of the class below.
+    Constructor,
+    /// `name: u16`, pops the initializer closure (or null) of a field of the
+    /// class below.
+    Field,
+}
+
+/// Opcodes in declaration order, indexed by their byte value.
+static OPCODES: &[OpCode] = &[
+    OpCode::Constant,
+    OpCode::Null,
+    OpCode::True,
+    OpCode::False,
+    OpCode::Pop,
+    OpCode::Dup,
+    OpCode::Dup2,
+    OpCode::Swap,
+    OpCode::GetLocal,
+    OpCode::SetLocal,
+    OpCode::GetGlobal,
+    OpCode::SetGlobal,
+    OpCode::DefineGlobal,
+    OpCode::GetUpvalue,
+    OpCode::SetUpvalue,
+    OpCode::CloseUpvalue,
+    OpCode::GetProperty,
+    OpCode::SetProperty,
+    OpCode::GetSuper,
+    OpCode::GetIndex,
+    OpCode::SetIndex,
+    OpCode::Equal,
+    OpCode::NotEqual,
+    OpCode::Less,
+    OpCode::LessEqual,
+    OpCode::Greater,
+    OpCode::GreaterEqual,
+    OpCode::Add,
+    OpCode::Subtract,
+    OpCode::Multiply,
+    OpCode::Divide,
+    OpCode::Modulo,
+    OpCode::Power,
+    OpCode::BitAnd,
+    OpCode::BitOr,
+    OpCode::BitXor,
+    OpCode::ShiftLeft,
+    OpCode::ShiftRight,
+    OpCode::Negate,
+    OpCode::Not,
+    OpCode::BitNot,
+    OpCode::Jump,
+    OpCode::JumpIfFalse,
+    OpCode::JumpIfNull,
+    OpCode::JumpIfNotNull,
+    OpCode::Loop,
+    OpCode::Call,
+    OpCode::CallSpread,
+    OpCode::Invoke,
+    OpCode::SuperInvoke,
+    OpCode::SuperCall,
+    OpCode::Closure,
+    OpCode::Return,
+    OpCode::Array,
+    OpCode::ArrayPush,
+    OpCode::ArrayExtend,
+    OpCode::Map,
+    OpCode::MapInsert,
+    OpCode::MapExtend,
+    OpCode::Tuple,
+    OpCode::Class,
+    OpCode::Inherit,
+    OpCode::Method,
+    OpCode::Constructor,
+    OpCode::Field,
+];
+
+/// How a `Closure` instruction captures one of the function's upvalues.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum UpvalueKind {
+    /// Upvalue of the enclosing function.
+    Enclosing,
+    /// Local slot of the enclosing function, shared with it until the slot
+    /// goes out of scope.
+    Local,
+    /// Value of a local slot of the enclosing function, which never changes
+    /// once captured.
+    Copy,
+}
+
+impl UpvalueKind {
+    pub fn from_byte(byte: u8) -> Option<UpvalueKind> {
+        match byte {
+            0 => Some(UpvalueKind::Enclosing),
+            1 => Some(UpvalueKind::Local),
+            2 => Some(UpvalueKind::Copy),
+            _ => None,
+        }
+    }
+}
+
+impl OpCode {
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        OPCODES.get(byte as usize).copied()
+    }
+
+    /// Size in bytes of the operands following the opcode. `Closure` is
+    /// additionally followed by two bytes per upvalue.
+    pub fn operand_size(self) -> usize {
+        match self {
+            OpCode::GetLocal
+            | OpCode::SetLocal
+            | OpCode::GetUpvalue
+            | OpCode::SetUpvalue
+            | OpCode::Call
+            | OpCode::SuperCall => 1,
+            OpCode::Constant
+            | OpCode::GetGlobal
+            | OpCode::SetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::Jump
+            | OpCode::JumpIfFalse
+            | OpCode::JumpIfNull
+            | OpCode::JumpIfNotNull
+            | OpCode::Loop
+            | OpCode::Closure
+            | OpCode::Array
+            | OpCode::Map
+            | OpCode::Tuple
+            | OpCode::Class
+            | OpCode::Method
+            | OpCode::Field => 2,
+            OpCode::Invoke | OpCode::SuperInvoke => 3,
+            _ => 0,
+        }
+    }
+}
+
+/// Value known at compile time, stored in the constant pool of a chunk.
+#[derive(Debug, Clone)]
+pub enum Constant {
+    Int(i64),
+    Float(f64),
+    String(Symbol),
+    Function(Rc<Function>),
+}
+
+impl Constant {
+    /// Constant for a literal, `None` for literals with their own opcode.
+    pub fn from_literal(literal: &Literal) -> Option<Constant> {
+        match literal {
+            Literal::Integer(value) => Some(Constant::Int(*value)),
+            Literal::Float(value) => Some(Constant::Float(*value)),
+            Literal::String(value) => Some(Constant::String(Symbol::intern(value))),
+            Literal::Null | Literal::Boolean(_) => None,
+        }
+    }
+
+    /// Whether both constants can share a single pool entry.
+    fn same(&self, other: &Constant) -> bool {
+        match (self, other) {
+            (Constant::Int(a), Constant::Int(b)) => a == b,
+            (Constant::Float(a), Constant::Float(b)) => a.to_bits() == b.to_bits(),
+            (Constant::String(a), Constant::String(b)) => a == b,
+            (Constant::Function(a), Constant::Function(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// Compiled function body.
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+
+/// Bytecode of a single function along with its constants and debug info.
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Constant>,
+    /// Source position of the instructions as `(offset, pos)` pairs. Each
+    /// entry covers the code from its offset up to the next entry.
+    pub lines: Vec<(usize, Pos)>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, byte: u8, pos: Pos) {
+        if self.lines.last().is_none_or(|(_, last)| *last != pos) {
+            self.lines.push((self.code.len(), pos));
+        }
+        self.code.push(byte);
+    }
+
+    pub fn write_op(&mut self, op: OpCode, pos: Pos) {
+        self.write(op as u8, pos);
+    }
+
+    pub fn write_u16(&mut self, value: u16, pos: Pos) {
+        for byte in value.to_be_bytes() {
+            self.write(byte, pos);
+        }
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+
+    pub fn patch_u16(&mut self, offset: usize, value: u16) {
+        self.code[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+    }
+
+    /// Adds a constant to the pool, reusing an equal one, and returns its
+    /// index.
+    pub fn add_constant(&mut self, constant: Constant) -> usize {
+        if let Some(index) = self.constants.iter().position(|c| c.same(&constant)) {
+            return index;
+        }
+        self.constants.push(constant);
+        self.constants.len() - 1
+    }
+
+    /// Source position of the instruction at `offset`.
+    pub fn pos_at(&self, offset: usize) -> Pos {
+        let index = self.lines.partition_point(|(start, _)| *start <= offset);
+        match index {
+            0 => Pos::initial(),
+            _ => self.lines[index - 1].1,
+        }
+    }
+}
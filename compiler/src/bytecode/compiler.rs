@@ -0,0 +1,1001 @@
+use crate::analysis::captures::{Captured, Captures};
+use crate::bytecode::chunk::{Chunk, Constant, Function, OpCode, UpvalueKind};
+use crate::diagnostic::Diagnostic;
+use crate::parser::expr::*;
+use crate::parser::operators::compound_operator;
+use crate::parser::stmt::*;
+use crate::parser::types::{TypeName, TypeReference};
+use crate::resolver::{node_id, Location, NodeId, Resolution};
+use crate::scanner::pos::{Pos, PosRange};
+use crate::scanner::token::{Literal, Token, TokenType};
+use std::rc::Rc;
+
+/// Storage of a variable as seen from the function using it.
+#[derive(Debug, Clone, Copy)]
+enum Variable {
+    Local(u8),
+    Upvalue(u8),
+    Global(u16),
+}
+
+struct LoopState {
+    /// Number of open blocks when the loop started. Jumping out of the loop
+    /// discards the locals of the blocks opened since.
+    scope_depth: usize,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+struct FunctionState {
+    function: Function,
+    /// Local declarations of every open block, innermost last.
+    scopes: Vec<Vec<usize>>,
+    loops: Vec<LoopState>,
+    /// Whether the function is a constructor, which returns `this`.
+    constructor: bool,
+}
+
+/// Lowers a resolved program into bytecode. Locals live in the stack slots
+/// the resolver assigned them, closures use the upvalues found by the
+/// capture analysis.
+///
+/// The program itself becomes a function without parameters. Top level
+/// functions and classes are defined before any other statement runs, so
+/// they can be used before their declaration.
+pub struct Compiler<'a> {
+    resolution: &'a Resolution,
+    captures: &'a Captures,
+    functions: Vec<FunctionState>,
+    /// Jumps taken by the `?.` links of every optional chain being compiled,
+    /// patched to the end of their chain.
+    chains: Vec<Vec<usize>>,
+    /// Source position of the code being emitted.
+    pos: Pos,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new(resolution: &'a Resolution, captures: &'a Captures) -> Self {
+        Compiler {
+            resolution,
+            captures,
+            functions: Vec::new(),
+            chains: Vec::new(),
+            pos: Pos::initial(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn compile(mut self, program: &Statement) -> Result<Rc<Function>, Vec<Diagnostic>> {
+        self.begin_function("<script>", 0);
+        match program {
+            Statement::Program(stmt) => {
+                for declaration in stmt.execution_order() {
+                    self.statement(declaration);
+                }
+            }
+            _ => self.statement(program),
+        }
+        let function = self.end_function(0);
+
+        if self.diagnostics.is_empty() {
+            Ok(Rc::new(function))
+        } else {
+            Err(self.diagnostics)
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        self.diagnostics
+            .push(Diagnostic::error_at(message, PosRange(self.pos, self.pos)));
+    }
+
+    fn state(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.state().function.chunk
+    }
+
+    fn emit(&mut self, op: OpCode) {
+        let pos = self.pos;
+        self.chunk().write_op(op, pos);
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let pos = self.pos;
+        self.chunk().write(byte, pos);
+    }
+
+    fn emit_u16(&mut self, value: u16) {
+        let pos = self.pos;
+        self.chunk().write_u16(value, pos);
+    }
+
+    fn emit_with_byte(&mut self, op: OpCode, operand: u8) {
+        self.emit(op);
+        self.emit_byte(operand);
+    }
+
+    fn emit_with_u16(&mut self, op: OpCode, operand: u16) {
+        self.emit(op);
+        self.emit_u16(operand);
+    }
+
+    fn byte_operand(&mut self, value: usize, what: &str) -> u8 {
+        u8::try_from(value).unwrap_or_else(|_| {
+            self.error(format!("Too many {} in one function", what));
+            0
+        })
+    }
+
+    fn u16_operand(&mut self, value: usize, what: &str) -> u16 {
+        u16::try_from(value).unwrap_or_else(|_| {
+            self.error(format!("Too many {}", what));
+            0
+        })
+    }
+
+    fn constant(&mut self, constant: Constant) -> u16 {
+        let index = self.chunk().add_constant(constant);
+        self.u16_operand(index, "constants in one function")
+    }
+
+    fn name_constant(&mut self, name: &Token) -> u16 {
+        self.constant(Constant::String(name.lexme))
+    }
+
+    /// Emits a forward jump and returns the offset of its operand, to be
+    /// patched once the target is known.
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_with_u16(op, u16::MAX);
+        self.chunk().code.len() - 2
+    }
+
+    /// Makes the jump at `offset` land on the next instruction.
+    fn patch_jump(&mut self, offset: usize) {
+        let distance = self.chunk().code.len() - offset - 2;
+        let distance = self.u16_operand(distance, "instructions to jump over");
+        self.chunk().patch_u16(offset, distance);
+    }
+
+    fn emit_loop(&mut self, start: usize) {
+        self.emit(OpCode::Loop);
+        let distance = self.chunk().code.len() - start + 2;
+        let distance = self.u16_operand(distance, "instructions in the loop body");
+        self.emit_u16(distance);
+    }
+
+    fn begin_function(&mut self, name: &str, arity: usize) {
+        self.functions.push(FunctionState {
+            function: Function {
+                name: name.to_string(),
+                arity,
+                upvalue_count: 0,
+                chunk: Chunk::default(),
+            },
+            scopes: vec![Vec::new()],
+            loops: Vec::new(),
+            constructor: false,
+        });
+    }
+
+    fn end_function(&mut self, upvalue_count: usize) -> Function {
+        self.emit_return(None);
+        let mut function = self.functions.pop().unwrap().function;
+        function.upvalue_count = upvalue_count;
+        function
+    }
+
+    /// Returns `value`, or the implicit result of the function when there is
+    /// none: `this` in constructors and null elsewhere.
+    fn emit_return(&mut self, value: Option<&Expression>) {
+        match value {
+            Some(value) => self.expression(value),
+            None if self.state().constructor => self.emit_with_byte(OpCode::GetLocal, 0),
+            None => self.emit(OpCode::Null),
+        }
+        self.emit(OpCode::Return);
+    }
+
+    /// Compiles a nested function with `body` and emits the `Closure`
+    /// creating it.
+    fn closure(&mut self, node: NodeId, name: &str, arity: usize, body: impl FnOnce(&mut Self)) {
+        let pos = self.pos;
+        self.byte_operand(arity, "parameters");
+        self.begin_function(name, arity);
+        body(self);
+        let upvalues = self
+            .captures
+            .functions
+            .get(&node)
+            .map_or(&[][..], Vec::as_slice);
+        let function = self.end_function(upvalues.len());
+        self.pos = pos;
+
+        let index = self.constant(Constant::Function(Rc::new(function)));
+        self.emit_with_u16(OpCode::Closure, index);
+        for upvalue in upvalues {
+            let kind = match upvalue.captured {
+                _ if !upvalue.is_local => UpvalueKind::Enclosing,
+                Captured::Declaration(declaration)
+                    if self.captures.mutated.contains(&declaration) =>
+                {
+                    UpvalueKind::Local
+                }
+                // `this` can't be assigned
+                _ => UpvalueKind::Copy,
+            };
+            self.emit_byte(kind as u8);
+            let index = self.byte_operand(upvalue.index, "captured variables");
+            self.emit_byte(index);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.state().scopes.push(Vec::new());
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.state().scopes.pop().unwrap();
+        self.discard_locals(&scope);
+    }
+
+    /// Pops the locals of `declarations` off the stack, moving the ones
+    /// closures share with the function to the heap.
+    fn discard_locals(&mut self, declarations: &[usize]) {
+        for declaration in declarations.iter().rev() {
+            if self.captures.mutated.contains(declaration) {
+                self.emit(OpCode::CloseUpvalue);
+            } else {
+                self.emit(OpCode::Pop);
+            }
+        }
+    }
+
+    /// Finishes the declaration of `node` whose value is on top of the
+    /// stack.
+    fn define<T>(&mut self, node: &T, name: &Token) {
+        let declaration = match self.resolution.declared.get(&node_id(node)) {
+            Some(declaration) => *declaration,
+            None => return,
+        };
+        match self.resolution.declarations[declaration].location {
+            Location::Local { slot } => {
+                self.byte_operand(slot, "local variables");
+                self.state().scopes.last_mut().unwrap().push(declaration);
+            }
+            Location::Global => {
+                let name = self.name_constant(name);
+                self.emit_with_u16(OpCode::DefineGlobal, name);
+            }
+        }
+    }
+
+    /// Storage of the variable `node` refers to. Names without a binding are
+    /// looked up among the globals at runtime.
+    fn variable(&mut self, node: NodeId, name: &Token) -> Variable {
+        if let Some(index) = self.captures.uses.get(&node) {
+            return Variable::Upvalue(self.byte_operand(*index, "captured variables"));
+        }
+        let location = self
+            .resolution
+            .bindings
+            .get(&node)
+            .map(|binding| self.resolution.declarations[binding.declaration].location);
+        match location {
+            Some(Location::Local { slot }) => {
+                Variable::Local(self.byte_operand(slot, "local variables"))
+            }
+            _ => Variable::Global(self.name_constant(name)),
+        }
+    }
+
+    fn load(&mut self, variable: Variable) {
+        match variable {
+            Variable::Local(slot) => self.emit_with_byte(OpCode::GetLocal, slot),
+            Variable::Upvalue(index) => self.emit_with_byte(OpCode::GetUpvalue, index),
+            Variable::Global(name) => self.emit_with_u16(OpCode::GetGlobal, name),
+        }
+    }
+
+    fn store(&mut self, variable: Variable) {
+        match variable {
+            Variable::Local(slot) => self.emit_with_byte(OpCode::SetLocal, slot),
+            Variable::Upvalue(index) => self.emit_with_byte(OpCode::SetUpvalue, index),
+            Variable::Global(name) => self.emit_with_u16(OpCode::SetGlobal, name),
+        }
+    }
+
+    /// Pushes the method receiver for `this` or `super`.
+    fn receiver(&mut self, node: NodeId) {
+        match self.captures.uses.get(&node) {
+            Some(index) => {
+                let index = self.byte_operand(*index, "captured variables");
+                self.emit_with_byte(OpCode::GetUpvalue, index);
+            }
+            None => self.emit_with_byte(OpCode::GetLocal, 0),
+        }
+    }
+
+    fn statements(&mut self, statements: &[Rc<Statement>]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Block(stmt) => {
+                self.begin_scope();
+                self.statements(&stmt.declarations);
+                self.end_scope();
+            }
+            Statement::Break(stmt) => {
+                self.pos = stmt.keyword.start_pos;
+                self.jump_out_of_loop(true);
+            }
+            Statement::Class(stmt) => self.class(stmt),
+            Statement::Continue(stmt) => {
+                self.pos = stmt.keyword.start_pos;
+                self.jump_out_of_loop(false);
+            }
+            Statement::Expression(stmt) => {
+                self.expression(&stmt.expression);
+                self.emit(OpCode::Pop);
+            }
+            Statement::For(stmt) => {
+                self.begin_scope();
+                if let Some(initializer) = &stmt.initializer {
+                    self.statement(initializer);
+                }
+                self.pos = stmt.keyword.start_pos;
+                self.loop_body(stmt.condition.as_ref(), &stmt.body, stmt.update.as_ref());
+                self.end_scope();
+            }
+            Statement::Function(stmt) => {
+                self.pos = stmt.name.start_pos;
+                self.closure(
+                    node_id(stmt),
+                    &stmt.name.lexme,
+                    stmt.parameters.len(),
+                    |compiler| compiler.statements(&stmt.body),
+                );
+                self.define(stmt, &stmt.name);
+            }
+            Statement::If(stmt) => {
+                self.expression(&stmt.condition);
+                self.pos = stmt.keyword.start_pos;
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.statement(&stmt.then_branch);
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump);
+                self.emit(OpCode::Pop);
+                if let Some(else_branch) = &stmt.else_branch {
+                    self.statement(else_branch);
+                }
+                self.patch_jump(else_jump);
+            }
+            Statement::Program(stmt) => self.statements(&stmt.declarations),
+            Statement::Return(stmt) => {
+                self.pos = stmt.keyword.start_pos;
+                self.emit_return(stmt.value.as_ref());
+            }
+            Statement::Var(stmt) => {
+                match &stmt.value {
+                    Some(value) => self.expression(value),
+                    None => {
+                        self.pos = stmt.name.start_pos;
+                        self.emit(OpCode::Null);
+                    }
+                }
+                self.define(stmt, &stmt.name);
+            }
+            Statement::While(stmt) => {
+                self.pos = stmt.keyword.start_pos;
+                self.loop_body(Some(&stmt.condition), &stmt.body, None);
+            }
+        }
+    }
+
+    /// Compiles a loop running `body` then `update` while `condition` holds.
+    /// `continue` jumps to the update, `break` past the loop.
+    fn loop_body(
+        &mut self,
+        condition: Option<&Expression>,
+        body: &Statement,
+        update: Option<&Expression>,
+    ) {
+        let start = self.chunk().code.len();
+        let exit_jump = condition.map(|condition| {
+            self.expression(condition);
+            let jump = self.emit_jump(OpCode::JumpIfFalse);
+            self.emit(OpCode::Pop);
+            jump
+        });
+
+        let scope_depth = self.state().scopes.len();
+        self.state().loops.push(LoopState {
+            scope_depth,
+            breaks: Vec::new(),
+            continues: Vec::new(),
+        });
+        self.statement(body);
+        let state = self.state().loops.pop().unwrap();
+
+        for jump in state.continues {
+            self.patch_jump(jump);
+        }
+        if let Some(update) = update {
+            self.expression(update);
+            self.emit(OpCode::Pop);
+        }
+        self.emit_loop(start);
+
+        if let Some(exit_jump) = exit_jump {
+            self.patch_jump(exit_jump);
+            self.emit(OpCode::Pop);
+        }
+        for jump in state.breaks {
+            self.patch_jump(jump);
+        }
+    }
+
+    fn jump_out_of_loop(&mut self, is_break: bool) {
+        let state = self.state();
+        let scope_depth = match state.loops.last() {
+            Some(current) => current.scope_depth,
+            None => return,
+        };
+        let locals: Vec<usize> = state.scopes[scope_depth..]
+            .iter()
+            .flatten()
+            .copied()
+            .collect();
+        self.discard_locals(&locals);
+
+        let jump = self.emit_jump(OpCode::Jump);
+        let current = self.state().loops.last_mut().unwrap();
+        if is_break {
+            current.breaks.push(jump);
+        } else {
+            current.continues.push(jump);
+        }
+    }
+
+    fn class(&mut self, stmt: &ClassStatement) {
+        self.pos = stmt.name.start_pos;
+        let name = self.name_constant(&stmt.name);
+        self.emit_with_u16(OpCode::Class, name);
+
+        if let Some(extends) = &stmt.extends {
+            if let Some(superclass) = superclass_name(extends) {
+                let variable = self.variable(node_id(extends), superclass);
+                self.load(variable);
+                self.emit(OpCode::Inherit);
+            }
+        }
+
+        for member in &stmt.members {
+            match member {
+                ClassMember::Field { name, value, .. } => {
+                    self.pos = name.start_pos;
+                    match value {
+                        Some(value) => {
+                            let function_name = format!("{}.{}", stmt.name.lexme, name.lexme);
+                            self.closure(node_id(member), &function_name, 0, |compiler| {
+                                compiler.expression(value);
+                                compiler.emit(OpCode::Return);
+                            });
+                        }
+                        None => self.emit(OpCode::Null),
+                    }
+                    let name = self.name_constant(name);
+                    self.emit_with_u16(OpCode::Field, name);
+                }
+                ClassMember::Method {
+                    name,
+                    parameters,
+                    body,
+                    ..
+                } => {
+                    self.pos = name.start_pos;
+                    let function_name = format!("{}.{}", stmt.name.lexme, name.lexme);
+                    self.closure(
+                        node_id(member),
+                        &function_name,
+                        parameters.len(),
+                        |compiler| compiler.statements(body),
+                    );
+                    let name = self.name_constant(name);
+                    self.emit_with_u16(OpCode::Method, name);
+                }
+                ClassMember::Constructor {
+                    name,
+                    parameters,
+                    body,
+                } => {
+                    self.pos = name.start_pos;
+                    self.closure(
+                        node_id(member),
+                        &stmt.name.lexme,
+                        parameters.len(),
+                        |compiler| {
+                            compiler.state().constructor = true;
+                            compiler.statements(body);
+                        },
+                    );
+                    self.emit(OpCode::Constructor);
+                }
+            }
+        }
+
+        self.define(stmt, &stmt.name);
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        self.pos = expression.start_pos();
+        match expression {
+            Expression::Array(expr) => self.elements(&expr.elements),
+            Expression::Assignment(expr) => self.assignment(expr),
+            Expression::Binary(expr) => {
+                self.expression(&expr.left);
+                self.expression(&expr.right);
+                self.pos = expr.operator.start_pos;
+                if let Some(op) = binary_op(expr.operator.token_type) {
+                    self.emit(op);
+                }
+            }
+            Expression::Call(expr) => self.call(expr),
+            Expression::Conditional(expr) => {
+                self.expression(&expr.condition);
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.expression(&expr.then_branch);
+                let end_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(else_jump);
+                self.emit(OpCode::Pop);
+                self.expression(&expr.else_branch);
+                self.patch_jump(end_jump);
+            }
+            Expression::Grouping(expr) => self.expression(&expr.expression),
+            Expression::Identifier(expr) => {
+                let variable = self.variable(node_id(expr), &expr.name);
+                self.load(variable);
+            }
+            Expression::Index(expr) => {
+                self.expression(&expr.object);
+                self.optional_link(expr.optional);
+                self.expression(&expr.index);
+                self.pos = expr.paren.start_pos;
+                self.emit(OpCode::GetIndex);
+            }
+            Expression::LambdaFunction(expr) => {
+                self.closure(
+                    node_id(expr),
+                    "<lambda>",
+                    expr.parameters.len(),
+                    |compiler| compiler.statements(&expr.body),
+                );
+            }
+            Expression::Literal(expr) => match &expr.value {
+                Literal::Null => self.emit(OpCode::Null),
+                Literal::Boolean(true) => self.emit(OpCode::True),
+                Literal::Boolean(false) => self.emit(OpCode::False),
+                literal => {
+                    let constant = Constant::from_literal(literal).unwrap();
+                    let index = self.constant(constant);
+                    self.emit_with_u16(OpCode::Constant, index);
+                }
+            },
+            Expression::Logical(expr) => self.logical(expr),
+            Expression::Map(expr) => self.map(expr),
+            Expression::Member(expr) => {
+                match expr.object.as_ref() {
+                    Expression::Super(object) => self.receiver(node_id(object)),
+                    object => {
+                        self.expression(object);
+                        self.optional_link(expr.optional);
+                    }
+                }
+                self.pos = expr.name.start_pos;
+                let name = self.name_constant(&expr.name);
+                if matches!(expr.object.as_ref(), Expression::Super(_)) {
+                    self.emit_with_u16(OpCode::GetSuper, name);
+                } else {
+                    self.emit_with_u16(OpCode::GetProperty, name);
+                }
+            }
+            Expression::OptionalChain(expr) => {
+                self.chains.push(Vec::new());
+                self.expression(&expr.expression);
+                for jump in self.chains.pop().unwrap() {
+                    self.patch_jump(jump);
+                }
+            }
+            // Spreads only appear among elements, which handle them
+            Expression::Spread(expr) => self.expression(&expr.expression),
+            Expression::Super(expr) => self.receiver(node_id(expr)),
+            Expression::This(expr) => self.receiver(node_id(expr)),
+            Expression::Tuple(expr) => {
+                if let Some(spread) = expr.elements.iter().find(|e| is_spread(e)) {
+                    self.pos = spread.start_pos();
+                    self.error(String::from("Can't spread into a tuple"));
+                }
+                for element in &expr.elements {
+                    self.expression(element);
+                }
+                let length = self.u16_operand(expr.elements.len(), "tuple elements");
+                self.emit_with_u16(OpCode::Tuple, length);
+            }
+            Expression::Unary(expr) => {
+                self.expression(&expr.right);
+                self.pos = expr.operator.start_pos;
+                match expr.operator.token_type {
+                    TokenType::Minus => self.emit(OpCode::Negate),
+                    TokenType::Bang => self.emit(OpCode::Not),
+                    TokenType::Tilde => self.emit(OpCode::BitNot),
+                    _ => {}
+                }
+            }
+            Expression::Update(expr) => self.update(expr),
+        }
+    }
+
+    /// Makes the enclosing optional chain evaluate to null when the value on
+    /// top of the stack is null.
+    fn optional_link(&mut self, optional: bool) {
+        if optional {
+            let jump = self.emit_jump(OpCode::JumpIfNull);
+            // The parser wraps every chain holding a `?.` link
+            self.chains.last_mut().unwrap().push(jump);
+        }
+    }
+
+    /// Pushes an array holding `elements`, expanding spreads.
+    fn elements(&mut self, elements: &[Rc<Expression>]) {
+        if !elements.iter().any(|element| is_spread(element)) {
+            for element in elements {
+                self.expression(element);
+            }
+            let length = self.u16_operand(elements.len(), "array elements");
+            self.emit_with_u16(OpCode::Array, length);
+            return;
+        }
+
+        self.emit_with_u16(OpCode::Array, 0);
+        for element in elements {
+            match element.as_ref() {
+                Expression::Spread(spread) => {
+                    self.expression(&spread.expression);
+                    self.pos = spread.operator.start_pos;
+                    self.emit(OpCode::ArrayExtend);
+                }
+                _ => {
+                    self.expression(element);
+                    self.emit(OpCode::ArrayPush);
+                }
+            }
+        }
+    }
+
+    fn map(&mut self, expr: &MapExpression) {
+        let has_spread = expr
+            .entries
+            .iter()
+            .any(|entry| matches!(entry, MapEntry::Spread { .. }));
+        if !has_spread {
+            for entry in &expr.entries {
+                if let MapEntry::Pair { key, value } = entry {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            self.pos = expr.brace.start_pos;
+            let length = self.u16_operand(expr.entries.len(), "map entries");
+            self.emit_with_u16(OpCode::Map, length);
+            return;
+        }
+
+        self.emit_with_u16(OpCode::Map, 0);
+        for entry in &expr.entries {
+            match entry {
+                MapEntry::Pair { key, value } => {
+                    self.expression(key);
+                    self.expression(value);
+                    self.emit(OpCode::MapInsert);
+                }
+                MapEntry::Spread { expression } => {
+                    self.expression(expression);
+                    self.emit(OpCode::MapExtend);
+                }
+            }
+        }
+    }
+
+    fn logical(&mut self, expr: &LogicalExpression) {
+        self.expression(&expr.left);
+        self.pos = expr.operator.start_pos;
+        match expr.operator.token_type {
+            TokenType::And => {
+                let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit(OpCode::Pop);
+                self.expression(&expr.right);
+                self.patch_jump(end_jump);
+            }
+            TokenType::Or => {
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                let end_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(else_jump);
+                self.emit(OpCode::Pop);
+                self.expression(&expr.right);
+                self.patch_jump(end_jump);
+            }
+            _ => {
+                let end_jump = self.emit_jump(OpCode::JumpIfNotNull);
+                self.emit(OpCode::Pop);
+                self.expression(&expr.right);
+                self.patch_jump(end_jump);
+            }
+        }
+    }
+
+    fn call(&mut self, expr: &CallExpression) {
+        let spread = expr.arguments.iter().any(|argument| is_spread(argument));
+
+        if let Expression::Super(callee) = expr.callee.as_ref() {
+            self.receiver(node_id(callee));
+            for argument in &expr.arguments {
+                self.expression(argument);
+            }
+            self.pos = expr.paren.start_pos;
+            if spread {
+                self.error(String::from(
+                    "Can't spread the arguments of a superclass constructor call",
+                ));
+            }
+            let count = self.byte_operand(expr.arguments.len(), "arguments");
+            self.emit_with_byte(OpCode::SuperCall, count);
+            return;
+        }
+
+        if let (Expression::Member(member), false, false) =
+            (expr.callee.as_ref(), spread, expr.optional)
+        {
+            let op = match member.object.as_ref() {
+                Expression::Super(object) => {
+                    self.receiver(node_id(object));
+                    OpCode::SuperInvoke
+                }
+                object => {
+                    self.expression(object);
+                    self.optional_link(member.optional);
+                    OpCode::Invoke
+                }
+            };
+            for argument in &expr.arguments {
+                self.expression(argument);
+            }
+            self.pos = expr.paren.start_pos;
+            let name = self.name_constant(&member.name);
+            let count = self.byte_operand(expr.arguments.len(), "arguments");
+            self.emit_with_u16(op, name);
+            self.emit_byte(count);
+            return;
+        }
+
+        self.expression(&expr.callee);
+        self.optional_link(expr.optional);
+        if spread {
+            self.elements(&expr.arguments);
+            self.pos = expr.paren.start_pos;
+            self.emit(OpCode::CallSpread);
+        } else {
+            for argument in &expr.arguments {
+                self.expression(argument);
+            }
+            self.pos = expr.paren.start_pos;
+            let count = self.byte_operand(expr.arguments.len(), "arguments");
+            self.emit_with_byte(OpCode::Call, count);
+        }
+    }
+
+    /// Pushes the object of a member target, `this` for `super.x`.
+    fn member_object(&mut self, expr: &MemberExpression) {
+        match expr.object.as_ref() {
+            Expression::Super(object) => self.receiver(node_id(object)),
+            object => self.expression(object),
+        }
+    }
+
+    fn assignment(&mut self, expr: &AssignmentExpression) {
+        let operator = compound_operator(expr.operator.token_type);
+        let coalesce = operator == Some(TokenType::QuestionQuestion);
+        let op = operator.and_then(binary_op);
+
+        match expr.left.as_ref() {
+            Expression::Identifier(target) => {
+                let variable = self.variable(node_id(target), &target.name);
+                if coalesce {
+                    self.load(variable);
+                    let end_jump = self.emit_jump(OpCode::JumpIfNotNull);
+                    self.emit(OpCode::Pop);
+                    self.expression(&expr.right);
+                    self.pos = expr.operator.start_pos;
+                    self.store(variable);
+                    self.patch_jump(end_jump);
+                    return;
+                }
+                if let Some(op) = op {
+                    self.load(variable);
+                    self.expression(&expr.right);
+                    self.pos = expr.operator.start_pos;
+                    self.emit(op);
+                } else {
+                    self.expression(&expr.right);
+                    self.pos = expr.operator.start_pos;
+                }
+                self.store(variable);
+            }
+            Expression::Member(target) => {
+                self.member_object(target);
+                let name = self.name_constant(&target.name);
+                if coalesce {
+                    // [object] -> [object, current] -> either [value] or [current]
+                    self.emit(OpCode::Dup);
+                    self.emit_with_u16(OpCode::GetProperty, name);
+                    let present_jump = self.emit_jump(OpCode::JumpIfNotNull);
+                    self.emit(OpCode::Pop);
+                    self.expression(&expr.right);
+                    self.pos = expr.operator.start_pos;
+                    self.emit_with_u16(OpCode::SetProperty, name);
+                    let end_jump = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(present_jump);
+                    self.emit(OpCode::Swap);
+                    self.emit(OpCode::Pop);
+                    self.patch_jump(end_jump);
+                    return;
+                }
+                if let Some(op) = op {
+                    self.emit(OpCode::Dup);
+                    self.emit_with_u16(OpCode::GetProperty, name);
+                    self.expression(&expr.right);
+                    self.pos = expr.operator.start_pos;
+                    self.emit(op);
+                } else {
+                    self.expression(&expr.right);
+                    self.pos = expr.operator.start_pos;
+                }
+                self.emit_with_u16(OpCode::SetProperty, name);
+            }
+            Expression::Index(target) => {
+                self.expression(&target.object);
+                self.expression(&target.index);
+                if coalesce {
+                    // [object, index] -> [object, index, current]
+                    self.emit(OpCode::Dup2);
+                    self.emit(OpCode::GetIndex);
+                    let present_jump = self.emit_jump(OpCode::JumpIfNotNull);
+                    self.emit(OpCode::Pop);
+                    self.expression(&expr.right);
+                    self.pos = expr.operator.start_pos;
+                    self.emit(OpCode::SetIndex);
+                    let end_jump = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(present_jump);
+                    self.emit(OpCode::Swap);
+                    self.emit(OpCode::Pop);
+                    self.emit(OpCode::Swap);
+                    self.emit(OpCode::Pop);
+                    self.patch_jump(end_jump);
+                    return;
+                }
+                if let Some(op) = op {
+                    self.emit(OpCode::Dup2);
+                    self.emit(OpCode::GetIndex);
+                    self.expression(&expr.right);
+                    self.pos = expr.operator.start_pos;
+                    self.emit(op);
+                } else {
+                    self.expression(&expr.right);
+                    self.pos = expr.operator.start_pos;
+                }
+                self.emit(OpCode::SetIndex);
+            }
+            // The parser rejects any other target
+            _ => {}
+        }
+    }
+
+    /// `++`/`--` on a variable, member or index. Postfix updates of members
+    /// and indexes recover the old value by applying the inverse operation.
+    fn update(&mut self, expr: &UpdateExpression) {
+        let (op, inverse) = if expr.operator.token_type == TokenType::PlusPlus {
+            (OpCode::Add, OpCode::Subtract)
+        } else {
+            (OpCode::Subtract, OpCode::Add)
+        };
+        let one = self.constant(Constant::Int(1));
+
+        match expr.expression.as_ref() {
+            Expression::Identifier(target) => {
+                let variable = self.variable(node_id(target), &target.name);
+                self.load(variable);
+                self.pos = expr.operator.start_pos;
+                if !expr.prefix {
+                    self.emit(OpCode::Dup);
+                }
+                self.emit_with_u16(OpCode::Constant, one);
+                self.emit(op);
+                self.store(variable);
+                if !expr.prefix {
+                    self.emit(OpCode::Pop);
+                }
+                return;
+            }
+            Expression::Member(target) => {
+                self.member_object(target);
+                let name = self.name_constant(&target.name);
+                self.pos = expr.operator.start_pos;
+                self.emit(OpCode::Dup);
+                self.emit_with_u16(OpCode::GetProperty, name);
+                self.emit_with_u16(OpCode::Constant, one);
+                self.emit(op);
+                self.emit_with_u16(OpCode::SetProperty, name);
+            }
+            Expression::Index(target) => {
+                self.expression(&target.object);
+                self.expression(&target.index);
+                self.pos = expr.operator.start_pos;
+                self.emit(OpCode::Dup2);
+                self.emit(OpCode::GetIndex);
+                self.emit_with_u16(OpCode::Constant, one);
+                self.emit(op);
+                self.emit(OpCode::SetIndex);
+            }
+            // The parser rejects any other target
+            _ => return,
+        }
+
+        if !expr.prefix {
+            self.emit_with_u16(OpCode::Constant, one);
+            self.emit(inverse);
+        }
+    }
+}
+
+fn is_spread(expression: &Expression) -> bool {
+    matches!(expression, Expression::Spread(_))
+}
+
+fn superclass_name(extends: &TypeReference) -> Option<&Token> {
+    match &extends.type_name {
+        TypeName::Identifier { name } => Some(name),
+        _ => None,
+    }
+}
+
+fn binary_op(token_type: TokenType) -> Option<OpCode> {
+    let op = match token_type {
+        TokenType::EqualEqual => OpCode::Equal,
+        TokenType::BangEqual => OpCode::NotEqual,
+        TokenType::Less => OpCode::Less,
+        TokenType::LessEqual => OpCode::LessEqual,
+        TokenType::Greater => OpCode::Greater,
+        TokenType::GreaterEqual => OpCode::GreaterEqual,
+        TokenType::Plus => OpCode::Add,
+        TokenType::Minus => OpCode::Subtract,
+        TokenType::Star => OpCode::Multiply,
+        TokenType::Slash => OpCode::Divide,
+        TokenType::Percent => OpCode::Modulo,
+        TokenType::StarStar => OpCode::Power,
+        TokenType::Ampersand => OpCode::BitAnd,
+        TokenType::Pipe => OpCode::BitOr,
+        TokenType::Caret => OpCode::BitXor,
+        TokenType::LessLess => OpCode::ShiftLeft,
+        TokenType::GreaterGreater => OpCode::ShiftRight,
+        _ => return None,
+    };
+    Some(op)
+}
@@ -0,0 +1,185 @@
+use crate::bytecode::chunk::{Chunk, Constant, Function, OpCode, UpvalueKind};
+use crate::bytecode::peb::Program;
+use std::fmt::Write;
+use std::rc::Rc;
+
+/// Human readable listing of `script` and every function nested in it.
+/// Each function's instructions are shown with their offset, mnemonic and
+/// decoded operands, preceded by the source line they were compiled from
+/// whenever it changes, or just its number when `source` doesn't have it.
+pub fn disassemble(script: &Rc<Function>, source: &str) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut text = String::new();
+    let mut pending = vec![script.clone()];
+    while let Some(function) = pending.pop() {
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        Disassembler {
+            chunk: &function.chunk,
+            lines: &lines,
+            text: &mut text,
+        }
+        .function(&function);
+        // Nested functions follow in the order they're declared
+        let nested = function.chunk.constants.iter().rev();
+        pending.extend(nested.filter_map(|constant| match constant {
+            Constant::Function(function) => Some(function.clone()),
+            _ => None,
+        }));
+    }
+    text
+}
+
+/// Class table of a bytecode file, each member with the function
+/// implementing it.
+pub fn list_classes(program: &Program) -> String {
+    let mut text = String::new();
+    let function = |index: usize| format!("<fun {}>", program.functions[index].name);
+    for class in &program.classes {
+        let _ = writeln!(text, "== class {} ==", class.name);
+        for (name, initializer) in &class.fields {
+            match initializer {
+                Some(initializer) => {
+                    let _ = writeln!(text, "field        {} = {}", name, function(*initializer));
+                }
+                None => {
+                    let _ = writeln!(text, "field        {}", name);
+                }
+            }
+        }
+        if let Some(constructor) = class.constructor {
+            let _ = writeln!(text, "constructor  {}", function(constructor));
+        }
+        for (name, method) in &class.methods {
+            let _ = writeln!(text, "method       {} = {}", name, function(*method));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+struct Disassembler<'a> {
+    chunk: &'a Chunk,
+    lines: &'a [&'a str],
+    text: &'a mut String,
+}
+
+impl Disassembler<'_> {
+    fn function(&mut self, function: &Function) {
+        let _ = writeln!(
+            self.text,
+            "== {} (arity {}, {} upvalue(s)) ==",
+            function.name, function.arity, function.upvalue_count
+        );
+        let mut row = 0;
+        let mut offset = 0;
+        while offset < self.chunk.code.len() {
+            let pos = self.chunk.pos_at(offset);
+            if pos.row != row {
+                row = pos.row;
+                match self.lines.get(row.wrapping_sub(1)) {
+                    Some(line) => {
+                        let _ = writeln!(self.text, "{:>4} | {}", row, line.trim_end());
+                    }
+                    // Bytecode files only keep the line table, not the source
+                    None if row > 0 => {
+                        let _ = writeln!(self.text, "line {}", row);
+                    }
+                    None => {}
+                }
+            }
+            offset = self.instruction(offset);
+        }
+    }
+
+    /// Writes the instruction at `offset` and returns the offset of the
+    /// next one.
+    fn instruction(&mut self, offset: usize) -> usize {
+        let code = &self.chunk.code;
+        let byte = code[offset];
+        let op = match OpCode::from_byte(byte) {
+            Some(op) => op,
+            None => {
+                let _ = writeln!(self.text, "{:04}  <unknown opcode {}>", offset, byte);
+                return offset + 1;
+            }
+        };
+        let next = offset + 1 + op.operand_size();
+        if next > code.len() {
+            let _ = writeln!(self.text, "{:04}  {:?} <truncated>", offset, op);
+            return code.len();
+        }
+
+        let u16_at = |at: usize| self.chunk.read_u16(at);
+        let operands = match op {
+            OpCode::GetLocal
+            | OpCode::SetLocal
+            | OpCode::GetUpvalue
+            | OpCode::SetUpvalue
+            | OpCode::Call
+            | OpCode::SuperCall => code[offset + 1].to_string(),
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::JumpIfNull | OpCode::JumpIfNotNull => {
+                let jump = u16_at(offset + 1) as usize;
+                format!("{} -> {:04}", jump, next + jump)
+            }
+            OpCode::Loop => {
+                let jump = u16_at(offset + 1) as usize;
+                format!("{} -> {:04}", jump, next.wrapping_sub(jump))
+            }
+            OpCode::Array | OpCode::Map | OpCode::Tuple => u16_at(offset + 1).to_string(),
+            OpCode::Invoke | OpCode::SuperInvoke => {
+                let name = u16_at(offset + 1);
+                format!("{} ({} argument(s))", self.constant(name), code[offset + 3])
+            }
+            _ if op.operand_size() == 2 => self.constant(u16_at(offset + 1)),
+            _ => String::new(),
+        };
+        let line = format!("{:04}  {:<14}{}", offset, format!("{:?}", op), operands);
+        let _ = writeln!(self.text, "{}", line.trim_end());
+
+        if op == OpCode::Closure {
+            return self.upvalues(offset, next);
+        }
+        next
+    }
+
+    /// Writes the upvalue descriptions following the `Closure` instruction
+    /// at `offset`.
+    fn upvalues(&mut self, offset: usize, mut next: usize) -> usize {
+        let index = self.chunk.read_u16(offset + 1) as usize;
+        let count = match self.chunk.constants.get(index) {
+            Some(Constant::Function(function)) => function.upvalue_count,
+            _ => 0,
+        };
+        for _ in 0..count {
+            let (Some(&kind), Some(&index)) =
+                (self.chunk.code.get(next), self.chunk.code.get(next + 1))
+            else {
+                let _ = writeln!(self.text, "{:04}    <truncated>", next);
+                return self.chunk.code.len();
+            };
+            let kind = match UpvalueKind::from_byte(kind) {
+                Some(UpvalueKind::Enclosing) => "upvalue",
+                Some(UpvalueKind::Local) => "local",
+                Some(UpvalueKind::Copy) => "copy",
+                None => "<unknown>",
+            };
+            let _ = writeln!(self.text, "{:04}    | {} {}", next, kind, index);
+            next += 2;
+        }
+        next
+    }
+
+    /// Index and value of a constant of the pool.
+    fn constant(&self, index: u16) -> String {
+        let value = match self.chunk.constants.get(index as usize) {
+            Some(Constant::Int(value)) => value.to_string(),
+            Some(Constant::Float(value)) => format!("{:?}", value),
+            Some(Constant::String(value)) => format!("{:?}", value.as_str()),
+            Some(Constant::Function(function)) => format!("<fun {}>", function.name),
+            None => String::from("<out of range>"),
+        };
+        format!("{} = {}", index, value)
+    }
+}
@@ -0,0 +1,5 @@
+pub mod chunk;
+pub mod compiler;
+pub mod disasm;
+pub mod peb;
+pub mod verifier;
@@ -0,0 +1,744 @@
+use crate::bytecode::chunk::{Chunk, Constant, Function, OpCode};
+use crate::scanner::pos::Pos;
+use crate::symbol::Symbol;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+const MAGIC: &[u8; 4] = b"PEB\0";
+/// Bumped whenever the layout or the instruction set changes.
+pub const VERSION: u16 = 1;
+/// Index standing for a missing function.
+const NONE: u32 = u32::MAX;
+
+const INT: u8 = 0;
+const FLOAT: u8 = 1;
+const STRING: u8 = 2;
+const POOL_CONSTANT: u8 = 0;
+const FUNCTION_CONSTANT: u8 = 1;
+
+/// Class declared by a program, recorded so tools can show the layout of
+/// classes without running the code building them.
+#[derive(Debug)]
+pub struct ClassLayout {
+    pub name: Symbol,
+    /// Field names with the index of their initializer in the function
+    /// table.
+    pub fields: Vec<(Symbol, Option<usize>)>,
+    pub methods: Vec<(Symbol, usize)>,
+    pub constructor: Option<usize>,
+}
+
+/// Contents of a bytecode file.
+#[derive(Debug)]
+pub struct Program {
+    /// Every function of the program, the script first.
+    pub functions: Vec<Rc<Function>>,
+    pub classes: Vec<ClassLayout>,
+}
+
+impl Program {
+    pub fn script(&self) -> Rc<Function> {
+        self.functions[0].clone()
+    }
+}
+
+/// Entry of the shared constant pool.
+#[derive(Clone, Copy, Eq, PartialEq, Hash)]
+enum PoolEntry {
+    Int(i64),
+    /// Bits of the float, so equal floats share an entry.
+    Float(u64),
+    String(Symbol),
+}
+
+/// Serializes a compiled program into a bytecode file, which runs without
+/// the source. All integers are little endian. A file is laid out as:
+///
+/// ```text
+/// magic      "PEB\0"
+/// version    u16
+/// pool       u32 count, then per entry a tag u8 and its value:
+///            0 int i64, 1 float f64, 2 string (u32 length, UTF-8 bytes)
+/// functions  u32 count, the script first and nested functions after the
+///            function declaring them. Per function:
+///            name u32 (pool), arity u32, upvalues u32,
+///            code (u32 length, bytes),
+///            constants u32 count, each 0 and a pool index or 1 and a
+///            function index,
+///            lines u32 count, each offset u32, row u32, column u32,
+///            index u32
+/// classes    u32 count, per class: name u32 (pool),
+///            fields u32 count, each name u32 (pool) and initializer u32
+///            (function, NONE when null),
+///            methods u32 count, each name u32 (pool) and function u32,
+///            constructor u32 (function or NONE)
+/// checksum   u32, CRC-32 of everything before it
+/// ```
+pub fn write(script: &Rc<Function>) -> Vec<u8> {
+    let mut writer = Writer::default();
+    writer.collect(script);
+    writer.finish()
+}
+
+#[derive(Default)]
+struct Writer {
+    pool: Vec<PoolEntry>,
+    pool_indices: HashMap<PoolEntry, u32>,
+    functions: Vec<Rc<Function>>,
+    function_indices: HashMap<*const Function, u32>,
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    /// Numbers `function` and the functions nested in it, parents first.
+    fn collect(&mut self, function: &Rc<Function>) {
+        let index = self.functions.len() as u32;
+        self.function_indices.insert(Rc::as_ptr(function), index);
+        self.functions.push(function.clone());
+        for constant in &function.chunk.constants {
+            if let Constant::Function(nested) = constant {
+                self.collect(nested);
+            }
+        }
+    }
+
+    fn pool_index(&mut self, entry: PoolEntry) -> u32 {
+        if let Some(index) = self.pool_indices.get(&entry) {
+            return *index;
+        }
+        let index = self.pool.len() as u32;
+        self.pool.push(entry);
+        self.pool_indices.insert(entry, index);
+        index
+    }
+
+    fn string(&mut self, string: &str) -> u32 {
+        self.pool_index(PoolEntry::String(Symbol::intern(string)))
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn length(&mut self, length: usize) {
+        self.u32(length as u32);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        // Functions and classes are encoded first so the pool holds every
+        // constant they refer to by the time it's written
+        let functions: Vec<Rc<Function>> = self.functions.clone();
+        self.length(functions.len());
+        for function in &functions {
+            self.function(function);
+        }
+        let classes = self.classes(&functions);
+        self.length(classes.len());
+        for class in classes {
+            self.u32(class.name);
+            self.length(class.fields.len());
+            for (name, initializer) in class.fields {
+                self.u32(name);
+                self.u32(initializer);
+            }
+            self.length(class.methods.len());
+            for (name, method) in class.methods {
+                self.u32(name);
+                self.u32(method);
+            }
+            self.u32(class.constructor);
+        }
+        let body = std::mem::take(&mut self.bytes);
+
+        self.bytes.extend_from_slice(MAGIC);
+        self.bytes.extend_from_slice(&VERSION.to_le_bytes());
+        self.length(self.pool.len());
+        for entry in self.pool.clone() {
+            match entry {
+                PoolEntry::Int(value) => {
+                    self.bytes.push(INT);
+                    self.bytes.extend_from_slice(&value.to_le_bytes());
+                }
+                PoolEntry::Float(bits) => {
+                    self.bytes.push(FLOAT);
+                    self.bytes.extend_from_slice(&bits.to_le_bytes());
+                }
+                PoolEntry::String(string) => {
+                    self.bytes.push(STRING);
+                    self.length(string.len());
+                    self.bytes.extend_from_slice(string.as_bytes());
+                }
+            }
+        }
+        self.bytes.extend_from_slice(&body);
+        let checksum = crc32(&self.bytes);
+        self.u32(checksum);
+        self.bytes
+    }
+
+    fn function(&mut self, function: &Function) {
+        let name = self.string(&function.name);
+        self.u32(name);
+        self.length(function.arity);
+        self.length(function.upvalue_count);
+        let chunk = &function.chunk;
+        self.length(chunk.code.len());
+        self.bytes.extend_from_slice(&chunk.code);
+
+        self.length(chunk.constants.len());
+        for constant in &chunk.constants {
+            let (tag, index) = match constant {
+                Constant::Int(value) => (POOL_CONSTANT, self.pool_index(PoolEntry::Int(*value))),
+                Constant::Float(value) => (
+                    POOL_CONSTANT,
+                    self.pool_index(PoolEntry::Float(value.to_bits())),
+                ),
+                Constant::String(string) => {
+                    (POOL_CONSTANT, self.pool_index(PoolEntry::String(*string)))
+                }
+                Constant::Function(nested) => (
+                    FUNCTION_CONSTANT,
+                    self.function_indices[&Rc::as_ptr(nested)],
+                ),
+            };
+            self.bytes.push(tag);
+            self.u32(index);
+        }
+
+        self.length(chunk.lines.len());
+        for (offset, pos) in &chunk.lines {
+            for value in [*offset, pos.row, pos.col, pos.index] {
+                self.length(value);
+            }
+        }
+    }
+
+    /// Finds the classes by the instructions declaring them: a `Class`
+    /// followed by members, each one the closure of its function (or null
+    /// for fields without initializer) and the instruction adding it.
+    fn classes(&mut self, functions: &[Rc<Function>]) -> Vec<EncodedClass> {
+        let mut classes = Vec::new();
+        for function in functions {
+            let chunk = &function.chunk;
+            let mut current: Option<EncodedClass> = None;
+            let mut closure = NONE;
+            for (offset, op) in instructions(chunk) {
+                let name = || match &chunk.constants[chunk.read_u16(offset + 1) as usize] {
+                    Constant::String(name) => *name,
+                    constant => panic!("expected a name constant, got {:?}", constant),
+                };
+                match op {
+                    OpCode::Class => {
+                        classes.extend(current.take());
+                        let name = self.pool_index(PoolEntry::String(name()));
+                        current = Some(EncodedClass::new(name));
+                    }
+                    OpCode::Closure => {
+                        closure = match &chunk.constants[chunk.read_u16(offset + 1) as usize] {
+                            Constant::Function(nested) => {
+                                self.function_indices[&Rc::as_ptr(nested)]
+                            }
+                            _ => NONE,
+                        };
+                        continue;
+                    }
+                    OpCode::Field | OpCode::Method => {
+                        let name = self.pool_index(PoolEntry::String(name()));
+                        if let Some(class) = &mut current {
+                            if op == OpCode::Field {
+                                class.fields.push((name, closure));
+                            } else {
+                                class.methods.push((name, closure));
+                            }
+                        }
+                    }
+                    OpCode::Constructor => {
+                        if let Some(class) = &mut current {
+                            class.constructor = closure;
+                        }
+                    }
+                    _ => {}
+                }
+                closure = NONE;
+            }
+            classes.extend(current);
+        }
+        classes
+    }
+}
+
+struct EncodedClass {
+    name: u32,
+    fields: Vec<(u32, u32)>,
+    methods: Vec<(u32, u32)>,
+    constructor: u32,
+}
+
+impl EncodedClass {
+    fn new(name: u32) -> Self {
+        EncodedClass {
+            name,
+            fields: Vec::new(),
+            methods: Vec::new(),
+            constructor: NONE,
+        }
+    }
+}
+
+/// Offsets and opcodes of the instructions of a chunk the compiler
+/// produced.
+fn instructions(chunk: &Chunk) -> Vec<(usize, OpCode)> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < chunk.code.len() {
+        let op = OpCode::from_byte(chunk.code[offset]).expect("compiled code is valid");
+        instructions.push((offset, op));
+        offset += 1 + op.operand_size();
+        if op == OpCode::Closure {
+            if let Constant::Function(function) =
+                &chunk.constants[chunk.read_u16(offset - 2) as usize]
+            {
+                offset += 2 * function.upvalue_count;
+            }
+        }
+    }
+    instructions
+}
+
+/// Deserializes a bytecode file. Files of another version, corrupted ones
+/// and ones whose tables refer to missing entries are rejected.
+pub fn read(bytes: &[u8]) -> Result<Program, String> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(String::from("not a bytecode file"));
+    }
+    let mut reader = Reader {
+        bytes,
+        offset: MAGIC.len(),
+    };
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(format!(
+            "unsupported bytecode version {} (expected {})",
+            version, VERSION
+        ));
+    }
+    if bytes.len() < reader.offset + 4 {
+        return Err(String::from("truncated file"));
+    }
+    let (content, checksum) = bytes.split_at(bytes.len() - 4);
+    if crc32(content) != u32::from_le_bytes(checksum.try_into().unwrap()) {
+        return Err(String::from("checksum mismatch, the file is corrupted"));
+    }
+    reader.bytes = content;
+
+    let mut pool = Vec::new();
+    for _ in 0..reader.u32()? {
+        let entry = match reader.u8()? {
+            INT => PoolEntry::Int(i64::from_le_bytes(reader.array()?)),
+            FLOAT => PoolEntry::Float(u64::from_le_bytes(reader.array()?)),
+            STRING => {
+                let length = reader.u32()? as usize;
+                let string = std::str::from_utf8(reader.take(length)?)
+                    .map_err(|_| String::from("invalid UTF-8 in a string constant"))?;
+                PoolEntry::String(Symbol::intern(string))
+            }
+            tag => return Err(format!("unknown constant tag {}", tag)),
+        };
+        pool.push(entry);
+    }
+    let string = |index: u32| match pool.get(index as usize) {
+        Some(PoolEntry::String(string)) => Ok(*string),
+        _ => Err(format!("constant {} is not a string", index)),
+    };
+
+    let function_count = reader.u32()? as usize;
+    if function_count == 0 {
+        return Err(String::from("no script function"));
+    }
+    let mut encoded = Vec::new();
+    for index in 0..function_count {
+        encoded.push(reader.function(index, function_count, &pool, &string)?);
+    }
+
+    // Nested functions come after the function declaring them, build them
+    // first
+    let mut functions: Vec<Option<Rc<Function>>> = vec![None; function_count];
+    for (index, function) in encoded.into_iter().enumerate().rev() {
+        let constants = function
+            .constants
+            .into_iter()
+            .map(|constant| match constant {
+                EncodedConstant::Pool(constant) => constant,
+                EncodedConstant::Function(nested) => Constant::Function(
+                    functions[nested]
+                        .clone()
+                        .expect("nested functions are built first"),
+                ),
+            })
+            .collect();
+        functions[index] = Some(Rc::new(Function {
+            name: function.name,
+            arity: function.arity,
+            upvalue_count: function.upvalue_count,
+            chunk: Chunk {
+                code: function.code,
+                constants,
+                lines: function.lines,
+            },
+        }));
+    }
+    let functions: Vec<Rc<Function>> = functions.into_iter().flatten().collect();
+
+    let function_index = |index: u32| -> Result<Option<usize>, String> {
+        match index {
+            NONE => Ok(None),
+            _ if (index as usize) < function_count => Ok(Some(index as usize)),
+            _ => Err(format!("function {} is out of range", index)),
+        }
+    };
+    let mut classes = Vec::new();
+    for _ in 0..reader.u32()? {
+        let name = string(reader.u32()?)?;
+        let mut fields = Vec::new();
+        for _ in 0..reader.u32()? {
+            let field = string(reader.u32()?)?;
+            fields.push((field, function_index(reader.u32()?)?));
+        }
+        let mut methods = Vec::new();
+        for _ in 0..reader.u32()? {
+            let method = string(reader.u32()?)?;
+            let function = function_index(reader.u32()?)?.ok_or_else(|| {
+                format!("method '{}' of class '{}' has no function", method, name)
+            })?;
+            methods.push((method, function));
+        }
+        let constructor = function_index(reader.u32()?)?;
+        classes.push(ClassLayout {
+            name,
+            fields,
+            methods,
+            constructor,
+        });
+    }
+
+    if reader.offset != reader.bytes.len() {
+        return Err(String::from("unexpected data after the class table"));
+    }
+    Ok(Program { functions, classes })
+}
+
+enum EncodedConstant {
+    Pool(Constant),
+    /// Index in the function table.
+    Function(usize),
+}
+
+struct EncodedFunction {
+    name: String,
+    arity: usize,
+    upvalue_count: usize,
+    code: Vec<u8>,
+    constants: Vec<EncodedConstant>,
+    lines: Vec<(usize, Pos)>,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, length: usize) -> Result<&[u8], String> {
+        let end = self
+            .offset
+            .checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| String::from("truncated file"))?;
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    /// Reads function `index` of the `count` in the function table.
+    fn function(
+        &mut self,
+        index: usize,
+        count: usize,
+        pool: &[PoolEntry],
+        string: &impl Fn(u32) -> Result<Symbol, String>,
+    ) -> Result<EncodedFunction, String> {
+        let name = string(self.u32()?)?.to_string();
+        let arity = self.u32()? as usize;
+        let upvalue_count = self.u32()? as usize;
+        // Arguments are counted and upvalues indexed by a byte operand
+        if arity > u8::MAX as usize {
+            return Err(format!(
+                "function '{}' takes {} parameters, at most {} are allowed",
+                name,
+                arity,
+                u8::MAX
+            ));
+        }
+        if upvalue_count > u8::MAX as usize + 1 {
+            return Err(format!(
+                "function '{}' captures {} variables, at most {} are allowed",
+                name,
+                upvalue_count,
+                u8::MAX as usize + 1
+            ));
+        }
+        let length = self.u32()? as usize;
+        let code = self.take(length)?.to_vec();
+
+        let mut constants = Vec::new();
+        for _ in 0..self.u32()? {
+            let tag = self.u8()?;
+            let value = self.u32()? as usize;
+            let constant = match (tag, pool.get(value)) {
+                (POOL_CONSTANT, Some(PoolEntry::Int(value))) => {
+                    EncodedConstant::Pool(Constant::Int(*value))
+                }
+                (POOL_CONSTANT, Some(PoolEntry::Float(bits))) => {
+                    EncodedConstant::Pool(Constant::Float(f64::from_bits(*bits)))
+                }
+                (POOL_CONSTANT, Some(PoolEntry::String(string))) => {
+                    EncodedConstant::Pool(Constant::String(*string))
+                }
+                (POOL_CONSTANT, None) => {
+                    return Err(format!("constant {} is out of range", value));
+                }
+                // Referring only to later functions rules out cycles
+                (FUNCTION_CONSTANT, _) if value > index && value < count => {
+                    EncodedConstant::Function(value)
+                }
+                (FUNCTION_CONSTANT, _) => {
+                    return Err(format!(
+                        "function {} can't refer to function {}",
+                        index, value
+                    ));
+                }
+                _ => return Err(format!("unknown constant kind {}", tag)),
+            };
+            constants.push(constant);
+        }
+
+        let mut lines: Vec<(usize, Pos)> = Vec::new();
+        for _ in 0..self.u32()? {
+            let offset = self.u32()? as usize;
+            let pos = Pos {
+                row: self.u32()? as usize,
+                col: self.u32()? as usize,
+                index: self.u32()? as usize,
+            };
+            if offset >= code.len() || lines.last().is_some_and(|(last, _)| *last >= offset) {
+                return Err(format!("invalid line table of function '{}'", name));
+            }
+            lines.push((offset, pos));
+        }
+
+        Ok(EncodedFunction {
+            name,
+            arity,
+            upvalue_count,
+            code,
+            constants,
+            lines,
+        })
+    }
+}
+
+/// CRC-32 (IEEE) of `bytes`.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile_program, parse_source};
+    use std::fs;
+
+    fn compile(name: &str) -> Rc<Function> {
+        let path = format!("{}/programs/{}", env!("CARGO_MANIFEST_DIR"), name);
+        let source = fs::read_to_string(path).unwrap();
+        let program = parse_source(&source).unwrap();
+        compile_program(&program, &source, false).unwrap()
+    }
+
+    /// Appends the checksum a valid file ends with.
+    fn seal(mut bytes: Vec<u8>) -> Vec<u8> {
+        bytes.extend_from_slice(&crc32(&bytes).to_le_bytes());
+        bytes
+    }
+
+    /// Builds a file by hand whose pool holds only the string "f".
+    struct Builder(Vec<u8>);
+
+    impl Builder {
+        fn new() -> Self {
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend_from_slice(&VERSION.to_le_bytes());
+            let mut builder = Builder(bytes);
+            builder.u32(1).u8(STRING).u32(1).u8(b'f');
+            builder
+        }
+
+        fn u8(&mut self, value: u8) -> &mut Self {
+            self.0.push(value);
+            self
+        }
+
+        fn u32(&mut self, value: u32) -> &mut Self {
+            self.0.extend_from_slice(&value.to_le_bytes());
+            self
+        }
+
+        /// Function "f" whose code is a single `Return`.
+        fn function(&mut self, constants: &[(u8, u32)]) -> &mut Self {
+            self.u32(0).u32(0).u32(0).u32(1).u8(OpCode::Return as u8);
+            self.u32(constants.len() as u32);
+            for (tag, index) in constants {
+                self.u8(*tag).u32(*index);
+            }
+            self.u32(0)
+        }
+
+        fn finish(&self) -> Vec<u8> {
+            seal(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for name in ["closures.txt", "classes.txt", "inheritance.txt"] {
+            let bytes = write(&compile(name));
+            let program = read(&bytes).unwrap();
+            assert_eq!(write(&program.script()), bytes, "{}", name);
+        }
+    }
+
+    #[test]
+    fn reads_hand_built_file() {
+        let bytes = Builder::new().u32(1).function(&[]).u32(0).finish();
+        let program = read(&bytes).unwrap();
+        assert_eq!(program.functions.len(), 1);
+        assert_eq!(program.script().name, "f");
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut bytes = write(&compile("basics.txt"));
+        bytes[0] = b'X';
+        assert_eq!(read(&bytes).unwrap_err(), "not a bytecode file");
+        assert_eq!(read(b"PE").unwrap_err(), "not a bytecode file");
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = write(&compile("basics.txt"));
+        bytes[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            read(&bytes).unwrap_err(),
+            format!(
+                "unsupported bytecode version {} (expected {})",
+                VERSION + 1,
+                VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_checksum_mismatch() {
+        let mut bytes = write(&compile("basics.txt"));
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0x40;
+        assert_eq!(
+            read(&bytes).unwrap_err(),
+            "checksum mismatch, the file is corrupted"
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = write(&compile("closures.txt"));
+        assert_eq!(read(&bytes[..7]).unwrap_err(), "truncated file");
+        let content = &bytes[..bytes.len() - 4];
+        for length in 6..content.len() {
+            // Resealed, so the missing data itself has to be noticed
+            let truncated = seal(content[..length].to_vec());
+            assert!(read(&truncated).is_err(), "accepted {} bytes", length);
+        }
+    }
+
+    #[test]
+    fn rejects_constants_out_of_range() {
+        let bytes = Builder::new()
+            .u32(1)
+            .function(&[(POOL_CONSTANT, 5)])
+            .u32(0)
+            .finish();
+        assert_eq!(read(&bytes).unwrap_err(), "constant 5 is out of range");
+    }
+
+    #[test]
+    fn rejects_functions_out_of_range() {
+        let bytes = Builder::new()
+            .u32(1)
+            .function(&[(FUNCTION_CONSTANT, 3)])
+            .u32(0)
+            .finish();
+        assert_eq!(
+            read(&bytes).unwrap_err(),
+            "function 0 can't refer to function 3"
+        );
+
+        // A function referring to itself would make a cycle
+        let bytes = Builder::new()
+            .u32(1)
+            .function(&[(FUNCTION_CONSTANT, 0)])
+            .u32(0)
+            .finish();
+        assert_eq!(
+            read(&bytes).unwrap_err(),
+            "function 0 can't refer to function 0"
+        );
+
+        // Class "f" without fields, its method "f" implemented by function 7
+        let bytes = Builder::new()
+            .u32(1)
+            .function(&[])
+            .u32(1)
+            .u32(0)
+            .u32(0)
+            .u32(1)
+            .u32(0)
+            .u32(7)
+            .u32(NONE)
+            .finish();
+        assert_eq!(read(&bytes).unwrap_err(), "function 7 is out of range");
+    }
+}
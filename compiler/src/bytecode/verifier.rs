@@ -0,0 +1,654 @@
+use crate::bytecode::chunk::{Constant, Function, OpCode, UpvalueKind};
+use crate::bytecode::peb::Program;
+use std::collections::BTreeSet;
+
+/// Checks every function of a loaded program before the VM runs it, so
+/// a malformed file is rejected instead of crashing the VM. The stack of
+/// each function is followed along every path: its depth has to agree
+/// wherever paths meet, operands have to point at existing constants,
+/// slots and upvalues, and instructions relying on the kind of a value,
+/// like `Method` on a class, have to be sure to get one.
+pub fn verify(program: &Program) -> Result<(), String> {
+    let script = &program.functions[0];
+    if script.arity != 0 || script.upvalue_count != 0 {
+        return Err(String::from(
+            "the script can't take parameters or capture variables",
+        ));
+    }
+    for function in &program.functions {
+        Verifier::new(function)
+            .function()
+            .map_err(|e| format!("function '{}', {}", function.name, e))?;
+    }
+    Ok(())
+}
+
+/// What is known about a value on the stack. Only the kinds some
+/// instruction relies on are told apart.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Kind {
+    Unknown,
+    Null,
+    Closure,
+    Class,
+    Array,
+    Map,
+}
+
+/// Stack of the frame before an instruction, from the callee slot up.
+#[derive(Clone)]
+struct State {
+    stack: Vec<Kind>,
+    /// Slots captured by closures, which may assign them behind the
+    /// frame's back until the upvalues are closed.
+    captured: BTreeSet<usize>,
+}
+
+impl State {
+    fn need(&self, count: usize) -> Result<(), String> {
+        match self.stack.len() < count {
+            true => Err(format!(
+                "needs {} value(s) on the stack but there are {}",
+                count,
+                self.stack.len()
+            )),
+            false => Ok(()),
+        }
+    }
+
+    fn pop(&mut self, count: usize) -> Result<(), String> {
+        self.need(count)?;
+        self.stack.truncate(self.stack.len() - count);
+        Ok(())
+    }
+
+    fn push(&mut self, kind: Kind) {
+        self.stack.push(kind);
+    }
+
+    /// Kind of the value `distance` slots below the top.
+    fn peek(&self, distance: usize) -> Result<Kind, String> {
+        self.need(distance + 1)?;
+        Ok(self.stack[self.stack.len() - 1 - distance])
+    }
+
+    /// Checks the value `distance` slots below the top is one of `kinds`.
+    fn expect(&self, distance: usize, kinds: &[Kind], what: &str) -> Result<(), String> {
+        match kinds.contains(&self.peek(distance)?) {
+            true => Ok(()),
+            false => Err(format!("expects {} on the stack", what)),
+        }
+    }
+
+    /// Forgets the kind of captured slots, closures may have changed them.
+    fn forget_captured(&mut self) {
+        for slot in &self.captured {
+            if let Some(kind) = self.stack.get_mut(*slot) {
+                *kind = Kind::Unknown;
+            }
+        }
+    }
+
+    /// Merges the state of another path reaching the same instruction.
+    /// Returns whether anything changed.
+    fn merge(&mut self, other: &State) -> Result<bool, String> {
+        if self.stack.len() != other.stack.len() {
+            return Err(format!(
+                "the stack holds {} value(s) on one path and {} on another",
+                self.stack.len(),
+                other.stack.len()
+            ));
+        }
+        let mut changed = false;
+        for (kind, other) in self.stack.iter_mut().zip(&other.stack) {
+            if kind != other && *kind != Kind::Unknown {
+                *kind = Kind::Unknown;
+                changed = true;
+            }
+        }
+        for slot in &other.captured {
+            changed |= self.captured.insert(*slot);
+        }
+        self.forget_captured();
+        Ok(changed)
+    }
+}
+
+struct Verifier<'a> {
+    function: &'a Function,
+    code: &'a [u8],
+    /// Whether an instruction starts at each offset.
+    starts: Vec<bool>,
+    /// State before each instruction reached so far.
+    states: Vec<Option<State>>,
+    /// Instructions whose state changed since they were last checked.
+    pending: Vec<usize>,
+}
+
+impl<'a> Verifier<'a> {
+    fn new(function: &'a Function) -> Self {
+        let code = &function.chunk.code[..];
+        Verifier {
+            function,
+            code,
+            starts: vec![false; code.len()],
+            states: vec![None; code.len()],
+            pending: Vec::new(),
+        }
+    }
+
+    fn function(mut self) -> Result<(), String> {
+        let mut offset = 0;
+        while offset < self.code.len() {
+            self.starts[offset] = true;
+            offset += self.size(offset).map_err(|e| self.at(offset, e))?;
+        }
+
+        let entry = State {
+            stack: vec![Kind::Unknown; self.function.arity + 1],
+            captured: BTreeSet::new(),
+        };
+        self.flow(0, entry)
+            .map_err(|e| format!("at entry: {}", e))?;
+        while let Some(offset) = self.pending.pop() {
+            let state = self.states[offset].clone().unwrap();
+            self.instruction(offset, state)
+                .map_err(|e| self.at(offset, e))?;
+        }
+        Ok(())
+    }
+
+    /// Error message locating `message` at the instruction at `offset`.
+    fn at(&self, offset: usize, message: String) -> String {
+        match OpCode::from_byte(self.code[offset]) {
+            Some(op) => format!("{:04} {:?}: {}", offset, op, message),
+            None => format!("{:04}: {}", offset, message),
+        }
+    }
+
+    /// Size in bytes of the instruction at `offset`, operands included.
+    fn size(&self, offset: usize) -> Result<usize, String> {
+        let byte = self.code[offset];
+        let op = OpCode::from_byte(byte).ok_or_else(|| format!("unknown opcode {}", byte))?;
+        let mut size = 1 + op.operand_size();
+        if op == OpCode::Closure && offset + size <= self.code.len() {
+            size += 2 * self
+                .closure_function(self.u16_at(offset + 1))?
+                .upvalue_count;
+        }
+        if offset + size > self.code.len() {
+            return Err(String::from("the instruction is truncated"));
+        }
+        Ok(size)
+    }
+
+    fn u16_at(&self, offset: usize) -> usize {
+        self.function.chunk.read_u16(offset) as usize
+    }
+
+    fn constant(&self, index: usize) -> Result<&'a Constant, String> {
+        let constants = &self.function.chunk.constants;
+        constants.get(index).ok_or_else(|| {
+            format!(
+                "constant {} is out of range, the pool has {}",
+                index,
+                constants.len()
+            )
+        })
+    }
+
+    fn name(&self, index: usize) -> Result<(), String> {
+        match self.constant(index)? {
+            Constant::String(_) => Ok(()),
+            _ => Err(format!("constant {} is not a name", index)),
+        }
+    }
+
+    fn closure_function(&self, index: usize) -> Result<&'a Function, String> {
+        match self.constant(index)? {
+            Constant::Function(function) => Ok(function),
+            _ => Err(format!("constant {} is not a function", index)),
+        }
+    }
+
+    /// Continues along a path to the instruction at `target`.
+    fn flow(&mut self, target: usize, state: State) -> Result<(), String> {
+        if target >= self.code.len() {
+            return Err(String::from("execution runs past the end of the code"));
+        }
+        if !self.starts[target] {
+            return Err(format!(
+                "jumps into the middle of an instruction at {:04}",
+                target
+            ));
+        }
+        match &mut self.states[target] {
+            Some(existing) => {
+                if existing
+                    .merge(&state)
+                    .map_err(|e| format!("{} at {:04}", e, target))?
+                {
+                    self.pending.push(target);
+                }
+            }
+            None => {
+                self.states[target] = Some(state);
+                self.pending.push(target);
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the instruction at `offset` to `state` and passes the result
+    /// on to the instructions that may follow.
+    fn instruction(&mut self, offset: usize, mut state: State) -> Result<(), String> {
+        let op = OpCode::from_byte(self.code[offset]).unwrap();
+        let next = offset + self.size(offset)?;
+        let byte = self.code.get(offset + 1).copied().unwrap_or(0) as usize;
+        let operand = match op.operand_size() {
+            0 | 1 => byte,
+            _ => self.u16_at(offset + 1),
+        };
+
+        let mut targets = vec![next];
+        match op {
+            OpCode::Constant => match self.constant(operand)? {
+                Constant::Function(function) if function.upvalue_count > 0 => {
+                    return Err(format!(
+                        "function '{}' captures variables and needs a closure",
+                        function.name
+                    ))
+                }
+                Constant::Function(_) => state.push(Kind::Closure),
+                _ => state.push(Kind::Unknown),
+            },
+            OpCode::Null => state.push(Kind::Null),
+            OpCode::True | OpCode::False => state.push(Kind::Unknown),
+            OpCode::Pop => state.pop(1)?,
+            OpCode::Dup => state.push(state.peek(0)?),
+            OpCode::Dup2 => {
+                let (below, top) = (state.peek(1)?, state.peek(0)?);
+                state.push(below);
+                state.push(top);
+            }
+            OpCode::Swap => {
+                state.need(2)?;
+                let length = state.stack.len();
+                state.stack.swap(length - 1, length - 2);
+            }
+            OpCode::GetLocal | OpCode::SetLocal => {
+                if operand >= state.stack.len() {
+                    return Err(format!(
+                        "local slot {} is out of range, the frame has {}",
+                        operand,
+                        state.stack.len()
+                    ));
+                }
+                match op {
+                    OpCode::GetLocal => state.push(state.stack[operand]),
+                    _ => state.stack[operand] = state.peek(0)?,
+                }
+            }
+            OpCode::GetGlobal => {
+                self.name(operand)?;
+                state.push(Kind::Unknown);
+            }
+            OpCode::SetGlobal => {
+                self.name(operand)?;
+                state.need(1)?;
+            }
+            OpCode::DefineGlobal => {
+                self.name(operand)?;
+                state.pop(1)?;
+            }
+            OpCode::GetUpvalue | OpCode::SetUpvalue => {
+                if operand >= self.function.upvalue_count {
+                    return Err(format!(
+                        "upvalue {} is out of range, the function has {}",
+                        operand, self.function.upvalue_count
+                    ));
+                }
+                match op {
+                    OpCode::GetUpvalue => state.push(Kind::Unknown),
+                    _ => state.need(1)?,
+                }
+            }
+            OpCode::CloseUpvalue => {
+                state.pop(1)?;
+                let top = state.stack.len();
+                state.captured.retain(|slot| *slot < top);
+            }
+            OpCode::GetProperty | OpCode::GetSuper => {
+                self.name(operand)?;
+                state.pop(1)?;
+                state.push(Kind::Unknown);
+            }
+            OpCode::SetProperty => {
+                self.name(operand)?;
+                state.pop(2)?;
+                state.push(Kind::Unknown);
+            }
+            OpCode::GetIndex => {
+                state.pop(2)?;
+                state.push(Kind::Unknown);
+            }
+            OpCode::SetIndex => {
+                state.pop(3)?;
+                state.push(Kind::Unknown);
+            }
+            OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Modulo
+            | OpCode::Power
+            | OpCode::BitAnd
+            | OpCode::BitOr
+            | OpCode::BitXor
+            | OpCode::ShiftLeft
+            | OpCode::ShiftRight => {
+                state.pop(2)?;
+                state.push(Kind::Unknown);
+            }
+            OpCode::Negate | OpCode::Not | OpCode::BitNot => {
+                state.pop(1)?;
+                state.push(Kind::Unknown);
+            }
+            OpCode::Jump => targets = vec![next + operand],
+            OpCode::JumpIfFalse | OpCode::JumpIfNull | OpCode::JumpIfNotNull => {
+                state.need(1)?;
+                targets.push(next + operand);
+            }
+            OpCode::Loop => match next.checked_sub(operand) {
+                Some(target) => targets = vec![target],
+                None => return Err(String::from("loops back past the start of the code")),
+            },
+            OpCode::Call | OpCode::SuperCall => {
+                state.pop(operand + 1)?;
+                state.push(Kind::Unknown);
+            }
+            OpCode::CallSpread => {
+                state.pop(2)?;
+                state.push(Kind::Unknown);
+            }
+            OpCode::Invoke | OpCode::SuperInvoke => {
+                self.name(operand)?;
+                state.pop(self.code[offset + 3] as usize + 1)?;
+                state.push(Kind::Unknown);
+            }
+            OpCode::Closure => {
+                // The size of the instruction already accounts for every
+                // upvalue of the function
+                for (i, pair) in self.code[offset + 3..next].chunks(2).enumerate() {
+                    let (kind, index) = (UpvalueKind::from_byte(pair[0]), pair[1] as usize);
+                    let (limit, what) = match kind {
+                        // A local function captures the slot the closure is
+                        // about to be stored in to call itself
+                        Some(UpvalueKind::Local) => (state.stack.len() + 1, "local slot"),
+                        Some(UpvalueKind::Copy) => (state.stack.len(), "local slot"),
+                        Some(UpvalueKind::Enclosing) => (self.function.upvalue_count, "upvalue"),
+                        None => {
+                            return Err(format!("upvalue {} has an invalid kind {}", i, pair[0]))
+                        }
+                    };
+                    if index >= limit {
+                        return Err(format!(
+                            "captures {} {} which is out of range of {}",
+                            what, index, limit
+                        ));
+                    }
+                    // Copies can't be assigned from the closure
+                    if kind == Some(UpvalueKind::Local) {
+                        state.captured.insert(index);
+                    }
+                }
+                state.push(Kind::Closure);
+            }
+            OpCode::Return => {
+                state.need(1)?;
+                targets.clear();
+            }
+            OpCode::Array | OpCode::Tuple => {
+                state.pop(operand)?;
+                state.push(match op {
+                    OpCode::Array => Kind::Array,
+                    _ => Kind::Unknown,
+                });
+            }
+            OpCode::ArrayPush | OpCode::ArrayExtend => {
+                state.expect(1, &[Kind::Array], "an array below the value")?;
+                state.pop(1)?;
+            }
+            OpCode::Map => {
+                state.pop(operand * 2)?;
+                state.push(Kind::Map);
+            }
+            OpCode::MapInsert => {
+                state.expect(2, &[Kind::Map], "a map below the entry")?;
+                state.pop(2)?;
+            }
+            OpCode::MapExtend => {
+                state.expect(1, &[Kind::Map], "a map below the value")?;
+                state.pop(1)?;
+            }
+            OpCode::Class => {
+                self.name(operand)?;
+                state.push(Kind::Class);
+            }
+            OpCode::Inherit => {
+                state.expect(1, &[Kind::Class], "a class below the superclass")?;
+                state.pop(1)?;
+            }
+            OpCode::Method | OpCode::Constructor | OpCode::Field => {
+                if op != OpCode::Constructor {
+                    self.name(operand)?;
+                }
+                match op {
+                    OpCode::Field => {
+                        state.expect(0, &[Kind::Closure, Kind::Null], "a closure or null")?
+                    }
+                    _ => state.expect(0, &[Kind::Closure], "a closure")?,
+                }
+                state.expect(1, &[Kind::Class], "a class below the member")?;
+                state.pop(1)?;
+            }
+        }
+
+        state.forget_captured();
+        for target in targets {
+            self.flow(target, state.clone())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::chunk::Chunk;
+    use crate::symbol::Symbol;
+    use std::rc::Rc;
+
+    fn function(code: &[u8], constants: Vec<Constant>, upvalue_count: usize) -> Function {
+        Function {
+            name: String::from("f"),
+            arity: 0,
+            upvalue_count,
+            chunk: Chunk {
+                code: code.to_vec(),
+                constants,
+                lines: Vec::new(),
+            },
+        }
+    }
+
+    /// Verifies a script made of `code` and `constants`, returning the
+    /// error it is rejected with.
+    fn verify_script(code: &[u8], constants: Vec<Constant>) -> Result<(), String> {
+        verify(&Program {
+            functions: vec![Rc::new(function(code, constants, 0))],
+            classes: Vec::new(),
+        })
+    }
+
+    fn assert_rejected(code: &[u8], constants: Vec<Constant>, message: &str) {
+        let error = verify_script(code, constants).unwrap_err();
+        assert!(error.contains(message), "{}", error);
+    }
+
+    #[test]
+    fn accepts_valid_code() {
+        verify_script(&[OpCode::Null as u8, OpCode::Return as u8], Vec::new()).unwrap();
+        // Both paths leave one value on the stack
+        let code = [
+            OpCode::True as u8,
+            OpCode::JumpIfFalse as u8,
+            0,
+            5,
+            OpCode::Pop as u8,
+            OpCode::Null as u8,
+            OpCode::Jump as u8,
+            0,
+            2,
+            OpCode::Pop as u8,
+            OpCode::True as u8,
+            OpCode::Return as u8,
+        ];
+        verify_script(&code, Vec::new()).unwrap();
+    }
+
+    #[test]
+    fn rejects_stack_underflow() {
+        assert_rejected(
+            &[
+                OpCode::Pop as u8,
+                OpCode::Pop as u8,
+                OpCode::Null as u8,
+                OpCode::Return as u8,
+            ],
+            Vec::new(),
+            "needs 1 value(s) on the stack but there are 0",
+        );
+        assert_rejected(
+            &[OpCode::Add as u8, OpCode::Return as u8],
+            Vec::new(),
+            "needs 2 value(s)",
+        );
+    }
+
+    #[test]
+    fn rejects_mismatched_stack_at_merge() {
+        // The jump skips the extra null pushed on the other path
+        let code = [
+            OpCode::True as u8,
+            OpCode::JumpIfFalse as u8,
+            0,
+            1,
+            OpCode::Null as u8,
+            OpCode::Return as u8,
+        ];
+        assert_rejected(&code, Vec::new(), "on one path and");
+    }
+
+    #[test]
+    fn rejects_jumps_into_instructions() {
+        let code = [
+            OpCode::Jump as u8,
+            0,
+            1,
+            OpCode::Constant as u8,
+            0,
+            0,
+            OpCode::Return as u8,
+        ];
+        assert_rejected(
+            &code,
+            vec![Constant::Int(1)],
+            "jumps into the middle of an instruction",
+        );
+    }
+
+    #[test]
+    fn rejects_jumps_past_the_end() {
+        assert_rejected(
+            &[
+                OpCode::Jump as u8,
+                0,
+                9,
+                OpCode::Null as u8,
+                OpCode::Return as u8,
+            ],
+            Vec::new(),
+            "execution runs past the end of the code",
+        );
+        assert_rejected(
+            &[OpCode::Null as u8],
+            Vec::new(),
+            "execution runs past the end of the code",
+        );
+    }
+
+    #[test]
+    fn rejects_operands_out_of_range() {
+        assert_rejected(
+            &[OpCode::GetLocal as u8, 5, OpCode::Return as u8],
+            Vec::new(),
+            "local slot 5 is out of range",
+        );
+        assert_rejected(
+            &[OpCode::GetUpvalue as u8, 0, OpCode::Return as u8],
+            Vec::new(),
+            "upvalue 0 is out of range",
+        );
+        assert_rejected(
+            &[OpCode::Constant as u8, 0, 3, OpCode::Return as u8],
+            vec![Constant::Int(1)],
+            "constant 3 is out of range, the pool has 1",
+        );
+    }
+
+    #[test]
+    fn rejects_method_on_non_class() {
+        let method = Rc::new(function(
+            &[OpCode::Null as u8, OpCode::Return as u8],
+            Vec::new(),
+            0,
+        ));
+        let code = [
+            OpCode::Null as u8,
+            OpCode::Constant as u8,
+            0,
+            0,
+            OpCode::Method as u8,
+            0,
+            1,
+            OpCode::Return as u8,
+        ];
+        let constants = vec![
+            Constant::Function(method),
+            Constant::String(Symbol::intern("m")),
+        ];
+        assert_rejected(&code, constants, "expects a class");
+    }
+
+    #[test]
+    fn rejects_invalid_upvalue_kind() {
+        let nested = Rc::new(function(
+            &[OpCode::GetUpvalue as u8, 0, OpCode::Return as u8],
+            Vec::new(),
+            1,
+        ));
+        let code = [OpCode::Closure as u8, 0, 0, 7, 0, OpCode::Return as u8];
+        assert_rejected(
+            &code,
+            vec![Constant::Function(nested)],
+            "upvalue 0 has an invalid kind 7",
+        );
+    }
+}
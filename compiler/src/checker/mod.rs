@@ -0,0 +1,1650 @@
+use crate::analysis::flow::can_complete;
+use crate::checker::narrowing::{assigned_names, closure_assigned_names, narrowing};
+use crate::checker::types::Type;
+use crate::diagnostic::Diagnostic;
+use crate::parser::expr::*;
+use crate::parser::operators::compound_operator;
+use crate::parser::stmt::*;
+use crate::parser::types::{TypeName, TypeParameter, TypeReference};
+use crate::resolver::members::{closest, unknown_member_message};
+use crate::scanner::pos::PosRange;
+use crate::scanner::token::{Literal, Token, TokenType};
+use crate::symbol::Symbol;
+use crate::vm::natives::{NativeFunction, NATIVES};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+mod narrowing;
+pub mod types;
+
+/// Signature of a class collected before any function body is checked.
+#[derive(Debug)]
+pub struct ClassInfo {
+    pub type_parameters: Vec<Symbol>,
+    /// Type each type parameter `extends`, in the same order.
+    pub bounds: Vec<Option<Rc<Type>>>,
+    pub superclass: Option<Type>,
+    pub fields: HashMap<Symbol, Type>,
+    pub methods: HashMap<Symbol, Type>,
+    pub constructor: Option<Type>,
+}
+
+/// Variable, function or class name visible to the code being checked.
+#[derive(Debug, Clone)]
+struct Variable {
+    ty: Type,
+    /// Non-null type proven by a null check, used instead of `ty` on reads.
+    narrowed: Option<Type>,
+    /// Whether every path reaching the current point assigns a value.
+    assigned: bool,
+    /// Number of functions enclosing the declaration.
+    function_depth: usize,
+    /// Whether null checks may narrow the variable. A variable assigned by
+    /// nested functions may change whenever one of them is called.
+    narrowable: bool,
+}
+
+type Scope = HashMap<Symbol, Variable>;
+
+/// Type checking pass. Assigns a type to expressions, infers the type of
+/// `var` declarations that don't have an annotation and reports operands,
+/// arguments, assignments and return values of the wrong type.
+///
+/// Variables are tracked flow-sensitively: reading one before it is assigned
+/// is an error, and null checks in conditions narrow nullable variables so
+/// their members can be accessed.
+///
+/// Top level classes and functions are visible to the whole program, nested
+/// declarations only become visible once they're declared.
+pub struct Checker {
+    classes: HashMap<Symbol, ClassInfo>,
+    scopes: Vec<Scope>,
+    /// Type parameters in scope with their bounds, innermost last.
+    type_parameters: Vec<(Symbol, Option<Rc<Type>>)>,
+    current_class: Option<Symbol>,
+    /// Declared return type of every function being checked, innermost last.
+    return_types: Vec<Type>,
+    /// Names assigned by the functions nested in every function being
+    /// checked, the program first.
+    closure_assigned: Vec<HashSet<Symbol>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        let natives = NATIVES
+            .iter()
+            .map(|native| {
+                let variable = Variable {
+                    ty: native_type(native),
+                    narrowed: None,
+                    assigned: true,
+                    function_depth: 0,
+                    narrowable: false,
+                };
+                (Symbol::intern(native.name), variable)
+            })
+            .collect();
+        Checker {
+            classes: HashMap::new(),
+            scopes: vec![natives],
+            type_parameters: Vec::new(),
+            current_class: None,
+            return_types: Vec::new(),
+            closure_assigned: Vec::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn check(mut self, program: &Statement) -> Vec<Diagnostic> {
+        let mut names = HashSet::new();
+        closure_assigned_names(program, &mut names);
+        self.closure_assigned.push(names);
+
+        if let Statement::Program(program) = program {
+            self.declare_globals(&program.declarations);
+            for declaration in &program.declarations {
+                self.statement(declaration);
+            }
+        } else {
+            self.statement(program);
+        }
+
+        self.diagnostics
+    }
+
+    fn declare_globals(&mut self, declarations: &[Rc<Statement>]) {
+        for declaration in declarations {
+            if let Statement::Class(class) = declaration.as_ref() {
+                self.declare_class(class);
+            }
+        }
+        // Bounds first, so type arguments of classes declared later are
+        // checked against them too
+        for declaration in declarations {
+            if let Statement::Class(class) = declaration.as_ref() {
+                self.declare_class_bounds(class);
+            }
+        }
+
+        for declaration in declarations {
+            match declaration.as_ref() {
+                Statement::Class(class) => self.declare_class_members(class),
+                Statement::Function(function) => {
+                    let function_type = self.function_type(
+                        &function.type_parameters,
+                        &function.parameters,
+                        &function.return_type,
+                    );
+                    self.declare(&function.name, function_type);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn declare_class(&mut self, class: &ClassStatement) {
+        self.classes.insert(
+            class.name.lexme,
+            ClassInfo {
+                type_parameters: type_parameter_names(&class.type_parameters),
+                bounds: Vec::new(),
+                superclass: None,
+                fields: HashMap::new(),
+                methods: HashMap::new(),
+                constructor: None,
+            },
+        );
+    }
+
+    fn declare_class_bounds(&mut self, class: &ClassStatement) {
+        let outer = self.enter_type_parameters(&class.type_parameters);
+        let bounds = self.type_parameters[outer..]
+            .iter()
+            .map(|(_, bound)| bound.clone())
+            .collect();
+        self.type_parameters.truncate(outer);
+
+        if let Some(info) = self.classes.get_mut(&class.name.lexme) {
+            info.bounds = bounds;
+        }
+    }
+
+    fn declare_class_members(&mut self, class: &ClassStatement) {
+        let outer = self.reenter_type_parameters(&class.type_parameters);
+
+        let superclass = class
+            .extends
+            .as_ref()
+            .map(|extends| self.resolve_type(extends));
+        let mut fields: HashMap<Symbol, Type> = HashMap::new();
+        let mut methods: HashMap<Symbol, Type> = HashMap::new();
+        let mut constructor = None;
+
+        for member in &class.members {
+            match member {
+                ClassMember::Field {
+                    name, value_type, ..
+                } => {
+                    fields.insert(name.lexme, self.resolve_type(value_type));
+                }
+                ClassMember::Method {
+                    name,
+                    type_parameters,
+                    parameters,
+                    return_type,
+                    ..
+                } => {
+                    let method_type = self.function_type(type_parameters, parameters, return_type);
+                    methods.insert(name.lexme, method_type);
+                }
+                ClassMember::Constructor { parameters, .. } => {
+                    constructor = Some(self.function_type(&[], parameters, &None));
+                }
+            }
+        }
+
+        self.type_parameters.truncate(outer);
+
+        if let Some(info) = self.classes.get_mut(&class.name.lexme) {
+            info.superclass = superclass;
+            info.fields = fields;
+            info.methods = methods;
+            info.constructor = constructor;
+        }
+    }
+
+    fn function_type(
+        &mut self,
+        type_parameters: &[TypeParameter],
+        parameters: &[FunctionParameter],
+        return_type: &Option<TypeReference>,
+    ) -> Type {
+        let outer = self.enter_type_parameters(type_parameters);
+        let parameters: Vec<Type> = parameters
+            .iter()
+            .map(|parameter| self.resolve_type(&parameter.value_type))
+            .collect();
+        let return_type = match return_type {
+            Some(return_type) => self.resolve_type(return_type),
+            None => Type::Void,
+        };
+        self.type_parameters.truncate(outer);
+
+        Type::Function(parameters, Rc::new(return_type))
+    }
+
+    /// Brings type parameters into scope. Their bounds are resolved once
+    /// every parameter is, so a bound can refer to the other parameters.
+    fn enter_type_parameters(&mut self, type_parameters: &[TypeParameter]) -> usize {
+        let outer = self.type_parameters.len();
+        self.type_parameters.extend(
+            type_parameter_names(type_parameters)
+                .into_iter()
+                .map(|name| (name, None)),
+        );
+        for (i, parameter) in type_parameters.iter().enumerate() {
+            if let Some(bound) = &parameter.bound {
+                let bound = self.resolve_type(bound);
+                self.type_parameters[outer + i].1 = Some(Rc::new(bound));
+            }
+        }
+        outer
+    }
+
+    /// Brings the type parameters of a declaration whose signature was
+    /// already resolved back into scope to check its body, without
+    /// reporting errors in their bounds again.
+    fn reenter_type_parameters(&mut self, type_parameters: &[TypeParameter]) -> usize {
+        let reported = self.diagnostics.len();
+        let outer = self.enter_type_parameters(type_parameters);
+        self.diagnostics.truncate(reported);
+        outer
+    }
+
+    /// Converts a type written in source into a checker type.
+    fn resolve_type(&mut self, reference: &TypeReference) -> Type {
+        let arguments: Vec<Type> = reference
+            .type_arguments
+            .iter()
+            .map(|argument| self.resolve_type(argument))
+            .collect();
+
+        match &reference.type_name {
+            TypeName::Identifier { name } => {
+                let parameter = self
+                    .type_parameters
+                    .iter()
+                    .rev()
+                    .find(|(parameter, _)| *parameter == name.lexme);
+                if let Some((_, bound)) = parameter {
+                    return Type::Parameter(name.lexme, bound.clone());
+                }
+                if let Some(ty) = Type::builtin(&name.lexme) {
+                    return ty;
+                }
+                if let Some(class) = self.classes.get(&name.lexme) {
+                    let expected = class.type_parameters.len();
+                    if arguments.len() != expected {
+                        self.error(
+                            format!(
+                                "Type '{}' expects {} type argument(s) but got {}",
+                                name.lexme,
+                                expected,
+                                arguments.len()
+                            ),
+                            name,
+                        );
+                        return Type::Class(name.lexme, vec![Type::Unknown; expected]);
+                    }
+                    self.check_class_bounds(name.lexme, &arguments, name.range());
+                    return Type::Class(name.lexme, arguments);
+                }
+                if name.lexme == "Map" && arguments.len() == 2 {
+                    return Type::Map(Rc::new(arguments[0].clone()), Rc::new(arguments[1].clone()));
+                }
+
+                self.error(format!("Unknown type '{}'", name.lexme), name);
+                Type::Unknown
+            }
+            TypeName::QualifiedName { right, .. } => {
+                self.error(
+                    format!("Cannot resolve qualified type name '{}'", right.lexme),
+                    right,
+                );
+                Type::Unknown
+            }
+            TypeName::Function {
+                parameters,
+                return_type,
+                ..
+            } => {
+                let parameters = parameters
+                    .iter()
+                    .map(|parameter| self.resolve_type(parameter))
+                    .collect();
+                let return_type = self.resolve_type(return_type);
+                Type::Function(parameters, Rc::new(return_type))
+            }
+            TypeName::Array { element } => Type::Array(Rc::new(self.resolve_type(element))),
+            TypeName::Nullable { inner } => self.resolve_type(inner).nullable(),
+            TypeName::Union { types } => {
+                let types = types.iter().map(|ty| self.resolve_type(ty)).collect();
+                Type::union(types)
+            }
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token, ty: Type) {
+        self.declare_variable(name, ty, true);
+    }
+
+    fn declare_variable(&mut self, name: &Token, ty: Type, assigned: bool) {
+        let variable = Variable {
+            ty,
+            narrowed: None,
+            assigned,
+            function_depth: self.return_types.len(),
+            narrowable: !self
+                .closure_assigned
+                .last()
+                .is_some_and(|names| names.contains(&name.lexme)),
+        };
+        self.scopes.last_mut().unwrap().insert(name.lexme, variable);
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<&Type> {
+        self.variable(name).map(|variable| &variable.ty)
+    }
+
+    fn variable(&self, name: Symbol) -> Option<&Variable> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(&name))
+    }
+
+    /// Variable declared in the function being checked. Variables of outer
+    /// functions may change whenever a closure runs, so they're not tracked.
+    fn local_variable_mut(&mut self, name: Symbol) -> Option<&mut Variable> {
+        let depth = self.return_types.len();
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(&name))
+            .filter(|variable| variable.function_depth == depth)
+    }
+
+    /// Records a value of type `value` being stored into `name`.
+    fn assign(&mut self, name: Symbol, value: &Type) {
+        if let Some(variable) = self.local_variable_mut(name) {
+            variable.assigned = true;
+            variable.narrowed = if value.is_nullable() || !variable.narrowable {
+                None
+            } else {
+                Some(variable.ty.non_null())
+            };
+        }
+    }
+
+    fn narrow(&mut self, names: &[Symbol]) {
+        for name in names {
+            if let Some(variable) = self.local_variable_mut(*name) {
+                if variable.narrowable {
+                    variable.narrowed = Some(variable.ty.non_null());
+                }
+            }
+        }
+    }
+
+    /// Drops narrowing of the variables, e.g. the ones a loop body assigns
+    /// since a null check before the loop doesn't hold on the next iteration.
+    fn forget_narrowing(&mut self, names: &HashSet<Symbol>) {
+        for name in names {
+            if let Some(variable) = self.local_variable_mut(*name) {
+                variable.narrowed = None;
+            }
+        }
+    }
+
+    /// Joins the variable states of two paths meeting again. A variable is
+    /// only assigned or narrowed if it is on both paths.
+    fn merge(&mut self, other: Vec<Scope>) {
+        for (scope, other) in self.scopes.iter_mut().zip(other) {
+            for (name, variable) in scope.iter_mut() {
+                if let Some(other) = other.get(name) {
+                    variable.assigned &= other.assigned;
+                    if variable.narrowed != other.narrowed {
+                        variable.narrowed = None;
+                    }
+                }
+            }
+        }
+    }
+
+    fn error(&mut self, message: String, token: &Token) {
+        self.diagnostics.push(Diagnostic::error(message, token));
+    }
+
+    fn error_at(&mut self, message: String, range: PosRange) {
+        self.diagnostics.push(Diagnostic::error_at(message, range));
+    }
+
+    /// Reports `expression` unless a value of type `actual` fits into
+    /// `expected`.
+    fn expect_assignable(&mut self, expected: &Type, actual: &Type, expression: &Expression) {
+        if !self.is_assignable(expected, actual) {
+            self.error_at(
+                format!("Type '{}' is not assignable to type '{}'", actual, expected),
+                expression.range(),
+            );
+        }
+    }
+
+    /// Checks the expression is usable as a condition of `if`, loops and
+    /// logical operators.
+    fn condition(&mut self, expression: &Expression) {
+        let ty = self.expression(expression);
+        if !matches!(ty, Type::Bool | Type::Unknown) {
+            self.error_at(
+                format!("Condition must be of type 'bool' but got '{}'", ty),
+                expression.range(),
+            );
+        }
+    }
+
+    /// Reports type arguments of `class` not satisfying the bound of their
+    /// type parameter.
+    fn check_class_bounds(&mut self, class: Symbol, arguments: &[Type], range: PosRange) {
+        let info = match self.classes.get(&class) {
+            Some(info) => info,
+            None => return,
+        };
+        let parameters: Vec<_> = info
+            .type_parameters
+            .iter()
+            .cloned()
+            .zip(info.bounds.iter().cloned())
+            .collect();
+        let bindings = info
+            .type_parameters
+            .iter()
+            .cloned()
+            .zip(arguments.iter().cloned())
+            .collect();
+        self.check_bounds(&parameters, &bindings, range);
+    }
+
+    /// Reports type parameters bound to a type that doesn't fit into what
+    /// they `extends`.
+    fn check_bounds(
+        &mut self,
+        parameters: &[(Symbol, Option<Rc<Type>>)],
+        bindings: &HashMap<Symbol, Type>,
+        range: PosRange,
+    ) {
+        for (name, bound) in parameters {
+            if let (Some(bound), Some(argument)) = (bound, bindings.get(name)) {
+                let bound = substitute(bound, bindings);
+                if !self.is_assignable(&bound, argument) {
+                    self.error_at(
+                        format!(
+                            "Type '{}' does not satisfy the bound '{}' of type parameter '{}'",
+                            argument, bound, name
+                        ),
+                        range,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Whether a value of type `source` can be stored where `target` is
+    /// expected.
+    fn is_assignable(&self, target: &Type, source: &Type) -> bool {
+        if target == source {
+            return true;
+        }
+        match (target, source) {
+            (Type::Unknown, _) | (_, Type::Unknown) => true,
+            (_, Type::Union(types)) => types.iter().all(|ty| self.is_assignable(target, ty)),
+            (Type::Nullable(_), Type::Null) => true,
+            (Type::Nullable(target), Type::Nullable(source)) => self.is_assignable(target, source),
+            (Type::Nullable(target), _) => self.is_assignable(target, source),
+            (_, Type::Nullable(source)) => {
+                self.is_assignable(target, &Type::Null) && self.is_assignable(target, source)
+            }
+            (Type::Union(types), _) => types.iter().any(|ty| self.is_assignable(ty, source)),
+            (Type::Float, Type::Int) => true,
+            (_, Type::Parameter(_, Some(bound))) => self.is_assignable(target, bound),
+            (Type::Class(..), Type::Class(..)) => self.is_subclass(source, target),
+            (Type::Array(target), Type::Array(source)) => self.is_assignable(target, source),
+            (Type::Map(target_key, target_value), Type::Map(source_key, source_value)) => {
+                self.is_assignable(target_key, source_key)
+                    && self.is_assignable(target_value, source_value)
+            }
+            (Type::Tuple(targets), Type::Tuple(sources)) => {
+                targets.len() == sources.len()
+                    && targets
+                        .iter()
+                        .zip(sources)
+                        .all(|(target, source)| self.is_assignable(target, source))
+            }
+            (
+                Type::Function(target_parameters, target_return),
+                Type::Function(source_parameters, source_return),
+            ) => {
+                // Parameters are contravariant, the function must accept
+                // everything callers of the target type may pass
+                target_parameters.len() == source_parameters.len()
+                    && target_parameters
+                        .iter()
+                        .zip(source_parameters)
+                        .all(|(target, source)| self.is_assignable(source, target))
+                    && (**target_return == Type::Void
+                        || self.is_assignable(target_return, source_return))
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether `class` is `ancestor` or extends it, directly or not. Type
+    /// arguments are compared invariantly.
+    fn is_subclass(&self, class: &Type, ancestor: &Type) -> bool {
+        let (ancestor_name, ancestor_arguments) = match ancestor {
+            Type::Class(name, arguments) => (name, arguments),
+            _ => return false,
+        };
+
+        let mut current = class.clone();
+        // Inheritance cycles are reported by the resolver, don't loop on them
+        for _ in 0..=self.classes.len() {
+            let (name, arguments) = match &current {
+                Type::Class(name, arguments) => (name, arguments),
+                _ => return false,
+            };
+            if name == ancestor_name {
+                return arguments.len() == ancestor_arguments.len()
+                    && arguments
+                        .iter()
+                        .zip(ancestor_arguments)
+                        .all(|(a, b)| self.is_assignable(a, b) && self.is_assignable(b, a));
+            }
+
+            let info = match self.classes.get(name) {
+                Some(info) => info,
+                None => return false,
+            };
+            let bindings: HashMap<Symbol, Type> = info
+                .type_parameters
+                .iter()
+                .cloned()
+                .zip(arguments.iter().cloned())
+                .collect();
+            current = match &info.superclass {
+                Some(superclass) => substitute(superclass, &bindings),
+                None => return false,
+            };
+        }
+
+        false
+    }
+
+    fn statements(&mut self, statements: &[Rc<Statement>]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Block(stmt) => {
+                self.begin_scope();
+                self.statements(&stmt.declarations);
+                self.end_scope();
+            }
+            Statement::Break(_) | Statement::Continue(_) => {}
+            Statement::Class(stmt) => self.class(stmt),
+            Statement::Expression(stmt) => {
+                self.expression(&stmt.expression);
+            }
+            Statement::For(stmt) => {
+                self.begin_scope();
+                if let Some(initializer) = &stmt.initializer {
+                    self.statement(initializer);
+                }
+                self.enter_loop(statement);
+                let before = self.scopes.clone();
+                if let Some(condition) = &stmt.condition {
+                    self.condition(condition);
+                    self.narrow(&narrowing(condition).when_true);
+                }
+                self.statement(&stmt.body);
+                if let Some(update) = &stmt.update {
+                    self.expression(update);
+                }
+                // The body may not run at all
+                self.scopes = before;
+                self.end_scope();
+            }
+            Statement::Function(stmt) => {
+                if self.scopes.len() > 1 {
+                    let function_type = self.function_type(
+                        &stmt.type_parameters,
+                        &stmt.parameters,
+                        &stmt.return_type,
+                    );
+                    self.declare(&stmt.name, function_type);
+                }
+                let function_type = self.lookup(stmt.name.lexme).cloned();
+                self.function(
+                    &stmt.type_parameters,
+                    &stmt.parameters,
+                    function_type,
+                    &stmt.body,
+                );
+            }
+            Statement::If(stmt) => self.if_statement(stmt),
+            Statement::Program(stmt) => self.statements(&stmt.declarations),
+            Statement::Return(stmt) => self.return_statement(stmt),
+            Statement::Var(stmt) => self.var(stmt),
+            Statement::While(stmt) => {
+                self.enter_loop(statement);
+                let before = self.scopes.clone();
+                self.condition(&stmt.condition);
+                self.narrow(&narrowing(&stmt.condition).when_true);
+                self.statement(&stmt.body);
+                // The body may not run at all
+                self.scopes = before;
+            }
+        }
+    }
+
+    fn enter_loop(&mut self, statement: &Statement) {
+        let mut assigned = HashSet::new();
+        assigned_names(statement, &mut assigned);
+        self.forget_narrowing(&assigned);
+    }
+
+    fn if_statement(&mut self, stmt: &IfStatement) {
+        self.condition(&stmt.condition);
+        let narrowing = narrowing(&stmt.condition);
+
+        let before = self.scopes.clone();
+        self.narrow(&narrowing.when_true);
+        self.statement(&stmt.then_branch);
+        let then_scopes = std::mem::replace(&mut self.scopes, before);
+
+        self.narrow(&narrowing.when_false);
+        if let Some(else_branch) = &stmt.else_branch {
+            self.statement(else_branch);
+        }
+
+        // A branch that always returns or jumps away doesn't affect the code
+        // after the `if`, e.g. `if (x == null) return;` narrows `x`
+        let then_completes = can_complete(&stmt.then_branch);
+        let else_completes = stmt
+            .else_branch
+            .as_ref()
+            .is_none_or(|else_branch| can_complete(else_branch));
+        match (then_completes, else_completes) {
+            (true, true) => self.merge(then_scopes),
+            (true, false) => self.scopes = then_scopes,
+            _ => {}
+        }
+    }
+
+    fn var(&mut self, stmt: &VarStatement) {
+        let annotation = stmt
+            .value_type
+            .as_ref()
+            .map(|annotation| self.resolve_type(annotation));
+        let value_type = stmt
+            .value
+            .as_ref()
+            .map(|value| self.expression_of(value, annotation.as_ref()));
+
+        let ty = match (annotation, value_type.clone()) {
+            (Some(annotation), value_type) => {
+                if let (Some(value), Some(value_type)) = (&stmt.value, value_type) {
+                    self.expect_assignable(&annotation, &value_type, value);
+                }
+                annotation
+            }
+            (None, Some(Type::Null)) => {
+                self.error(
+                    format!(
+                        "Cannot infer the type of '{}' from null, add a type annotation",
+                        stmt.name.lexme
+                    ),
+                    &stmt.name,
+                );
+                Type::Unknown
+            }
+            (None, Some(Type::Void)) => {
+                self.error(
+                    format!(
+                        "Cannot infer the type of '{}' from a void value",
+                        stmt.name.lexme
+                    ),
+                    &stmt.name,
+                );
+                Type::Unknown
+            }
+            (None, Some(inferred)) => inferred,
+            (None, None) => {
+                self.error(
+                    format!(
+                        "Variable '{}' needs a type annotation or an initializer",
+                        stmt.name.lexme
+                    ),
+                    &stmt.name,
+                );
+                Type::Unknown
+            }
+        };
+
+        self.declare_variable(&stmt.name, ty, stmt.value.is_some());
+        if let Some(value_type) = value_type {
+            self.assign(stmt.name.lexme, &value_type);
+        }
+    }
+
+    fn class(&mut self, stmt: &ClassStatement) {
+        if self.scopes.len() > 1 {
+            self.declare_class(stmt);
+            self.declare_class_bounds(stmt);
+            self.declare_class_members(stmt);
+        }
+
+        let outer_class = self.current_class.replace(stmt.name.lexme);
+        let outer = self.reenter_type_parameters(&stmt.type_parameters);
+
+        for member in &stmt.members {
+            match member {
+                ClassMember::Field { name, value, .. } => {
+                    if let Some(value) = value {
+                        let field_type = self.classes[&stmt.name.lexme].fields[&name.lexme].clone();
+                        let value_type = self.expression_of(value, Some(&field_type));
+                        self.expect_assignable(&field_type, &value_type, value);
+                    }
+                }
+                ClassMember::Method {
+                    name,
+                    type_parameters,
+                    parameters,
+                    body,
+                    ..
+                } => {
+                    let method_type = self.classes[&stmt.name.lexme]
+                        .methods
+                        .get(&name.lexme)
+                        .cloned();
+                    self.function(type_parameters, parameters, method_type, body);
+                }
+                ClassMember::Constructor {
+                    parameters, body, ..
+                } => {
+                    let constructor_type = self.classes[&stmt.name.lexme].constructor.clone();
+                    self.function(&[], parameters, constructor_type, body);
+                }
+            }
+        }
+
+        self.type_parameters.truncate(outer);
+        self.current_class = outer_class;
+    }
+
+    /// Checks a function body against its already resolved signature.
+    fn function(
+        &mut self,
+        type_parameters: &[TypeParameter],
+        parameters: &[FunctionParameter],
+        function_type: Option<Type>,
+        body: &[Rc<Statement>],
+    ) {
+        let (parameter_types, return_type) = match function_type {
+            Some(Type::Function(parameter_types, return_type)) => {
+                (parameter_types, return_type.as_ref().clone())
+            }
+            _ => (vec![Type::Unknown; parameters.len()], Type::Unknown),
+        };
+
+        let outer = self.reenter_type_parameters(type_parameters);
+        // Parameters belong to the function, pushing its return type first
+        // lets them be narrowed
+        self.return_types.push(return_type);
+        let mut names = HashSet::new();
+        for statement in body {
+            closure_assigned_names(statement, &mut names);
+        }
+        self.closure_assigned.push(names);
+        self.begin_scope();
+        for (parameter, ty) in parameters.iter().zip(parameter_types) {
+            self.declare(&parameter.name, ty);
+        }
+        self.statements(body);
+        self.closure_assigned.pop();
+        self.return_types.pop();
+        self.end_scope();
+        self.type_parameters.truncate(outer);
+    }
+
+    fn return_statement(&mut self, stmt: &ReturnStatement) {
+        let expected = self.return_types.last().cloned();
+        let value_type = stmt
+            .value
+            .as_ref()
+            .map(|value| self.expression_of(value, expected.as_ref()));
+        let expected = match expected {
+            Some(expected) => expected,
+            None => return,
+        };
+
+        match (&stmt.value, value_type) {
+            (Some(value), Some(_)) if expected == Type::Void => self.error_at(
+                String::from("Cannot return a value from a function returning 'void'"),
+                value.range(),
+            ),
+            (Some(value), Some(value_type)) => {
+                self.expect_assignable(&expected, &value_type, value)
+            }
+            _ if matches!(expected, Type::Void | Type::Unknown) => {}
+            _ => self.error(
+                format!("Expected a return value of type '{}'", expected),
+                &stmt.keyword,
+            ),
+        }
+    }
+
+    /// Returns the type of `name` on an instance of `class`, looking through
+    /// superclasses and substituting class type arguments.
+    fn member_type(&self, class: Symbol, arguments: &[Type], name: Symbol) -> Option<Type> {
+        self.inherited(class, arguments, |info| {
+            info.fields.get(&name).or_else(|| info.methods.get(&name))
+        })
+    }
+
+    /// Returns the constructor signature of `class`, which inherits the
+    /// constructor of its superclass when it doesn't declare one.
+    fn constructor_type(&self, class: Symbol, arguments: &[Type]) -> Option<Type> {
+        self.inherited(class, arguments, |info| info.constructor.as_ref())
+    }
+
+    /// Returns the first type `find` picks from `class` or its superclasses,
+    /// with class type arguments substituted.
+    fn inherited(
+        &self,
+        mut class: Symbol,
+        arguments: &[Type],
+        find: impl Fn(&ClassInfo) -> Option<&Type>,
+    ) -> Option<Type> {
+        let mut arguments = arguments.to_vec();
+
+        // Inheritance cycles are reported by the resolver, don't loop on them
+        for _ in 0..self.classes.len() {
+            let info = self.classes.get(&class)?;
+            let bindings: HashMap<Symbol, Type> = info
+                .type_parameters
+                .iter()
+                .cloned()
+                .zip(arguments.iter().cloned())
+                .collect();
+
+            if let Some(ty) = find(info) {
+                return Some(substitute(ty, &bindings));
+            }
+
+            match &info.superclass {
+                Some(Type::Class(superclass, super_arguments)) => {
+                    arguments = super_arguments
+                        .iter()
+                        .map(|ty| substitute(ty, &bindings))
+                        .collect();
+                    class = *superclass;
+                }
+                _ => return None,
+            }
+        }
+
+        None
+    }
+
+    /// Names of every member `class` has, including inherited ones.
+    fn member_names(&self, class: Symbol) -> Vec<&'static str> {
+        let mut names: Vec<&str> = Vec::new();
+        let mut current = self.classes.get(&class);
+        for _ in 0..self.classes.len() {
+            let info = match current {
+                Some(info) => info,
+                None => break,
+            };
+            names.extend(info.fields.keys().map(|name| name.as_str()));
+            names.extend(info.methods.keys().map(|name| name.as_str()));
+            current = match &info.superclass {
+                Some(Type::Class(superclass, _)) => self.classes.get(superclass),
+                _ => None,
+            };
+        }
+        names
+    }
+
+    fn this_type(&self) -> Type {
+        match &self.current_class {
+            Some(class) => {
+                let arguments = self.classes[class]
+                    .type_parameters
+                    .iter()
+                    .zip(&self.classes[class].bounds)
+                    .map(|(name, bound)| Type::Parameter(*name, bound.clone()))
+                    .collect();
+                Type::Class(*class, arguments)
+            }
+            None => Type::Unknown,
+        }
+    }
+
+    /// Type of an expression whose value is stored where `expected` is. A
+    /// new instance of a generic class takes its type arguments from it
+    /// instead of inferring them from the constructor arguments.
+    fn expression_of(&mut self, expression: &Expression, expected: Option<&Type>) -> Type {
+        match expression {
+            Expression::Call(expr) => self.call(expr, expected),
+            _ => self.expression(expression),
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Type {
+        match expression {
+            Expression::Array(expr) => {
+                let mut element = None;
+                for item in &expr.elements {
+                    let ty = self.element(item);
+                    element = Some(match element {
+                        Some(element) => Type::join(&element, &ty),
+                        None => ty,
+                    });
+                }
+                Type::Array(Rc::new(element.unwrap_or(Type::Unknown)))
+            }
+            Expression::Assignment(expr) => self.assignment(expr),
+            Expression::Binary(expr) => {
+                let left = self.expression(&expr.left);
+                let right = self.expression(&expr.right);
+                self.binary(&expr.operator, &left, &right, expression.range())
+            }
+            Expression::Call(expr) => self.call(expr, None),
+            Expression::Conditional(expr) => {
+                self.condition(&expr.condition);
+                let narrowing = narrowing(&expr.condition);
+
+                let before = self.scopes.clone();
+                self.narrow(&narrowing.when_true);
+                let then_type = self.expression(&expr.then_branch);
+                let then_scopes = std::mem::replace(&mut self.scopes, before);
+
+                self.narrow(&narrowing.when_false);
+                let else_type = self.expression(&expr.else_branch);
+                self.merge(then_scopes);
+
+                then_type.join(&else_type)
+            }
+            Expression::Grouping(expr) => self.expression(&expr.expression),
+            Expression::Identifier(expr) => self.identifier(&expr.name),
+            Expression::Index(expr) => self.index(expr),
+            Expression::LambdaFunction(expr) => {
+                let function_type = self.function_type(&[], &expr.parameters, &expr.return_type);
+                self.function(
+                    &[],
+                    &expr.parameters,
+                    Some(function_type.clone()),
+                    &expr.body,
+                );
+                function_type
+            }
+            Expression::Literal(expr) => literal_type(&expr.value),
+            // The right operand only runs depending on the left one
+            Expression::Logical(expr) => match expr.operator.token_type {
+                TokenType::QuestionQuestion => {
+                    let left = self.expression(&expr.left);
+                    let before = self.scopes.clone();
+                    let right = self.expression(&expr.right);
+                    self.scopes = before;
+                    left.non_null().join(&right)
+                }
+                operator => {
+                    self.condition(&expr.left);
+                    let narrowing = narrowing(&expr.left);
+                    let before = self.scopes.clone();
+                    if operator == TokenType::And {
+                        self.narrow(&narrowing.when_true);
+                    } else {
+                        self.narrow(&narrowing.when_false);
+                    }
+                    self.condition(&expr.right);
+                    self.scopes = before;
+                    Type::Bool
+                }
+            },
+            Expression::Map(expr) => {
+                let mut key_type: Option<Type> = None;
+                let mut value_type: Option<Type> = None;
+                for entry in &expr.entries {
+                    let (key, value) = match entry {
+                        MapEntry::Pair { key, value } => {
+                            (self.expression(key), self.expression(value))
+                        }
+                        MapEntry::Spread { expression } => match self.expression(expression) {
+                            Type::Map(key, value) => (key.as_ref().clone(), value.as_ref().clone()),
+                            _ => (Type::Unknown, Type::Unknown),
+                        },
+                    };
+                    key_type = Some(key_type.map_or(key.clone(), |ty| ty.join(&key)));
+                    value_type = Some(value_type.map_or(value.clone(), |ty| ty.join(&value)));
+                }
+                Type::Map(
+                    Rc::new(key_type.unwrap_or(Type::Unknown)),
+                    Rc::new(value_type.unwrap_or(Type::Unknown)),
+                )
+            }
+            Expression::Member(expr) => self.member(expr, false),
+            Expression::OptionalChain(expr) => self.expression(&expr.expression).nullable(),
+            Expression::Spread(expr) => {
+                self.expression(&expr.expression);
+                Type::Unknown
+            }
+            Expression::Super(_) => {
+                let this = self.this_type();
+                match this {
+                    Type::Class(class, _) => self.classes[&class]
+                        .superclass
+                        .clone()
+                        .unwrap_or(Type::Unknown),
+                    _ => Type::Unknown,
+                }
+            }
+            Expression::This(_) => self.this_type(),
+            Expression::Tuple(expr) => {
+                let types = expr
+                    .elements
+                    .iter()
+                    .map(|element| self.expression(element))
+                    .collect();
+                Type::Tuple(types)
+            }
+            Expression::Unary(expr) => {
+                let right = self.expression(&expr.right);
+                match unary_type(expr.operator.token_type, &right) {
+                    Some(ty) => ty,
+                    None => {
+                        self.error_at(
+                            format!(
+                                "Operator '{}' cannot be applied to type '{}'",
+                                expr.operator.lexme, right
+                            ),
+                            expression.range(),
+                        );
+                        Type::Unknown
+                    }
+                }
+            }
+            Expression::Update(expr) => {
+                let ty = self.expression(&expr.expression);
+                if !matches!(ty, Type::Int | Type::Float | Type::Unknown) {
+                    self.error_at(
+                        format!(
+                            "Operator '{}' cannot be applied to type '{}'",
+                            expr.operator.lexme, ty
+                        ),
+                        expression.range(),
+                    );
+                }
+                ty
+            }
+        }
+    }
+
+    /// Type of `object.name`. `assigned` is set when the member is the
+    /// target of an assignment, methods can only be read.
+    fn member(&mut self, expr: &MemberExpression, assigned: bool) -> Type {
+        let object = self.expression(&expr.object);
+        if !expr.optional && object.is_nullable() {
+            self.error(
+                format!(
+                    "Cannot access member '{}' of a possibly null value of type '{}'",
+                    expr.name.lexme, object
+                ),
+                &expr.name,
+            );
+            return Type::Unknown;
+        }
+        // Members of a bounded type parameter are the ones of its bound
+        let object = match object.non_null() {
+            Type::Parameter(_, Some(bound)) => bound.as_ref().clone(),
+            object => object,
+        };
+        match &object {
+            Type::Class(class, arguments) => {
+                match self.member_type(*class, arguments, expr.name.lexme) {
+                    Some(_) if assigned && self.is_method(*class, expr.name.lexme) => {
+                        self.error(
+                            format!(
+                                "Cannot assign to method '{}' of class '{}'",
+                                expr.name.lexme, class
+                            ),
+                            &expr.name,
+                        );
+                        Type::Unknown
+                    }
+                    Some(ty) => ty,
+                    None => {
+                        // `this.x` and `super.x` are reported by the resolver
+                        if !matches!(
+                            expr.object.as_ref(),
+                            Expression::This(_) | Expression::Super(_)
+                        ) {
+                            let names = self.member_names(*class);
+                            let suggestion = closest(&expr.name.lexme, names.into_iter());
+                            let message =
+                                unknown_member_message(class, &expr.name.lexme, suggestion);
+                            self.error(message, &expr.name);
+                        }
+                        Type::Unknown
+                    }
+                }
+            }
+            Type::String | Type::Array(_) | Type::Map(..) | Type::Tuple(_)
+                if expr.name.lexme == "length" =>
+            {
+                Type::Int
+            }
+            Type::Unknown | Type::Parameter(..) | Type::Union(_) => Type::Unknown,
+            _ => {
+                self.error(
+                    format!("Type '{}' has no member '{}'", object, expr.name.lexme),
+                    &expr.name,
+                );
+                Type::Unknown
+            }
+        }
+    }
+
+    /// Whether `name` is a method of `class`, fields hide methods of
+    /// superclasses with the same name like `member_type` does.
+    fn is_method(&self, class: Symbol, name: Symbol) -> bool {
+        let mut current = self.classes.get(&class);
+        for _ in 0..self.classes.len() {
+            let info = match current {
+                Some(info) => info,
+                None => break,
+            };
+            if info.fields.contains_key(&name) {
+                return false;
+            }
+            if info.methods.contains_key(&name) {
+                return true;
+            }
+            current = match &info.superclass {
+                Some(Type::Class(superclass, _)) => self.classes.get(superclass),
+                _ => None,
+            };
+        }
+        false
+    }
+
+    /// Type of a single array element, unwrapping spread arrays.
+    fn element(&mut self, element: &Expression) -> Type {
+        match element {
+            Expression::Spread(spread) => match self.expression(&spread.expression) {
+                Type::Array(element) => element.as_ref().clone(),
+                _ => Type::Unknown,
+            },
+            _ => self.expression(element),
+        }
+    }
+
+    fn identifier(&mut self, name: &Token) -> Type {
+        let depth = self.return_types.len();
+        let variable = match self.variable(name.lexme) {
+            Some(variable) => variable.clone(),
+            None => return Type::Unknown,
+        };
+        // Closures may run after outer variables are assigned or changed
+        if variable.function_depth != depth {
+            return variable.ty;
+        }
+
+        if !variable.assigned {
+            self.error(
+                format!("Variable '{}' is read before being assigned", name.lexme),
+                name,
+            );
+            // Report each variable only once
+            if let Some(variable) = self.local_variable_mut(name.lexme) {
+                variable.assigned = true;
+            }
+        }
+        variable.narrowed.unwrap_or(variable.ty)
+    }
+
+    fn call(&mut self, expr: &CallExpression, expected: Option<&Type>) -> Type {
+        let arguments: Vec<Type> = expr
+            .arguments
+            .iter()
+            .map(|argument| self.expression(argument))
+            .collect();
+
+        // `App(args)` creates an instance unless `App` is shadowed by a value
+        if let Expression::Identifier(IdentifierExpression { name }) = expr.callee.as_ref() {
+            if self.lookup(name.lexme).is_none() && self.classes.contains_key(&name.lexme) {
+                let instance = match expected.map(Type::non_null) {
+                    Some(Type::Class(class, instance)) if class == name.lexme => instance,
+                    _ => self.infer_type_arguments(name.lexme, &arguments),
+                };
+                let range = PosRange(expr.callee.start_pos(), expr.paren.end_pos);
+                self.check_class_bounds(name.lexme, &instance, range);
+                let constructor = self.constructor_type(name.lexme, &instance);
+                self.constructor_call(expr, constructor, &arguments);
+                return Type::Class(name.lexme, instance);
+            }
+        }
+        if let Expression::Super(_) = expr.callee.as_ref() {
+            if let Type::Class(superclass, instance) = self.expression(&expr.callee) {
+                let constructor = self.constructor_type(superclass, &instance);
+                self.constructor_call(expr, constructor, &arguments);
+            }
+            return Type::Void;
+        }
+
+        let callee = self.expression(&expr.callee);
+        if !expr.optional && callee.is_nullable() {
+            self.error_at(
+                format!("Cannot call a possibly null value of type '{}'", callee),
+                expr.callee.range(),
+            );
+            return Type::Unknown;
+        }
+        let callee = callee.non_null();
+
+        match callee {
+            Type::Function(parameters, return_type) => {
+                self.function_call(expr, &parameters, &return_type, &arguments)
+            }
+            Type::Unknown | Type::Parameter(..) | Type::Union(_) => Type::Unknown,
+            _ => {
+                self.error_at(
+                    format!("Type '{}' is not callable", callee),
+                    expr.callee.range(),
+                );
+                Type::Unknown
+            }
+        }
+    }
+
+    /// Type arguments of a new instance of `class`, inferred from the types
+    /// of the constructor arguments. Parameters the constructor doesn't
+    /// mention stay unknown.
+    fn infer_type_arguments(&self, class: Symbol, arguments: &[Type]) -> Vec<Type> {
+        let info = &self.classes[&class];
+        let generic: Vec<Type> = info
+            .type_parameters
+            .iter()
+            .zip(&info.bounds)
+            .map(|(name, bound)| Type::Parameter(*name, bound.clone()))
+            .collect();
+        let mut bindings = HashMap::new();
+        if let Some(Type::Function(parameters, _)) = self.constructor_type(class, &generic) {
+            for (parameter, argument) in parameters.iter().zip(arguments) {
+                bind(parameter, argument, &mut bindings);
+            }
+        }
+        info.type_parameters
+            .iter()
+            .map(|name| bindings.remove(name).unwrap_or(Type::Unknown))
+            .collect()
+    }
+
+    /// Checks the arguments of a constructor call, a class without a
+    /// constructor takes none.
+    fn constructor_call(
+        &mut self,
+        expr: &CallExpression,
+        constructor: Option<Type>,
+        arguments: &[Type],
+    ) {
+        match constructor {
+            Some(Type::Function(parameters, return_type)) => {
+                self.function_call(expr, &parameters, &return_type, arguments);
+            }
+            _ => {
+                self.function_call(expr, &[], &Type::Void, arguments);
+            }
+        }
+    }
+
+    /// Checks the arguments of a call and returns the type of its result.
+    fn function_call(
+        &mut self,
+        expr: &CallExpression,
+        parameters: &[Type],
+        return_type: &Type,
+        arguments: &[Type],
+    ) -> Type {
+        // Spread arguments expand to an unknown number of values
+        let spread = expr
+            .arguments
+            .iter()
+            .any(|argument| matches!(argument.as_ref(), Expression::Spread(_)));
+        if !spread && parameters.len() != arguments.len() {
+            self.error_at(
+                format!(
+                    "Expected {} argument(s) but got {}",
+                    parameters.len(),
+                    arguments.len()
+                ),
+                PosRange(expr.callee.start_pos(), expr.paren.end_pos),
+            );
+        }
+
+        let mut bindings: HashMap<Symbol, Type> = HashMap::new();
+        for (parameter, argument) in parameters.iter().zip(arguments.iter()) {
+            bind(parameter, argument, &mut bindings);
+        }
+        let mut type_parameters = Vec::new();
+        for parameter in parameters {
+            collect_type_parameters(parameter, &mut type_parameters);
+        }
+        let range = PosRange(expr.callee.start_pos(), expr.paren.end_pos);
+        self.check_bounds(&type_parameters, &bindings, range);
+        if !spread {
+            for ((parameter, argument), expression) in
+                parameters.iter().zip(arguments).zip(&expr.arguments)
+            {
+                let parameter = substitute(parameter, &bindings);
+                self.expect_assignable(&parameter, argument, expression);
+            }
+        }
+        substitute(return_type, &bindings)
+    }
+
+    fn assignment(&mut self, expr: &AssignmentExpression) -> Type {
+        let target = match expr.left.as_ref() {
+            Expression::Identifier(IdentifierExpression { name }) => Some(name),
+            _ => None,
+        };
+        // The stored value has to fit the declared type, even where the
+        // variable is narrowed
+        let declared = target.map(|name| self.lookup(name.lexme).cloned().unwrap_or(Type::Unknown));
+        // Plain assignment doesn't read the variable
+        let left = match (&declared, expr.left.as_ref(), expr.operator.token_type) {
+            (Some(declared), _, TokenType::Equal) => declared.clone(),
+            (_, Expression::Member(member), _) => self.member(member, true),
+            _ => self.expression(&expr.left),
+        };
+        let right = match expr.operator.token_type {
+            TokenType::Equal => self.expression_of(&expr.right, Some(&left)),
+            _ => self.expression(&expr.right),
+        };
+
+        let value = match compound_operator(expr.operator.token_type) {
+            Some(TokenType::QuestionQuestion) => left.non_null().join(&right),
+            Some(operator) => {
+                // `+=` reports errors as `+` would
+                let operator = Token {
+                    token_type: operator,
+                    lexme: Symbol::intern(expr.operator.lexme.trim_end_matches('=')),
+                    ..expr.operator.clone()
+                };
+                let range = PosRange(expr.left.start_pos(), expr.right.end_pos());
+                self.binary(&operator, &left, &right, range)
+            }
+            None => right,
+        };
+        self.expect_assignable(declared.as_ref().unwrap_or(&left), &value, &expr.right);
+        if let Some(name) = target {
+            self.assign(name.lexme, &value);
+        }
+
+        left
+    }
+
+    fn binary(&mut self, operator: &Token, left: &Type, right: &Type, range: PosRange) -> Type {
+        match binary_type(operator.token_type, left, right) {
+            Some(ty) => ty,
+            None => {
+                self.error_at(
+                    format!(
+                        "Operator '{}' cannot be applied to types '{}' and '{}'",
+                        operator.lexme, left, right
+                    ),
+                    range,
+                );
+                Type::Unknown
+            }
+        }
+    }
+
+    fn index(&mut self, expr: &IndexExpression) -> Type {
+        let object = self.expression(&expr.object);
+        let index = self.expression(&expr.index);
+        if !expr.optional && object.is_nullable() {
+            self.error_at(
+                format!("Cannot index a possibly null value of type '{}'", object),
+                expr.object.range(),
+            );
+            return Type::Unknown;
+        }
+        let object = object.non_null();
+
+        let key = match &object {
+            Type::Array(_) | Type::String | Type::Tuple(_) => Type::Int,
+            Type::Map(key, _) => key.as_ref().clone(),
+            Type::Unknown | Type::Parameter(..) | Type::Union(_) => Type::Unknown,
+            _ => {
+                self.error_at(
+                    format!("Type '{}' cannot be indexed", object),
+                    expr.object.range(),
+                );
+                return Type::Unknown;
+            }
+        };
+        self.expect_assignable(&key, &index, &expr.index);
+
+        index_type(&object, &expr.index)
+    }
+}
+
+fn type_parameter_names(type_parameters: &[TypeParameter]) -> Vec<Symbol> {
+    type_parameters
+        .iter()
+        .map(|parameter| parameter.name.lexme)
+        .collect()
+}
+
+fn native_type(native: &NativeFunction) -> Type {
+    let builtin = |name: &str| Type::builtin(name).unwrap_or(Type::Unknown);
+    Type::Function(
+        native.parameters.iter().map(|name| builtin(name)).collect(),
+        Rc::new(builtin(native.return_type)),
+    )
+}
+
+fn literal_type(literal: &Literal) -> Type {
+    match literal {
+        Literal::Null => Type::Null,
+        Literal::Boolean(_) => Type::Bool,
+        Literal::Integer(_) => Type::Int,
+        Literal::Float(_) => Type::Float,
+        Literal::String(_) => Type::String,
+    }
+}
+
+/// Result type of a binary operator, `None` when it doesn't apply to the
+/// operand types.
+fn binary_type(operator: TokenType, left: &Type, right: &Type) -> Option<Type> {
+    let numeric = |ty: &Type| matches!(ty, Type::Int | Type::Float | Type::Unknown);
+    let integer = |ty: &Type| matches!(ty, Type::Int | Type::Unknown);
+
+    match operator {
+        TokenType::EqualEqual | TokenType::BangEqual => Some(Type::Bool),
+        TokenType::Less | TokenType::LessEqual | TokenType::Greater | TokenType::GreaterEqual => {
+            let strings = matches!(
+                (left, right),
+                (Type::String, Type::String | Type::Unknown) | (Type::Unknown, Type::String)
+            );
+            (strings || (numeric(left) && numeric(right))).then_some(Type::Bool)
+        }
+        TokenType::Ampersand
+        | TokenType::Pipe
+        | TokenType::Caret
+        | TokenType::LessLess
+        | TokenType::GreaterGreater => (integer(left) && integer(right)).then_some(Type::Int),
+        // Strings can be concatenated with primitive values
+        TokenType::Plus if *left == Type::String || *right == Type::String => {
+            let concatenable = |ty: &Type| {
+                matches!(
+                    ty,
+                    Type::String | Type::Int | Type::Float | Type::Bool | Type::Unknown
+                )
+            };
+            (concatenable(left) && concatenable(right)).then_some(Type::String)
+        }
+        _ => match (left, right) {
+            (Type::Int, Type::Int) => Some(Type::Int),
+            (Type::Int | Type::Float, Type::Int | Type::Float) => Some(Type::Float),
+            _ if numeric(left) && numeric(right) => Some(Type::Unknown),
+            _ => None,
+        },
+    }
+}
+
+/// Result type of a prefix operator, `None` when it doesn't apply to the
+/// operand type.
+fn unary_type(operator: TokenType, right: &Type) -> Option<Type> {
+    match (operator, right) {
+        (_, Type::Unknown) => Some(Type::Unknown),
+        (TokenType::Bang, Type::Bool) => Some(Type::Bool),
+        (TokenType::Tilde, Type::Int) => Some(Type::Int),
+        (TokenType::Minus, Type::Int | Type::Float) => Some(right.clone()),
+        _ => None,
+    }
+}
+
+fn index_type(object: &Type, index: &Expression) -> Type {
+    match object {
+        Type::Array(element) => element.as_ref().clone(),
+        Type::Map(_, value) => value.as_ref().clone().nullable(),
+        Type::String => Type::String,
+        Type::Tuple(types) => match index {
+            Expression::Literal(LiteralExpression {
+                value: Literal::Integer(i),
+                ..
+            }) => types.get(*i as usize).cloned().unwrap_or(Type::Unknown),
+            _ => Type::Unknown,
+        },
+        _ => Type::Unknown,
+    }
+}
+
+/// Replaces generic type parameters with their bound types.
+pub fn substitute(ty: &Type, bindings: &HashMap<Symbol, Type>) -> Type {
+    if bindings.is_empty() {
+        return ty.clone();
+    }
+    match ty {
+        Type::Parameter(name, _) => bindings.get(name).cloned().unwrap_or_else(|| ty.clone()),
+        Type::Class(name, arguments) => Type::Class(
+            *name,
+            arguments
+                .iter()
+                .map(|ty| substitute(ty, bindings))
+                .collect(),
+        ),
+        Type::Array(element) => Type::Array(Rc::new(substitute(element, bindings))),
+        Type::Map(key, value) => Type::Map(
+            Rc::new(substitute(key, bindings)),
+            Rc::new(substitute(value, bindings)),
+        ),
+        Type::Tuple(types) => {
+            Type::Tuple(types.iter().map(|ty| substitute(ty, bindings)).collect())
+        }
+        Type::Function(parameters, return_type) => Type::Function(
+            parameters
+                .iter()
+                .map(|ty| substitute(ty, bindings))
+                .collect(),
+            Rc::new(substitute(return_type, bindings)),
+        ),
+        Type::Nullable(inner) => substitute(inner, bindings).nullable(),
+        Type::Union(types) => {
+            Type::union(types.iter().map(|ty| substitute(ty, bindings)).collect())
+        }
+        _ => ty.clone(),
+    }
+}
+
+/// Adds the type parameters `ty` mentions, with their bounds, to `found`.
+fn collect_type_parameters(ty: &Type, found: &mut Vec<(Symbol, Option<Rc<Type>>)>) {
+    match ty {
+        Type::Parameter(name, bound) if !found.iter().any(|(other, _)| other == name) => {
+            found.push((*name, bound.clone()));
+        }
+        Type::Array(inner) | Type::Nullable(inner) => collect_type_parameters(inner, found),
+        Type::Map(key, value) => {
+            collect_type_parameters(key, found);
+            collect_type_parameters(value, found);
+        }
+        Type::Class(_, types) | Type::Tuple(types) | Type::Union(types) => {
+            for ty in types {
+                collect_type_parameters(ty, found);
+            }
+        }
+        Type::Function(parameters, return_type) => {
+            for ty in parameters {
+                collect_type_parameters(ty, found);
+            }
+            collect_type_parameters(return_type, found);
+        }
+        _ => {}
+    }
+}
+
+/// Infers generic parameter bindings by matching a parameter type against
+/// the type of the passed argument.
+fn bind(parameter: &Type, argument: &Type, bindings: &mut HashMap<Symbol, Type>) {
+    match (parameter, argument) {
+        (Type::Parameter(name, _), _)
+            if !bindings.contains_key(name) && *argument != Type::Unknown =>
+        {
+            bindings.insert(*name, argument.clone());
+        }
+        (Type::Array(parameter), Type::Array(argument))
+        | (Type::Nullable(parameter), Type::Nullable(argument)) => {
+            bind(parameter, argument, bindings)
+        }
+        (Type::Nullable(parameter), argument) => bind(parameter, argument, bindings),
+        (Type::Map(key, value), Type::Map(argument_key, argument_value)) => {
+            bind(key, argument_key, bindings);
+            bind(value, argument_value, bindings);
+        }
+        (Type::Class(name, parameters), Type::Class(argument_name, arguments))
+            if name == argument_name =>
+        {
+            for (parameter, argument) in parameters.iter().zip(arguments) {
+                bind(parameter, argument, bindings);
+            }
+        }
+        (Type::Function(parameters, return_type), Type::Function(arguments, argument_return)) => {
+            for (parameter, argument) in parameters.iter().zip(arguments) {
+                bind(parameter, argument, bindings);
+            }
+            bind(return_type, argument_return, bindings);
+        }
+        _ => {}
+    }
+}
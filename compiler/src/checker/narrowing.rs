@@ -0,0 +1,250 @@
+use crate::parser::expr::*;
+use crate::parser::stmt::*;
+use crate::scanner::token::{Literal, TokenType};
+use crate::symbol::Symbol;
+use std::collections::HashSet;
+
+/// Variables a condition proves to be non-null, depending on its outcome.
+#[derive(Debug, Default)]
+pub struct Narrowing {
+    pub when_true: Vec<Symbol>,
+    pub when_false: Vec<Symbol>,
+}
+
+impl Narrowing {
+    fn negate(self) -> Narrowing {
+        Narrowing {
+            when_true: self.when_false,
+            when_false: self.when_true,
+        }
+    }
+}
+
+/// Collects null checks like `x != null`, `null == x`, and their
+/// combinations through `and`, `or` and `!`.
+pub fn narrowing(condition: &Expression) -> Narrowing {
+    match condition {
+        Expression::Binary(expr) => {
+            let name = match (expr.left.as_ref(), expr.right.as_ref()) {
+                (Expression::Identifier(IdentifierExpression { name }), other)
+                | (other, Expression::Identifier(IdentifierExpression { name }))
+                    if is_null(other) =>
+                {
+                    name.lexme
+                }
+                _ => return Narrowing::default(),
+            };
+            match expr.operator.token_type {
+                TokenType::BangEqual => Narrowing {
+                    when_true: vec![name],
+                    when_false: Vec::new(),
+                },
+                TokenType::EqualEqual => Narrowing {
+                    when_true: Vec::new(),
+                    when_false: vec![name],
+                },
+                _ => Narrowing::default(),
+            }
+        }
+        Expression::Logical(expr) => {
+            let left = narrowing(&expr.left);
+            let right = narrowing(&expr.right);
+            match expr.operator.token_type {
+                // Both operands are true when `and` is
+                TokenType::And => Narrowing {
+                    when_true: [left.when_true, right.when_true].concat(),
+                    when_false: Vec::new(),
+                },
+                // Both operands are false when `or` is
+                TokenType::Or => Narrowing {
+                    when_true: Vec::new(),
+                    when_false: [left.when_false, right.when_false].concat(),
+                },
+                _ => Narrowing::default(),
+            }
+        }
+        Expression::Unary(expr) if expr.operator.token_type == TokenType::Bang => {
+            narrowing(&expr.right).negate()
+        }
+        Expression::Grouping(expr) => narrowing(&expr.expression),
+        _ => Narrowing::default(),
+    }
+}
+
+fn is_null(expression: &Expression) -> bool {
+    matches!(
+        expression,
+        Expression::Literal(LiteralExpression {
+            value: Literal::Null,
+            ..
+        })
+    )
+}
+
+/// Names of the variables a statement may assign to, including assignments
+/// made by nested functions.
+pub fn assigned_names(statement: &Statement, names: &mut HashSet<Symbol>) {
+    statement_assignments(statement, names, false);
+}
+
+/// Names of the variables functions nested in a statement may assign to.
+/// Calling one of these functions may change the variables at any time.
+pub fn closure_assigned_names(statement: &Statement, names: &mut HashSet<Symbol>) {
+    statement_assignments(statement, names, true);
+}
+
+/// Collects assigned names, only the ones inside nested functions when
+/// `nested_only`.
+fn statement_assignments(statement: &Statement, names: &mut HashSet<Symbol>, nested_only: bool) {
+    match statement {
+        Statement::Block(stmt) => {
+            for declaration in &stmt.declarations {
+                statement_assignments(declaration, names, nested_only);
+            }
+        }
+        Statement::Break(_) | Statement::Continue(_) => {}
+        Statement::Class(stmt) => {
+            for member in &stmt.members {
+                match member {
+                    ClassMember::Field { value, .. } => {
+                        if let Some(value) = value {
+                            expression_assignments(value, names, false);
+                        }
+                    }
+                    ClassMember::Method { body, .. } | ClassMember::Constructor { body, .. } => {
+                        for statement in body {
+                            statement_assignments(statement, names, false);
+                        }
+                    }
+                }
+            }
+        }
+        Statement::Expression(stmt) => expression_assignments(&stmt.expression, names, nested_only),
+        Statement::For(stmt) => {
+            if let Some(initializer) = &stmt.initializer {
+                statement_assignments(initializer, names, nested_only);
+            }
+            for expression in stmt.condition.iter().chain(&stmt.update) {
+                expression_assignments(expression, names, nested_only);
+            }
+            statement_assignments(&stmt.body, names, nested_only);
+        }
+        Statement::Function(stmt) => {
+            for statement in &stmt.body {
+                statement_assignments(statement, names, false);
+            }
+        }
+        Statement::If(stmt) => {
+            expression_assignments(&stmt.condition, names, nested_only);
+            statement_assignments(&stmt.then_branch, names, nested_only);
+            if let Some(else_branch) = &stmt.else_branch {
+                statement_assignments(else_branch, names, nested_only);
+            }
+        }
+        Statement::Program(stmt) => {
+            for declaration in &stmt.declarations {
+                statement_assignments(declaration, names, nested_only);
+            }
+        }
+        Statement::Return(stmt) => {
+            if let Some(value) = &stmt.value {
+                expression_assignments(value, names, nested_only);
+            }
+        }
+        Statement::Var(stmt) => {
+            if let Some(value) = &stmt.value {
+                expression_assignments(value, names, nested_only);
+            }
+        }
+        Statement::While(stmt) => {
+            expression_assignments(&stmt.condition, names, nested_only);
+            statement_assignments(&stmt.body, names, nested_only);
+        }
+    }
+}
+
+fn expression_assignments(expression: &Expression, names: &mut HashSet<Symbol>, nested_only: bool) {
+    match expression {
+        Expression::Array(expr) => {
+            for element in &expr.elements {
+                expression_assignments(element, names, nested_only);
+            }
+        }
+        Expression::Assignment(expr) => {
+            match expr.left.as_ref() {
+                Expression::Identifier(IdentifierExpression { name }) if !nested_only => {
+                    names.insert(name.lexme);
+                }
+                _ => {}
+            }
+            expression_assignments(&expr.left, names, nested_only);
+            expression_assignments(&expr.right, names, nested_only);
+        }
+        Expression::Binary(expr) => {
+            expression_assignments(&expr.left, names, nested_only);
+            expression_assignments(&expr.right, names, nested_only);
+        }
+        Expression::Call(expr) => {
+            expression_assignments(&expr.callee, names, nested_only);
+            for argument in &expr.arguments {
+                expression_assignments(argument, names, nested_only);
+            }
+        }
+        Expression::Conditional(expr) => {
+            expression_assignments(&expr.condition, names, nested_only);
+            expression_assignments(&expr.then_branch, names, nested_only);
+            expression_assignments(&expr.else_branch, names, nested_only);
+        }
+        Expression::Grouping(expr) => expression_assignments(&expr.expression, names, nested_only),
+        Expression::Index(expr) => {
+            expression_assignments(&expr.object, names, nested_only);
+            expression_assignments(&expr.index, names, nested_only);
+        }
+        Expression::LambdaFunction(expr) => {
+            for statement in &expr.body {
+                statement_assignments(statement, names, false);
+            }
+        }
+        Expression::Logical(expr) => {
+            expression_assignments(&expr.left, names, nested_only);
+            expression_assignments(&expr.right, names, nested_only);
+        }
+        Expression::Map(expr) => {
+            for entry in &expr.entries {
+                match entry {
+                    MapEntry::Pair { key, value } => {
+                        expression_assignments(key, names, nested_only);
+                        expression_assignments(value, names, nested_only);
+                    }
+                    MapEntry::Spread { expression } => {
+                        expression_assignments(expression, names, nested_only)
+                    }
+                }
+            }
+        }
+        Expression::Member(expr) => expression_assignments(&expr.object, names, nested_only),
+        Expression::OptionalChain(expr) => {
+            expression_assignments(&expr.expression, names, nested_only)
+        }
+        Expression::Spread(expr) => expression_assignments(&expr.expression, names, nested_only),
+        Expression::Tuple(expr) => {
+            for element in &expr.elements {
+                expression_assignments(element, names, nested_only);
+            }
+        }
+        Expression::Unary(expr) => expression_assignments(&expr.right, names, nested_only),
+        Expression::Update(expr) => {
+            match expr.expression.as_ref() {
+                Expression::Identifier(IdentifierExpression { name }) if !nested_only => {
+                    names.insert(name.lexme);
+                }
+                _ => {}
+            }
+            expression_assignments(&expr.expression, names, nested_only);
+        }
+        Expression::Identifier(_)
+        | Expression::Literal(_)
+        | Expression::Super(_)
+        | Expression::This(_) => {}
+    }
+}
@@ -0,0 +1,167 @@
+use crate::symbol::Symbol;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+/// Semantic type of a value as seen by the checker.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    /// Type that couldn't be determined. It is compatible with every other
+    /// type so a single mistake doesn't cascade into many errors.
+    Unknown,
+    Void,
+    Null,
+    Bool,
+    Int,
+    Float,
+    String,
+    Class(Symbol, Vec<Type>),
+    /// Generic type parameter in scope, e.g. `T` inside `class Box<T>`, with
+    /// the type it `extends` if any.
+    Parameter(Symbol, Option<Rc<Type>>),
+    Array(Rc<Type>),
+    Map(Rc<Type>, Rc<Type>),
+    Tuple(Vec<Type>),
+    Function(Vec<Type>, Rc<Type>),
+    Nullable(Rc<Type>),
+    Union(Vec<Type>),
+}
+
+impl Type {
+    pub fn builtin(name: &str) -> Option<Type> {
+        match name {
+            "any" => Some(Type::Unknown),
+            "void" => Some(Type::Void),
+            "null" => Some(Type::Null),
+            "bool" => Some(Type::Bool),
+            "int" => Some(Type::Int),
+            "float" => Some(Type::Float),
+            "string" => Some(Type::String),
+            _ => None,
+        }
+    }
+
+    /// Wraps the type into `Nullable` unless it already accepts null.
+    pub fn nullable(self) -> Type {
+        match self {
+            Type::Unknown | Type::Null | Type::Nullable(_) => self,
+            _ => Type::Nullable(Rc::new(self)),
+        }
+    }
+
+    /// Whether null is one of the values of the type.
+    pub fn is_nullable(&self) -> bool {
+        match self {
+            Type::Null | Type::Nullable(_) => true,
+            Type::Union(types) => types.contains(&Type::Null),
+            _ => false,
+        }
+    }
+
+    /// Strips `Nullable` and null members of a union.
+    pub fn non_null(&self) -> Type {
+        match self {
+            Type::Nullable(inner) => inner.as_ref().clone(),
+            Type::Union(types) => {
+                let types: Vec<Type> = types
+                    .iter()
+                    .filter(|ty| **ty != Type::Null)
+                    .cloned()
+                    .collect();
+                Type::union(types)
+            }
+            _ => self.clone(),
+        }
+    }
+
+    /// Smallest type both `self` and `other` values fit in.
+    pub fn join(&self, other: &Type) -> Type {
+        if self == other {
+            return self.clone();
+        }
+        match (self, other) {
+            (Type::Unknown, _) | (_, Type::Unknown) => Type::Unknown,
+            (Type::Null, other) | (other, Type::Null) => other.clone().nullable(),
+            (Type::Nullable(inner), other) | (other, Type::Nullable(inner)) => {
+                inner.join(&other.non_null()).nullable()
+            }
+            (Type::Int, Type::Float) | (Type::Float, Type::Int) => Type::Float,
+            _ => Type::union(vec![self.clone(), other.clone()]),
+        }
+    }
+
+    /// Builds a union flattening nested unions and dropping duplicates.
+    pub fn union(types: Vec<Type>) -> Type {
+        let mut members: Vec<Type> = Vec::new();
+        for ty in types {
+            let flattened = match ty {
+                Type::Union(inner) => inner,
+                ty => vec![ty],
+            };
+            for ty in flattened {
+                if !members.contains(&ty) {
+                    members.push(ty);
+                }
+            }
+        }
+
+        match members.len() {
+            0 => Type::Void,
+            1 => members.pop().unwrap(),
+            _ => Type::Union(members),
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Unknown => write!(f, "any"),
+            Type::Void => write!(f, "void"),
+            Type::Null => write!(f, "null"),
+            Type::Bool => write!(f, "bool"),
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Class(name, arguments) => {
+                write!(f, "{}", name)?;
+                if !arguments.is_empty() {
+                    write!(f, "<")?;
+                    write_joined(f, arguments, ", ")?;
+                    write!(f, ">")?;
+                }
+                Ok(())
+            }
+            Type::Parameter(name, _) => write!(f, "{}", name),
+            Type::Array(element) => match element.as_ref() {
+                Type::Function(..) | Type::Union(_) => write!(f, "({})[]", element),
+                _ => write!(f, "{}[]", element),
+            },
+            Type::Map(key, value) => write!(f, "Map<{}, {}>", key, value),
+            Type::Tuple(types) => {
+                write!(f, "(")?;
+                write_joined(f, types, ", ")?;
+                write!(f, ")")
+            }
+            Type::Function(parameters, return_type) => {
+                write!(f, "(")?;
+                write_joined(f, parameters, ", ")?;
+                write!(f, ") -> {}", return_type)
+            }
+            Type::Nullable(inner) => match inner.as_ref() {
+                Type::Function(..) | Type::Union(_) => write!(f, "({})?", inner),
+                _ => write!(f, "{}?", inner),
+            },
+            Type::Union(types) => write_joined(f, types, " | "),
+        }
+    }
+}
+
+fn write_joined(f: &mut Formatter<'_>, types: &[Type], separator: &str) -> std::fmt::Result {
+    for (i, ty) in types.iter().enumerate() {
+        if i > 0 {
+            write!(f, "{}", separator)?;
+        }
+        write!(f, "{}", ty)?;
+    }
+    Ok(())
+}
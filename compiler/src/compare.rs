@@ -0,0 +1,214 @@
+use crate::bytecode::chunk::Function;
+use crate::interpreter::Interpreter;
+use crate::parser::stmt::Statement;
+use crate::vm::gc::GcConfig;
+use crate::vm::{RuntimeError, Vm};
+use crate::{compile_program, parse_source};
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::{fs, io};
+
+/// Output buffer shared between a backend and the comparison.
+#[derive(Clone, Default)]
+struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Capture {
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// What running a program produced: the text it printed, then either the
+/// value `main` returned or the runtime error message.
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    output: String,
+    result: Result<String, String>,
+}
+
+impl Outcome {
+    fn new(capture: &Capture, result: Result<String, RuntimeError>) -> Self {
+        Outcome {
+            output: capture.text(),
+            result: result.map_err(|e| e.message),
+        }
+    }
+
+    /// Text of an expected output file for this outcome: what the program
+    /// printed followed by a `returned` or `runtime error` line.
+    fn expectation(&self) -> String {
+        let result = match &self.result {
+            Ok(value) => format!("returned {}", value),
+            Err(message) => format!("runtime error: {}", message),
+        };
+        format!("{}{}\n", self.output, result)
+    }
+
+    fn print(&self, backend: &str) {
+        println!("  {}:", backend);
+        for line in self.output.lines() {
+            println!("    | {}", line);
+        }
+        match &self.result {
+            Ok(value) => println!("    returned {}", value),
+            Err(message) => println!("    runtime error: {}", message),
+        }
+    }
+}
+
+fn run_vm(script: Rc<Function>, gc: GcConfig) -> Outcome {
+    let capture = Capture::default();
+    let mut vm = Vm::new(Box::new(capture.clone()), gc);
+    let result = vm
+        .run(script)
+        .and_then(|_| vm.call_main(&[]))
+        .map(|value| vm.heap.display(value));
+    Outcome::new(&capture, result)
+}
+
+fn run_interpreter(program: &Statement) -> Outcome {
+    let capture = Capture::default();
+    let mut interpreter = Interpreter::new(Box::new(capture.clone()));
+    let result = interpreter
+        .run(program)
+        .and_then(|_| interpreter.call_main(&[]))
+        .map(|value| value.to_string());
+    Outcome::new(&capture, result)
+}
+
+/// Programs among `paths`: files as given, and the `.txt` files of
+/// directories.
+fn programs(paths: &[String]) -> io::Result<Vec<PathBuf>> {
+    let mut programs = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            let mut entries = Vec::new();
+            for entry in fs::read_dir(path)? {
+                let entry = entry?.path();
+                if entry
+                    .extension()
+                    .is_some_and(|extension| extension == "txt")
+                {
+                    entries.push(entry);
+                }
+            }
+            entries.sort();
+            programs.extend(entries);
+        } else {
+            programs.push(path.to_path_buf());
+        }
+    }
+    Ok(programs)
+}
+
+/// Runs every program on the bytecode VM and on the tree-walking
+/// interpreter and reports the ones where they disagree, either in what
+/// they print, the value `main` returns or the runtime error they stop
+/// with. A program with an `.out` file next to it must also produce the
+/// outcome written there, so bugs both backends share are caught too.
+/// Returns `false` if any program differs or doesn't compile. The VM
+/// collects garbage as `gc` says.
+pub fn compare_backends(paths: &[String], gc: GcConfig) -> bool {
+    let programs = match programs(paths) {
+        Ok(programs) => programs,
+        Err(e) => {
+            eprintln!("cannot list programs: {}", e);
+            return false;
+        }
+    };
+
+    let mut failures = 0;
+    for path in &programs {
+        let name = path.display();
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("cannot read '{}': {}", name, e);
+                failures += 1;
+                continue;
+            }
+        };
+        let compiled = parse_source(&source)
+            .and_then(|program| Some((compile_program(&program, &source, false)?, program)));
+        let (script, program) = match compiled {
+            Some(compiled) => compiled,
+            None => {
+                println!("FAIL {} (does not compile)", name);
+                failures += 1;
+                continue;
+            }
+        };
+
+        let vm = run_vm(script, gc);
+        let interpreter = run_interpreter(&program);
+        let expected_path = path.with_extension("out");
+        let expected = fs::read_to_string(&expected_path).ok();
+        if vm != interpreter {
+            println!("FAIL {}", name);
+            vm.print("vm");
+            interpreter.print("ast");
+            failures += 1;
+        } else if expected
+            .as_ref()
+            .is_some_and(|text| *text != vm.expectation())
+        {
+            println!("FAIL {} (differs from {})", name, expected_path.display());
+            println!("  expected:");
+            for line in expected.unwrap().lines() {
+                println!("    | {}", line);
+            }
+            vm.print("both backends");
+            failures += 1;
+        } else {
+            println!("ok   {}", name);
+        }
+    }
+
+    println!("{} program(s), {} failure(s)", programs.len(), failures);
+    failures == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// Compares the programs of the corpus on a thread with the stack the
+    /// compiler gets, which deep recursion in the interpreter needs.
+    fn compare_corpus(gc: GcConfig) -> bool {
+        let corpus = vec![format!("{}/programs", env!("CARGO_MANIFEST_DIR"))];
+        thread::Builder::new()
+            .stack_size(crate::STACK_SIZE)
+            .spawn(move || compare_backends(&corpus, gc))
+            .unwrap()
+            .join()
+            .unwrap()
+    }
+
+    #[test]
+    fn backends_agree() {
+        assert!(compare_corpus(GcConfig::default()));
+    }
+
+    #[test]
+    fn backends_agree_under_gc_stress() {
+        assert!(compare_corpus(GcConfig {
+            stress: true,
+            ..GcConfig::default()
+        }));
+    }
+}
@@ -0,0 +1,73 @@
+use crate::scanner::pos::{Pos, PosRange};
+use crate::scanner::token::Token;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Problem reported by one of the passes running after the parser. Only
+/// errors prevent the program from being compiled.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub start_pos: Pos,
+    pub end_pos: Pos,
+}
+
+impl Diagnostic {
+    pub fn error(message: String, token: &Token) -> Self {
+        Diagnostic::error_at(message, token.range())
+    }
+
+    pub fn error_at(message: String, range: PosRange) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message,
+            start_pos: range.0,
+            end_pos: range.1,
+        }
+    }
+
+    pub fn warning(message: String, token: &Token) -> Self {
+        Diagnostic::warning_at(message, token.range())
+    }
+
+    pub fn warning_at(message: String, range: PosRange) -> Self {
+        Diagnostic {
+            severity: Severity::Warning,
+            ..Diagnostic::error_at(message, range)
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn range(&self) -> PosRange {
+        PosRange(self.start_pos, self.end_pos)
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at {}",
+            self.message,
+            PosRange(self.start_pos, self.end_pos),
+        )
+    }
+}
@@ -0,0 +1,878 @@
+use crate::interpreter::value::{Class, Environment, Function, Initializer, Instance, Value};
+use crate::parser::expr::*;
+use crate::parser::operators::compound_operator;
+use crate::parser::stmt::*;
+use crate::parser::types::TypeName;
+use crate::scanner::pos::Pos;
+use crate::scanner::token::{Literal, Token, TokenType};
+use crate::symbol::Symbol;
+use crate::vm::natives::{NativeFunction, NATIVES};
+use crate::vm::{RuntimeError, FRAMES_MAX};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod operators;
+pub mod value;
+
+/// How a statement finished.
+enum Flow<'a> {
+    Normal,
+    Break,
+    Continue,
+    Return(Value<'a>),
+}
+
+/// Active function call.
+struct Frame {
+    name: String,
+    /// Position of the call the function is currently making.
+    pos: Pos,
+}
+
+/// Tree-walking interpreter over the syntax tree. It is the executable
+/// specification of the language: it implements the same semantics as the
+/// bytecode VM in the most direct way, so running a program on both and
+/// comparing the results catches compiler bugs.
+///
+/// Variables are looked up by name in a chain of environments, closures
+/// keep the environment they were created in.
+pub struct Interpreter<'a> {
+    globals: Rc<Environment<'a>>,
+    frames: Vec<Frame>,
+    /// Where `print` writes to.
+    output: Box<dyn Write>,
+}
+
+type Result<T> = std::result::Result<T, RuntimeError>;
+
+impl<'a> Interpreter<'a> {
+    pub fn new(output: Box<dyn Write>) -> Self {
+        let globals = Rc::new(Environment::default());
+        for native in NATIVES {
+            globals.define(Symbol::from(native.name), Value::Native(native));
+        }
+        Interpreter {
+            globals,
+            frames: Vec::new(),
+            output,
+        }
+    }
+
+    /// Runs the top level code of a program. Top level functions and
+    /// classes are defined before any other statement runs.
+    pub fn run(&mut self, program: &'a Statement) -> Result<()> {
+        self.frames.push(Frame {
+            name: String::from("<script>"),
+            pos: Pos::initial(),
+        });
+        let globals = self.globals.clone();
+        match program {
+            Statement::Program(stmt) => {
+                for declaration in stmt.execution_order() {
+                    self.statement(declaration, &globals)?;
+                }
+            }
+            _ => {
+                self.statement(program, &globals)?;
+            }
+        }
+        self.frames.pop();
+        Ok(())
+    }
+
+    /// Calls the program's `main` function like `Vm::call_main` does.
+    pub fn call_main(&mut self, args: &[String]) -> Result<Value<'a>> {
+        let main = match self.globals.get(Symbol::from("main")) {
+            Some(main) => main,
+            None => return Ok(Value::Null),
+        };
+        let takes_args =
+            matches!(&main, Value::Function(function) if !function.parameters.is_empty());
+        let arguments = if takes_args {
+            vec![Value::String(Rc::from(args.join(" ")))]
+        } else {
+            Vec::new()
+        };
+        self.call_value(main, arguments, Pos::initial())
+    }
+
+    /// Error raised at `pos` of the innermost call.
+    fn error(&mut self, message: String, pos: Pos) -> RuntimeError {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.pos = pos;
+        }
+        let trace = self
+            .frames
+            .iter()
+            .rev()
+            .map(|frame| (frame.name.clone(), frame.pos))
+            .collect();
+        self.frames.clear();
+        RuntimeError { message, trace }
+    }
+
+    fn statements(
+        &mut self,
+        statements: &'a [Rc<Statement>],
+        env: &Rc<Environment<'a>>,
+    ) -> Result<Flow<'a>> {
+        for statement in statements {
+            match self.statement(statement, env)? {
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn statement(
+        &mut self,
+        statement: &'a Statement,
+        env: &Rc<Environment<'a>>,
+    ) -> Result<Flow<'a>> {
+        match statement {
+            Statement::Block(stmt) => self.statements(&stmt.declarations, &Environment::new(env)),
+            Statement::Break(_) => Ok(Flow::Break),
+            Statement::Class(stmt) => {
+                let class = self.class(stmt, env)?;
+                env.define(stmt.name.lexme, Value::Class(Rc::new(class)));
+                Ok(Flow::Normal)
+            }
+            Statement::Continue(_) => Ok(Flow::Continue),
+            Statement::Expression(stmt) => {
+                self.expression(&stmt.expression, env)?;
+                Ok(Flow::Normal)
+            }
+            Statement::For(stmt) => {
+                let env = Environment::new(env);
+                if let Some(initializer) = &stmt.initializer {
+                    self.statement(initializer, &env)?;
+                }
+                loop {
+                    if let Some(condition) = &stmt.condition {
+                        if self.expression(condition, &env)?.is_falsey() {
+                            break;
+                        }
+                    }
+                    match self.statement(&stmt.body, &env)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal | Flow::Continue => {}
+                    }
+                    if let Some(update) = &stmt.update {
+                        self.expression(update, &env)?;
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Statement::Function(stmt) => {
+                let function = self.function(&stmt.name.lexme, &stmt.parameters, &stmt.body, env);
+                env.define(stmt.name.lexme, function);
+                Ok(Flow::Normal)
+            }
+            Statement::If(stmt) => {
+                if !self.expression(&stmt.condition, env)?.is_falsey() {
+                    self.statement(&stmt.then_branch, env)
+                } else if let Some(else_branch) = &stmt.else_branch {
+                    self.statement(else_branch, env)
+                } else {
+                    Ok(Flow::Normal)
+                }
+            }
+            Statement::Program(stmt) => self.statements(&stmt.declarations, env),
+            Statement::Return(stmt) => {
+                let value = match &stmt.value {
+                    Some(value) => self.expression(value, env)?,
+                    None => Value::Null,
+                };
+                Ok(Flow::Return(value))
+            }
+            Statement::Var(stmt) => {
+                let value = match &stmt.value {
+                    Some(value) => self.expression(value, env)?,
+                    None => Value::Null,
+                };
+                env.define(stmt.name.lexme, value);
+                Ok(Flow::Normal)
+            }
+            Statement::While(stmt) => {
+                while !self.expression(&stmt.condition, env)?.is_falsey() {
+                    match self.statement(&stmt.body, env)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal | Flow::Continue => {}
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+        }
+    }
+
+    /// Creates a class, copying down the members of its superclass. Methods
+    /// of a subclass see the superclass as `super`.
+    fn class(&mut self, stmt: &'a ClassStatement, env: &Rc<Environment<'a>>) -> Result<Class<'a>> {
+        let mut class = Class {
+            name: stmt.name.lexme.to_string(),
+            fields: Vec::new(),
+            methods: HashMap::new(),
+            constructor: None,
+        };
+        let mut env = env.clone();
+        if let Some(extends) = &stmt.extends {
+            let name = match &extends.type_name {
+                TypeName::Identifier { name } => name,
+                _ => return Ok(class),
+            };
+            let superclass = match env.get(name.lexme) {
+                Some(Value::Class(superclass)) => superclass,
+                Some(value) => {
+                    let message = format!(
+                        "Cannot inherit from a value of type '{}'",
+                        value.type_name()
+                    );
+                    return Err(self.error(message, stmt.name.start_pos));
+                }
+                None => return Err(self.undefined(name)),
+            };
+            class.fields = superclass.fields.clone();
+            class.methods = superclass.methods.clone();
+            class.constructor = superclass.constructor.clone();
+            env = Environment::new(&env);
+            env.define(Symbol::from("super"), Value::Class(superclass));
+        }
+
+        for member in &stmt.members {
+            match member {
+                ClassMember::Field { name, value, .. } => {
+                    let initializer = value.as_ref().map(|value| {
+                        Rc::new(Initializer {
+                            name: format!("{}.{}", stmt.name.lexme, name.lexme),
+                            value,
+                            closure: env.clone(),
+                        })
+                    });
+                    match class
+                        .fields
+                        .iter_mut()
+                        .find(|(field, _)| *field == name.lexme)
+                    {
+                        Some(field) => field.1 = initializer,
+                        None => class.fields.push((name.lexme, initializer)),
+                    }
+                }
+                ClassMember::Method {
+                    name,
+                    parameters,
+                    body,
+                    ..
+                } => {
+                    let function = Rc::new(Function {
+                        name: format!("{}.{}", stmt.name.lexme, name.lexme),
+                        parameters,
+                        body,
+                        closure: env.clone(),
+                    });
+                    class.methods.insert(name.lexme, function);
+                }
+                ClassMember::Constructor {
+                    parameters, body, ..
+                } => {
+                    class.constructor = Some(Rc::new(Function {
+                        name: stmt.name.lexme.to_string(),
+                        parameters,
+                        body,
+                        closure: env.clone(),
+                    }));
+                }
+            }
+        }
+        Ok(class)
+    }
+
+    fn function(
+        &self,
+        name: &str,
+        parameters: &'a [FunctionParameter],
+        body: &'a [Rc<Statement>],
+        env: &Rc<Environment<'a>>,
+    ) -> Value<'a> {
+        Value::Function(Rc::new(Function {
+            name: name.to_string(),
+            parameters,
+            body,
+            closure: env.clone(),
+        }))
+    }
+
+    fn call_value(
+        &mut self,
+        callee: Value<'a>,
+        arguments: Vec<Value<'a>>,
+        pos: Pos,
+    ) -> Result<Value<'a>> {
+        let (arity, function) = match &callee {
+            Value::Function(function) => (function.parameters.len(), Some(function.clone())),
+            Value::Native(native) => (native.parameters.len(), None),
+            Value::Class(class) => {
+                let arity = class
+                    .constructor
+                    .as_ref()
+                    .map_or(0, |constructor| constructor.parameters.len());
+                if arity != arguments.len() {
+                    let message =
+                        format!("Expected {} argument(s) but got {}", arity, arguments.len());
+                    return Err(self.error(message, pos));
+                }
+                return self.instantiate(class.clone(), arguments, pos);
+            }
+            _ => {
+                let message = format!("Value of type '{}' is not callable", callee.type_name());
+                return Err(self.error(message, pos));
+            }
+        };
+        if arity != arguments.len() {
+            let message = format!("Expected {} argument(s) but got {}", arity, arguments.len());
+            return Err(self.error(message, pos));
+        }
+
+        let function = match (function, &callee) {
+            (Some(function), _) => function,
+            (None, Value::Native(native)) => {
+                return self
+                    .native(native, &arguments)
+                    .map_err(|message| self.error(message, pos));
+            }
+            _ => unreachable!(),
+        };
+        if self.frames.len() == FRAMES_MAX {
+            return Err(self.error(String::from("Stack overflow"), pos));
+        }
+
+        if let Some(frame) = self.frames.last_mut() {
+            frame.pos = pos;
+        }
+        self.frames.push(Frame {
+            name: function.name.clone(),
+            pos,
+        });
+        let env = Environment::new(&function.closure);
+        for (parameter, argument) in function.parameters.iter().zip(arguments) {
+            env.define(parameter.name.lexme, argument);
+        }
+        let result = match self.statements(function.body, &env)? {
+            Flow::Return(value) => value,
+            _ => Value::Null,
+        };
+        self.frames.pop();
+        Ok(result)
+    }
+
+    /// Creates an instance of `class`: field initializers run first, from
+    /// the root of the class hierarchy down, then the constructor receives
+    /// the arguments.
+    fn instantiate(
+        &mut self,
+        class: Rc<Class<'a>>,
+        arguments: Vec<Value<'a>>,
+        pos: Pos,
+    ) -> Result<Value<'a>> {
+        let fields = class
+            .fields
+            .iter()
+            .map(|(name, _)| (*name, Value::Null))
+            .collect();
+        let instance = Value::Instance(Rc::new(Instance {
+            class: class.clone(),
+            fields: RefCell::new(fields),
+        }));
+
+        for (name, initializer) in &class.fields {
+            if let Some(initializer) = initializer {
+                let value = self.initialize(initializer, &instance, pos)?;
+                operators::set_property(&instance, *name, value)
+                    .map_err(|message| self.error(message, pos))?;
+            }
+        }
+        if let Some(constructor) = &class.constructor {
+            let constructor = Value::Function(constructor.bind(instance.clone()));
+            self.call_value(constructor, arguments, pos)?;
+        }
+        Ok(instance)
+    }
+
+    /// Evaluates a field initializer in its own frame, like a method of the
+    /// instance taking no arguments.
+    fn initialize(
+        &mut self,
+        initializer: &Initializer<'a>,
+        instance: &Value<'a>,
+        pos: Pos,
+    ) -> Result<Value<'a>> {
+        if self.frames.len() == FRAMES_MAX {
+            return Err(self.error(String::from("Stack overflow"), pos));
+        }
+        if let Some(frame) = self.frames.last_mut() {
+            frame.pos = pos;
+        }
+        self.frames.push(Frame {
+            name: initializer.name.clone(),
+            pos,
+        });
+        let env = Environment::new(&initializer.closure);
+        env.define(Symbol::from("this"), instance.clone());
+        let value = self.expression(initializer.value, &env)?;
+        self.frames.pop();
+        Ok(value)
+    }
+
+    fn native(
+        &mut self,
+        native: &NativeFunction,
+        arguments: &[Value<'a>],
+    ) -> std::result::Result<Value<'a>, String> {
+        match native.name {
+            "print" => {
+                writeln!(self.output, "{}", arguments[0]).map_err(|e| e.to_string())?;
+                Ok(Value::Null)
+            }
+            "clock" => {
+                let elapsed = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(|e| e.to_string())?;
+                Ok(Value::Float(elapsed.as_secs_f64()))
+            }
+            name => Err(format!("Native '{}' is not available", name)),
+        }
+    }
+
+    fn expression(
+        &mut self,
+        expression: &'a Expression,
+        env: &Rc<Environment<'a>>,
+    ) -> Result<Value<'a>> {
+        match expression {
+            Expression::Array(expr) => {
+                let elements = self.elements(&expr.elements, env)?;
+                Ok(Value::Array(Rc::new(RefCell::new(elements))))
+            }
+            Expression::Assignment(expr) => self.assignment(expr, env),
+            Expression::Binary(expr) => {
+                let left = self.expression(&expr.left, env)?;
+                let right = self.expression(&expr.right, env)?;
+                operators::binary(&expr.operator, left, right)
+                    .map_err(|message| self.error(message, expr.operator.start_pos))
+            }
+            Expression::Call(_) | Expression::Index(_) | Expression::Member(_) => {
+                Ok(self.chain(expression, env)?.unwrap_or(Value::Null))
+            }
+            Expression::Conditional(expr) => {
+                if !self.expression(&expr.condition, env)?.is_falsey() {
+                    self.expression(&expr.then_branch, env)
+                } else {
+                    self.expression(&expr.else_branch, env)
+                }
+            }
+            Expression::Grouping(expr) => self.expression(&expr.expression, env),
+            Expression::Identifier(expr) => match env.get(expr.name.lexme) {
+                Some(value) => Ok(value),
+                None => Err(self.undefined(&expr.name)),
+            },
+            Expression::LambdaFunction(expr) => {
+                Ok(self.function("<lambda>", &expr.parameters, &expr.body, env))
+            }
+            Expression::Literal(expr) => Ok(match &expr.value {
+                Literal::Null => Value::Null,
+                Literal::Boolean(value) => Value::Bool(*value),
+                Literal::Integer(value) => Value::Int(*value),
+                Literal::Float(value) => Value::Float(*value),
+                Literal::String(value) => Value::String(Rc::from(value.as_str())),
+            }),
+            Expression::Logical(expr) => {
+                let left = self.expression(&expr.left, env)?;
+                let short_circuits = match expr.operator.token_type {
+                    TokenType::And => left.is_falsey(),
+                    TokenType::Or => !left.is_falsey(),
+                    _ => !matches!(left, Value::Null),
+                };
+                if short_circuits {
+                    Ok(left)
+                } else {
+                    self.expression(&expr.right, env)
+                }
+            }
+            Expression::Map(expr) => {
+                let mut entries = Vec::new();
+                for entry in &expr.entries {
+                    match entry {
+                        MapEntry::Pair { key, value } => {
+                            let key = self.expression(key, env)?;
+                            let value = self.expression(value, env)?;
+                            operators::map_insert(&mut entries, key, value);
+                        }
+                        MapEntry::Spread { expression } => {
+                            let source = self.expression(expression, env)?;
+                            let spread = operators::spread_entries(&source)
+                                .map_err(|message| self.error(message, expression.start_pos()))?;
+                            for (key, value) in spread {
+                                operators::map_insert(&mut entries, key, value);
+                            }
+                        }
+                    }
+                }
+                Ok(Value::Map(Rc::new(RefCell::new(entries))))
+            }
+            Expression::OptionalChain(expr) => {
+                Ok(self.chain(&expr.expression, env)?.unwrap_or(Value::Null))
+            }
+            Expression::Spread(expr) => self.expression(&expr.expression, env),
+            // The resolver only lets `super` appear before a member or a
+            // call, those are handled by `chain` and `call`
+            Expression::Super(expr) => Err(self.undefined(&expr.keyword)),
+            Expression::This(expr) => match env.get(Symbol::from("this")) {
+                Some(this) => Ok(this),
+                None => Err(self.undefined(&expr.keyword)),
+            },
+            Expression::Tuple(expr) => {
+                let elements = self.elements(&expr.elements, env)?;
+                Ok(Value::Tuple(Rc::new(elements)))
+            }
+            Expression::Unary(expr) => {
+                let value = self.expression(&expr.right, env)?;
+                operators::unary(&expr.operator, value)
+                    .map_err(|message| self.error(message, expr.operator.start_pos))
+            }
+            Expression::Update(expr) => self.update(expr, env),
+        }
+    }
+
+    fn undefined(&mut self, name: &Token) -> RuntimeError {
+        self.error(
+            format!("Undefined variable '{}'", name.lexme),
+            name.start_pos,
+        )
+    }
+
+    /// Superclass of the class whose code is running.
+    fn superclass(&mut self, keyword: &Token, env: &Rc<Environment<'a>>) -> Result<Rc<Class<'a>>> {
+        match env.get(Symbol::from("super")) {
+            Some(Value::Class(superclass)) => Ok(superclass),
+            _ => Err(self.error(
+                String::from("Can't use 'super' in a class without a superclass"),
+                keyword.start_pos,
+            )),
+        }
+    }
+
+    /// `super.name`, the superclass method bound to `this`.
+    fn super_method(
+        &mut self,
+        keyword: &Token,
+        name: &Token,
+        env: &Rc<Environment<'a>>,
+    ) -> Result<Value<'a>> {
+        let superclass = self.superclass(keyword, env)?;
+        let this = env.get(Symbol::from("this")).unwrap_or(Value::Null);
+        match superclass.methods.get(&name.lexme) {
+            Some(method) => Ok(Value::Function(method.bind(this))),
+            None => {
+                let message = format!(
+                    "Superclass '{}' has no method '{}'",
+                    superclass.name, name.lexme
+                );
+                Err(self.error(message, name.start_pos))
+            }
+        }
+    }
+
+    /// Evaluates `elements`, expanding spreads.
+    fn elements(
+        &mut self,
+        elements: &'a [Rc<Expression>],
+        env: &Rc<Environment<'a>>,
+    ) -> Result<Vec<Value<'a>>> {
+        let mut values = Vec::new();
+        for element in elements {
+            match element.as_ref() {
+                Expression::Spread(spread) => {
+                    let source = self.expression(&spread.expression, env)?;
+                    let elements = operators::spread_elements(&source)
+                        .map_err(|message| self.error(message, spread.operator.start_pos))?;
+                    values.extend(elements);
+                }
+                _ => values.push(self.expression(element, env)?),
+            }
+        }
+        Ok(values)
+    }
+
+    /// Evaluates a link of a member/call/index chain. `None` when an
+    /// optional link met null, which makes the whole chain null.
+    fn chain(
+        &mut self,
+        expression: &'a Expression,
+        env: &Rc<Environment<'a>>,
+    ) -> Result<Option<Value<'a>>> {
+        match expression {
+            Expression::Member(expr) => {
+                if let Expression::Super(object) = expr.object.as_ref() {
+                    return self
+                        .super_method(&object.keyword, &expr.name, env)
+                        .map(Some);
+                }
+                let object = match self.link_object(&expr.object, expr.optional, env)? {
+                    Some(object) => object,
+                    None => return Ok(None),
+                };
+                operators::property(&object, expr.name.lexme)
+                    .map(Some)
+                    .map_err(|message| self.error(message, expr.name.start_pos))
+            }
+            Expression::Index(expr) => {
+                let object = match self.link_object(&expr.object, expr.optional, env)? {
+                    Some(object) => object,
+                    None => return Ok(None),
+                };
+                let index = self.expression(&expr.index, env)?;
+                operators::get_index(&object, &index)
+                    .map(Some)
+                    .map_err(|message| self.error(message, expr.paren.start_pos))
+            }
+            Expression::Call(expr) => self.call(expr, env),
+            _ => self.expression(expression, env).map(Some),
+        }
+    }
+
+    /// Evaluates the object of a chain link, `None` when the chain stops.
+    fn link_object(
+        &mut self,
+        object: &'a Expression,
+        optional: bool,
+        env: &Rc<Environment<'a>>,
+    ) -> Result<Option<Value<'a>>> {
+        match self.chain(object, env)? {
+            Some(Value::Null) if optional => Ok(None),
+            object => Ok(object),
+        }
+    }
+
+    fn call(
+        &mut self,
+        expr: &'a CallExpression,
+        env: &Rc<Environment<'a>>,
+    ) -> Result<Option<Value<'a>>> {
+        let spread = expr
+            .arguments
+            .iter()
+            .any(|argument| matches!(argument.as_ref(), Expression::Spread(_)));
+
+        // `super(...)` runs the superclass constructor on `this`
+        if let Expression::Super(callee) = expr.callee.as_ref() {
+            let superclass = self.superclass(&callee.keyword, env)?;
+            let this = env.get(Symbol::from("this")).unwrap_or(Value::Null);
+            let arguments = self.elements(&expr.arguments, env)?;
+            let pos = expr.paren.start_pos;
+            match &superclass.constructor {
+                Some(constructor) => {
+                    let constructor = Value::Function(constructor.bind(this.clone()));
+                    self.call_value(constructor, arguments, pos)?;
+                }
+                None if !arguments.is_empty() => {
+                    let message = format!("Expected 0 argument(s) but got {}", arguments.len());
+                    return Err(self.error(message, pos));
+                }
+                None => {}
+            }
+            return Ok(Some(this));
+        }
+
+        // Method calls look the method up once the arguments are evaluated
+        if let (Expression::Member(member), false, false) =
+            (expr.callee.as_ref(), spread, expr.optional)
+        {
+            if let Expression::Super(object) = member.object.as_ref() {
+                let arguments = self.elements(&expr.arguments, env)?;
+                let callee = self.super_method(&object.keyword, &member.name, env)?;
+                return self
+                    .call_value(callee, arguments, expr.paren.start_pos)
+                    .map(Some);
+            }
+            let object = match self.link_object(&member.object, member.optional, env)? {
+                Some(object) => object,
+                None => return Ok(None),
+            };
+            let arguments = self.elements(&expr.arguments, env)?;
+            let callee = operators::property(&object, member.name.lexme)
+                .map_err(|message| self.error(message, expr.paren.start_pos))?;
+            return self
+                .call_value(callee, arguments, expr.paren.start_pos)
+                .map(Some);
+        }
+
+        let callee = match self.link_object(&expr.callee, expr.optional, env)? {
+            Some(callee) => callee,
+            None => return Ok(None),
+        };
+        let arguments = self.elements(&expr.arguments, env)?;
+        self.call_value(callee, arguments, expr.paren.start_pos)
+            .map(Some)
+    }
+
+    /// Object of an assignment target, `this` for `super.x`.
+    fn member_object(
+        &mut self,
+        target: &'a MemberExpression,
+        env: &Rc<Environment<'a>>,
+    ) -> Result<Value<'a>> {
+        match target.object.as_ref() {
+            Expression::Super(_) => Ok(env.get(Symbol::from("this")).unwrap_or(Value::Null)),
+            object => self.expression(object, env),
+        }
+    }
+
+    fn assignment(
+        &mut self,
+        expr: &'a AssignmentExpression,
+        env: &Rc<Environment<'a>>,
+    ) -> Result<Value<'a>> {
+        let operator = compound_operator(expr.operator.token_type);
+        let coalesce = operator == Some(TokenType::QuestionQuestion);
+        // Compound assignments reuse the binary operator of their token
+        let binary = operator.filter(|_| !coalesce).map(|token_type| Token {
+            token_type,
+            lexme: Symbol::intern(expr.operator.lexme.trim_end_matches('=')),
+            ..expr.operator.clone()
+        });
+        let pos = expr.operator.start_pos;
+
+        match expr.left.as_ref() {
+            Expression::Identifier(target) => {
+                let name = target.name.lexme;
+                let value = if coalesce || binary.is_some() {
+                    let current = match env.get(name) {
+                        Some(current) => current,
+                        None => return Err(self.undefined(&target.name)),
+                    };
+                    if coalesce && !matches!(current, Value::Null) {
+                        return Ok(current);
+                    }
+                    let right = self.expression(&expr.right, env)?;
+                    match &binary {
+                        Some(binary) => operators::binary(binary, current, right)
+                            .map_err(|message| self.error(message, pos))?,
+                        None => right,
+                    }
+                } else {
+                    self.expression(&expr.right, env)?
+                };
+                if !env.assign(name, value.clone()) {
+                    return Err(self.undefined(&target.name));
+                }
+                Ok(value)
+            }
+            Expression::Member(target) => {
+                let object = self.member_object(target, env)?;
+                let value = if coalesce || binary.is_some() {
+                    let current = operators::property(&object, target.name.lexme)
+                        .map_err(|message| self.error(message, pos))?;
+                    if coalesce && !matches!(current, Value::Null) {
+                        return Ok(current);
+                    }
+                    let right = self.expression(&expr.right, env)?;
+                    match &binary {
+                        Some(binary) => operators::binary(binary, current, right)
+                            .map_err(|message| self.error(message, pos))?,
+                        None => right,
+                    }
+                } else {
+                    self.expression(&expr.right, env)?
+                };
+                operators::set_property(&object, target.name.lexme, value.clone())
+                    .map_err(|message| self.error(message, pos))?;
+                Ok(value)
+            }
+            Expression::Index(target) => {
+                let object = self.expression(&target.object, env)?;
+                let index = self.expression(&target.index, env)?;
+                let value = if coalesce || binary.is_some() {
+                    let current = operators::get_index(&object, &index)
+                        .map_err(|message| self.error(message, pos))?;
+                    if coalesce && !matches!(current, Value::Null) {
+                        return Ok(current);
+                    }
+                    let right = self.expression(&expr.right, env)?;
+                    match &binary {
+                        Some(binary) => operators::binary(binary, current, right)
+                            .map_err(|message| self.error(message, pos))?,
+                        None => right,
+                    }
+                } else {
+                    self.expression(&expr.right, env)?
+                };
+                operators::set_index(&object, index, value.clone())
+                    .map_err(|message| self.error(message, pos))?;
+                Ok(value)
+            }
+            // The parser rejects any other target
+            _ => Ok(Value::Null),
+        }
+    }
+
+    /// `++`/`--`, evaluating to the new value when prefix and to the old
+    /// one when postfix.
+    fn update(
+        &mut self,
+        expr: &'a UpdateExpression,
+        env: &Rc<Environment<'a>>,
+    ) -> Result<Value<'a>> {
+        let binary = Token {
+            token_type: if expr.operator.token_type == TokenType::PlusPlus {
+                TokenType::Plus
+            } else {
+                TokenType::Minus
+            },
+            lexme: Symbol::intern(&expr.operator.lexme[..1]),
+            ..expr.operator.clone()
+        };
+        let pos = expr.operator.start_pos;
+
+        let (current, updated) = match expr.expression.as_ref() {
+            Expression::Identifier(target) => {
+                let current = match env.get(target.name.lexme) {
+                    Some(current) => current,
+                    None => return Err(self.undefined(&target.name)),
+                };
+                let updated = operators::binary(&binary, current.clone(), Value::Int(1))
+                    .map_err(|message| self.error(message, pos))?;
+                env.assign(target.name.lexme, updated.clone());
+                (current, updated)
+            }
+            Expression::Member(target) => {
+                let object = self.member_object(target, env)?;
+                let current = operators::property(&object, target.name.lexme)
+                    .map_err(|message| self.error(message, pos))?;
+                let updated = operators::binary(&binary, current.clone(), Value::Int(1))
+                    .map_err(|message| self.error(message, pos))?;
+                operators::set_property(&object, target.name.lexme, updated.clone())
+                    .map_err(|message| self.error(message, pos))?;
+                (current, updated)
+            }
+            Expression::Index(target) => {
+                let object = self.expression(&target.object, env)?;
+                let index = self.expression(&target.index, env)?;
+                let current = operators::get_index(&object, &index)
+                    .map_err(|message| self.error(message, pos))?;
+                let updated = operators::binary(&binary, current.clone(), Value::Int(1))
+                    .map_err(|message| self.error(message, pos))?;
+                operators::set_index(&object, index, updated.clone())
+                    .map_err(|message| self.error(message, pos))?;
+                (current, updated)
+            }
+            // The parser rejects any other target
+            _ => return Ok(Value::Null),
+        };
+        Ok(if expr.prefix { updated } else { current })
+    }
+}
@@ -0,0 +1,258 @@
+use crate::interpreter::value::Value;
+use crate::scanner::token::{Token, TokenType};
+use crate::symbol::Symbol;
+use std::rc::Rc;
+
+/// Applies a comparison, arithmetic or bitwise operator with the semantics
+/// the VM implements: checked integer arithmetic, ints widened to floats
+/// when mixed, and `+` concatenating as soon as one side is a string.
+pub fn binary<'a>(operator: &Token, a: Value<'a>, b: Value<'a>) -> Result<Value<'a>, String> {
+    let kind = operator.token_type;
+    match kind {
+        TokenType::EqualEqual => return Ok(Value::Bool(a.equals(&b))),
+        TokenType::BangEqual => return Ok(Value::Bool(!a.equals(&b))),
+        TokenType::Plus if matches!(a, Value::String(_)) || matches!(b, Value::String(_)) => {
+            return Ok(Value::String(Rc::from(format!("{}{}", a, b))));
+        }
+        _ => {}
+    }
+
+    let result = match (&a, &b) {
+        (Value::Int(x), Value::Int(y)) => return integer(operator, *x, *y),
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            float(kind, as_float(&a), as_float(&b))
+        }
+        (Value::String(x), Value::String(y)) => compare(kind, x.as_ref(), y.as_ref()),
+        _ => None,
+    };
+    result.ok_or_else(|| {
+        format!(
+            "Operator '{}' cannot be applied to values of type '{}' and '{}'",
+            operator.lexme,
+            a.type_name(),
+            b.type_name()
+        )
+    })
+}
+
+pub fn unary<'a>(operator: &Token, value: Value<'a>) -> Result<Value<'a>, String> {
+    match (operator.token_type, &value) {
+        (TokenType::Bang, _) => Ok(Value::Bool(value.is_falsey())),
+        (TokenType::Minus, Value::Int(value)) => value
+            .checked_neg()
+            .map(Value::Int)
+            .ok_or_else(|| String::from("Integer overflow")),
+        (TokenType::Minus, Value::Float(value)) => Ok(Value::Float(-value)),
+        (TokenType::Tilde, Value::Int(value)) => Ok(Value::Int(!value)),
+        _ => Err(format!(
+            "Operator '{}' cannot be applied to a value of type '{}'",
+            operator.lexme,
+            value.type_name()
+        )),
+    }
+}
+
+fn as_float(value: &Value) -> f64 {
+    match value {
+        Value::Int(value) => *value as f64,
+        Value::Float(value) => *value,
+        _ => f64::NAN,
+    }
+}
+
+fn compare<'a, T: PartialOrd + ?Sized>(kind: TokenType, a: &T, b: &T) -> Option<Value<'a>> {
+    let result = match kind {
+        TokenType::Less => a < b,
+        TokenType::LessEqual => a <= b,
+        TokenType::Greater => a > b,
+        TokenType::GreaterEqual => a >= b,
+        _ => return None,
+    };
+    Some(Value::Bool(result))
+}
+
+fn integer<'a>(operator: &Token, a: i64, b: i64) -> Result<Value<'a>, String> {
+    let kind = operator.token_type;
+    if let Some(result) = compare(kind, &a, &b) {
+        return Ok(result);
+    }
+    let overflow = || String::from("Integer overflow");
+    let value = match kind {
+        TokenType::Plus => a.checked_add(b).ok_or_else(overflow)?,
+        TokenType::Minus => a.checked_sub(b).ok_or_else(overflow)?,
+        TokenType::Star => a.checked_mul(b).ok_or_else(overflow)?,
+        TokenType::Slash | TokenType::Percent if b == 0 => {
+            return Err(String::from("Division by zero"));
+        }
+        TokenType::Slash => a.checked_div(b).ok_or_else(overflow)?,
+        TokenType::Percent => a.checked_rem(b).ok_or_else(overflow)?,
+        TokenType::StarStar if b < 0 => return Err(String::from("Negative integer exponent")),
+        TokenType::StarStar => u32::try_from(b)
+            .ok()
+            .and_then(|b| a.checked_pow(b))
+            .ok_or_else(overflow)?,
+        TokenType::Ampersand => a & b,
+        TokenType::Pipe => a | b,
+        TokenType::Caret => a ^ b,
+        TokenType::LessLess | TokenType::GreaterGreater if !(0..64).contains(&b) => {
+            return Err(format!("Shift amount {} is out of range", b));
+        }
+        TokenType::LessLess => a << b,
+        TokenType::GreaterGreater => a >> b,
+        _ => {
+            return Err(format!(
+                "Operator '{}' cannot be applied to ints",
+                operator.lexme
+            ))
+        }
+    };
+    Ok(Value::Int(value))
+}
+
+fn float<'a>(kind: TokenType, a: f64, b: f64) -> Option<Value<'a>> {
+    if let Some(result) = compare(kind, &a, &b) {
+        return Some(result);
+    }
+    let value = match kind {
+        TokenType::Plus => a + b,
+        TokenType::Minus => a - b,
+        TokenType::Star => a * b,
+        TokenType::Slash => a / b,
+        TokenType::Percent => a % b,
+        TokenType::StarStar => a.powf(b),
+        _ => return None,
+    };
+    Some(Value::Float(value))
+}
+
+/// Field or bound method of an instance, or `.length` of strings and
+/// collections, the only properties they have.
+pub fn property<'a>(object: &Value<'a>, name: Symbol) -> Result<Value<'a>, String> {
+    if let Value::Instance(instance) = object {
+        if let Some(value) = instance.fields.borrow().get(&name) {
+            return Ok(value.clone());
+        }
+        if let Some(method) = instance.class.methods.get(&name) {
+            return Ok(Value::Function(method.bind(object.clone())));
+        }
+    }
+
+    let length = match (object, name.as_str()) {
+        (Value::String(string), "length") => Some(string.chars().count()),
+        (Value::Array(elements), "length") => Some(elements.borrow().len()),
+        (Value::Tuple(elements), "length") => Some(elements.len()),
+        (Value::Map(entries), "length") => Some(entries.borrow().len()),
+        _ => None,
+    };
+    match length {
+        Some(length) => Ok(Value::Int(length as i64)),
+        None => Err(format!(
+            "Value of type '{}' has no property '{}'",
+            object.type_name(),
+            name
+        )),
+    }
+}
+
+/// Assigns an existing field of an instance.
+pub fn set_property<'a>(object: &Value<'a>, name: Symbol, value: Value<'a>) -> Result<(), String> {
+    if let Value::Instance(instance) = object {
+        if let Some(field) = instance.fields.borrow_mut().get_mut(&name) {
+            *field = value;
+            return Ok(());
+        }
+    }
+    Err(format!(
+        "Cannot assign property '{}' of a value of type '{}'",
+        name,
+        object.type_name()
+    ))
+}
+
+fn element_index(index: &Value, length: usize) -> Result<usize, String> {
+    match index {
+        Value::Int(i) if *i >= 0 && (*i as usize) < length => Ok(*i as usize),
+        Value::Int(i) => Err(format!(
+            "Index {} is out of bounds for length {}",
+            i, length
+        )),
+        _ => Err(format!(
+            "Index must be an int but got '{}'",
+            index.type_name()
+        )),
+    }
+}
+
+pub fn get_index<'a>(object: &Value<'a>, index: &Value<'a>) -> Result<Value<'a>, String> {
+    match object {
+        Value::Array(elements) => {
+            let elements = elements.borrow();
+            Ok(elements[element_index(index, elements.len())?].clone())
+        }
+        Value::Tuple(elements) => Ok(elements[element_index(index, elements.len())?].clone()),
+        Value::Map(entries) => Ok(entries
+            .borrow()
+            .iter()
+            .find(|(key, _)| key.equals(index))
+            .map_or(Value::Null, |(_, value)| value.clone())),
+        Value::String(string) => {
+            let i = element_index(index, string.chars().count())?;
+            let character = string.chars().nth(i).unwrap().to_string();
+            Ok(Value::String(Rc::from(character)))
+        }
+        _ => Err(format!("Type '{}' cannot be indexed", object.type_name())),
+    }
+}
+
+pub fn set_index<'a>(object: &Value<'a>, index: Value<'a>, value: Value<'a>) -> Result<(), String> {
+    match object {
+        Value::Array(elements) => {
+            let mut elements = elements.borrow_mut();
+            let i = element_index(&index, elements.len())?;
+            elements[i] = value;
+            Ok(())
+        }
+        Value::Map(entries) => {
+            map_insert(&mut entries.borrow_mut(), index, value);
+            Ok(())
+        }
+        Value::Null | Value::Bool(_) | Value::Int(_) | Value::Float(_) => {
+            Err(format!("Type '{}' cannot be indexed", object.type_name()))
+        }
+        _ => Err(format!(
+            "Elements of type '{}' cannot be assigned",
+            object.type_name()
+        )),
+    }
+}
+
+pub fn map_insert<'a>(entries: &mut Vec<(Value<'a>, Value<'a>)>, key: Value<'a>, value: Value<'a>) {
+    match entries
+        .iter_mut()
+        .find(|(existing, _)| existing.equals(&key))
+    {
+        Some(entry) => entry.1 = value,
+        None => entries.push((key, value)),
+    }
+}
+
+pub fn spread_elements<'a>(value: &Value<'a>) -> Result<Vec<Value<'a>>, String> {
+    match value {
+        Value::Array(elements) => Ok(elements.borrow().clone()),
+        Value::Tuple(elements) => Ok(elements.as_ref().clone()),
+        _ => Err(format!(
+            "Cannot spread a value of type '{}'",
+            value.type_name()
+        )),
+    }
+}
+
+pub fn spread_entries<'a>(value: &Value<'a>) -> Result<Vec<(Value<'a>, Value<'a>)>, String> {
+    match value {
+        Value::Map(entries) => Ok(entries.borrow().clone()),
+        _ => Err(format!(
+            "Cannot spread a value of type '{}' into a map",
+            value.type_name()
+        )),
+    }
+}
@@ -0,0 +1,246 @@
+use crate::parser::expr::Expression;
+use crate::parser::stmt::{FunctionParameter, Statement};
+use crate::symbol::Symbol;
+use crate::vm::natives::NativeFunction;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+/// Value of the tree-walking interpreter. Borrows function bodies from the
+/// syntax tree being run.
+#[derive(Debug, Clone)]
+pub enum Value<'a> {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(Rc<str>),
+    Array(Rc<RefCell<Vec<Value<'a>>>>),
+    /// Entries in insertion order.
+    Map(Rc<RefCell<Vec<(Value<'a>, Value<'a>)>>>),
+    Tuple(Rc<Vec<Value<'a>>>),
+    Function(Rc<Function<'a>>),
+    Native(&'static NativeFunction),
+    Class(Rc<Class<'a>>),
+    Instance(Rc<Instance<'a>>),
+}
+
+#[derive(Debug)]
+pub struct Function<'a> {
+    pub name: String,
+    pub parameters: &'a [FunctionParameter],
+    pub body: &'a [Rc<Statement>],
+    pub closure: Rc<Environment<'a>>,
+}
+
+impl<'a> Function<'a> {
+    /// Method bound to `this`, its body sees the receiver as `this`.
+    pub fn bind(&self, this: Value<'a>) -> Rc<Function<'a>> {
+        let closure = Environment::new(&self.closure);
+        closure.define(Symbol::from("this"), this);
+        Rc::new(Function {
+            name: self.name.clone(),
+            parameters: self.parameters,
+            body: self.body,
+            closure,
+        })
+    }
+}
+
+/// Initializer expression of a field, evaluated with `this` bound to the
+/// new instance.
+#[derive(Debug)]
+pub struct Initializer<'a> {
+    /// Name of the function the initializer runs as in stack traces.
+    pub name: String,
+    pub value: &'a Expression,
+    pub closure: Rc<Environment<'a>>,
+}
+
+/// Class with its inherited members copied down, like the VM does.
+#[derive(Debug)]
+pub struct Class<'a> {
+    pub name: String,
+    /// Fields in initialization order, inherited ones first.
+    pub fields: Vec<(Symbol, Option<Rc<Initializer<'a>>>)>,
+    pub methods: HashMap<Symbol, Rc<Function<'a>>>,
+    pub constructor: Option<Rc<Function<'a>>>,
+}
+
+#[derive(Debug)]
+pub struct Instance<'a> {
+    pub class: Rc<Class<'a>>,
+    pub fields: RefCell<HashMap<Symbol, Value<'a>>>,
+}
+
+/// Variables of a block, chained to the enclosing blocks.
+#[derive(Debug, Default)]
+pub struct Environment<'a> {
+    values: RefCell<HashMap<Symbol, Value<'a>>>,
+    enclosing: Option<Rc<Environment<'a>>>,
+}
+
+impl<'a> Environment<'a> {
+    pub fn new(enclosing: &Rc<Environment<'a>>) -> Rc<Self> {
+        Rc::new(Environment {
+            values: RefCell::new(HashMap::new()),
+            enclosing: Some(enclosing.clone()),
+        })
+    }
+
+    pub fn define(&self, name: Symbol, value: Value<'a>) {
+        self.values.borrow_mut().insert(name, value);
+    }
+
+    pub fn get(&self, name: Symbol) -> Option<Value<'a>> {
+        match self.values.borrow().get(&name) {
+            Some(value) => Some(value.clone()),
+            None => self.enclosing.as_ref()?.get(name),
+        }
+    }
+
+    /// Assigns an existing variable, returns `false` if there is none.
+    pub fn assign(&self, name: Symbol, value: Value<'a>) -> bool {
+        if let Some(variable) = self.values.borrow_mut().get_mut(&name) {
+            *variable = value;
+            return true;
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.assign(name, value),
+            None => false,
+        }
+    }
+}
+
+impl<'a> Value<'a> {
+    pub fn is_falsey(&self) -> bool {
+        matches!(self, Value::Null | Value::Bool(false))
+    }
+
+    /// Name of the value's type in error messages, instances go by the
+    /// name of their class.
+    pub fn type_name(&self) -> &str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            Value::Map(_) => "map",
+            Value::Tuple(_) => "tuple",
+            Value::Function(_) | Value::Native(_) => "function",
+            Value::Class(_) => "class",
+            Value::Instance(instance) => &instance.class.name,
+        }
+    }
+
+    /// Strings and numbers compare by value, other objects by identity.
+    pub fn equals(&self, other: &Value<'a>) -> bool {
+        match (self, other) {
+            (Value::Null, Value::Null) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::Int(a), Value::Float(b)) | (Value::Float(b), Value::Int(a)) => *a as f64 == *b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Array(a), Value::Array(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            (Value::Tuple(a), Value::Tuple(b)) => Rc::ptr_eq(a, b),
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Native(a), Value::Native(b)) => a.name == b.name,
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// Address identifying a collection, to detect cycles when displaying.
+    fn address(&self) -> Option<*const ()> {
+        match self {
+            Value::Array(elements) => Some(Rc::as_ptr(elements) as *const ()),
+            Value::Map(entries) => Some(Rc::as_ptr(entries) as *const ()),
+            Value::Tuple(elements) => Some(Rc::as_ptr(elements) as *const ()),
+            _ => None,
+        }
+    }
+
+    /// Writes the value the way the VM does. Strings nested in collections
+    /// are quoted, `enclosing` holds the collections being written.
+    fn write(
+        &self,
+        f: &mut Formatter<'_>,
+        quoted: bool,
+        enclosing: &mut Vec<*const ()>,
+    ) -> std::fmt::Result {
+        let address = self.address();
+        if let Some(address) = address {
+            if enclosing.contains(&address) {
+                return match self {
+                    Value::Array(_) => write!(f, "[...]"),
+                    Value::Map(_) => write!(f, "{{...}}"),
+                    _ => write!(f, "(...)"),
+                };
+            }
+            enclosing.push(address);
+        }
+
+        match self {
+            Value::Null => write!(f, "null")?,
+            Value::Bool(value) => write!(f, "{}", value)?,
+            Value::Int(value) => write!(f, "{}", value)?,
+            Value::Float(value) => write!(f, "{:?}", value)?,
+            Value::String(string) if quoted => write!(f, "{:?}", string)?,
+            Value::String(string) => write!(f, "{}", string)?,
+            Value::Function(function) => write!(f, "<fun {}>", function.name)?,
+            Value::Native(native) => write!(f, "<native {}>", native.name)?,
+            Value::Class(class) => write!(f, "<class {}>", class.name)?,
+            Value::Instance(instance) => write!(f, "<{} instance>", instance.class.name)?,
+            Value::Array(elements) => {
+                write_elements(f, "[", "]", &elements.borrow(), enclosing)?;
+            }
+            Value::Tuple(elements) => write_elements(f, "(", ")", elements, enclosing)?,
+            Value::Map(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    key.write(f, true, enclosing)?;
+                    write!(f, ": ")?;
+                    value.write(f, true, enclosing)?;
+                }
+                write!(f, "}}")?;
+            }
+        }
+
+        if address.is_some() {
+            enclosing.pop();
+        }
+        Ok(())
+    }
+}
+
+fn write_elements(
+    f: &mut Formatter<'_>,
+    open: &str,
+    close: &str,
+    elements: &[Value],
+    enclosing: &mut Vec<*const ()>,
+) -> std::fmt::Result {
+    write!(f, "{}", open)?;
+    for (i, element) in elements.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        element.write(f, true, enclosing)?;
+    }
+    write!(f, "{}", close)
+}
+
+impl Display for Value<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.write(f, false, &mut Vec::new())
+    }
+}
@@ -1,30 +1,419 @@
+use crate::analysis::captures::CaptureAnalyzer;
+use crate::analysis::flow::FlowAnalyzer;
+use crate::analysis::lints::Linter;
+use crate::bytecode::chunk::Function;
+use crate::bytecode::compiler::Compiler;
+use crate::bytecode::disasm::{disassemble, list_classes};
+use crate::bytecode::peb;
+use crate::bytecode::verifier;
+use crate::checker::Checker;
+use crate::compare::compare_backends;
+use crate::diagnostic::Diagnostic;
+use crate::interpreter::Interpreter;
+use crate::parser::sexpr::SExpr;
+use crate::parser::stmt::Statement;
 use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::pos::PosRange;
 use crate::scanner::{token::TokenType, Scanner};
+use crate::vm::gc::GcConfig;
+use crate::vm::value::Value;
+use crate::vm::{RuntimeError, Vm};
 
-use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use std::{env, fs, io, process, thread};
 
+mod analysis;
+mod bytecode;
+mod checker;
+mod compare;
+mod diagnostic;
+mod interpreter;
 mod parser;
+mod resolver;
 mod scanner;
+mod symbol;
+mod vm;
 
-fn main() {
-    let source = fs::read_to_string("script.txt").unwrap();
-    let mut scanner = Scanner::new(source.clone());
+const USAGE: &str = "usage: compiler parse [--format debug|json|sexpr] [file]
+       compiler check [--deny-warnings] [file]
+       compiler run [--deny-warnings] [--backend vm|ast] [gc options] [file|file.peb] [args...]
+       compiler build [--deny-warnings] [-o file.peb] [file]
+       compiler compare [gc options] [file|directory...]
+       compiler disasm [file|file.peb]
+gc options: [--gc-stress] [--gc-growth factor] [--gc-stats]";
+
+enum Command {
+    Parse,
+    Check,
+    Run,
+    /// Compiles a program into a bytecode file.
+    Build,
+    /// Runs programs on both backends and compares the results.
+    Compare,
+    /// Prints the bytecode a program compiles to.
+    Disasm,
+}
+
+enum OutputFormat {
+    Debug,
+    Json,
+    SExpr,
+}
+
+enum Backend {
+    Vm,
+    /// The tree-walking interpreter.
+    Ast,
+}
+
+impl Backend {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "vm" => Some(Backend::Vm),
+            "ast" => Some(Backend::Ast),
+            _ => None,
+        }
+    }
+}
+
+impl OutputFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "debug" => Some(OutputFormat::Debug),
+            "json" => Some(OutputFormat::Json),
+            "sexpr" => Some(OutputFormat::SExpr),
+            _ => None,
+        }
+    }
+}
+
+struct Options {
+    command: Command,
+    format: OutputFormat,
+    backend: Backend,
+    /// Makes `check` fail on warnings too.
+    deny_warnings: bool,
+    gc: GcConfig,
+    /// Prints garbage collector statistics after running on the VM.
+    gc_stats: bool,
+    path: String,
+    /// Bytecode file `build` writes, next to the source by default.
+    output: Option<String>,
+    /// Arguments passed on to the program's `main` by `run`, or the
+    /// programs to `compare` after the first one.
+    args: Vec<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut args = args.iter();
+    let mut options = Options {
+        command: Command::Parse,
+        format: OutputFormat::Debug,
+        backend: Backend::Vm,
+        deny_warnings: false,
+        gc: GcConfig::default(),
+        gc_stats: false,
+        path: String::from("script.txt"),
+        output: None,
+        args: Vec::new(),
+    };
+    let mut path = None;
+
+    match args.next().map(String::as_str) {
+        None | Some("parse") => {}
+        Some("check") => options.command = Command::Check,
+        Some("run") => options.command = Command::Run,
+        Some("build") => options.command = Command::Build,
+        Some("compare") => options.command = Command::Compare,
+        Some("disasm") => options.command = Command::Disasm,
+        Some(command) => return Err(format!("unknown command '{}'", command)),
+    }
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                let name = args.next().ok_or("expected a value after '--format'")?;
+                options.format = OutputFormat::from_name(name)
+                    .ok_or_else(|| format!("unknown format '{}'", name))?;
+            }
+            "--backend" => {
+                let name = args.next().ok_or("expected a value after '--backend'")?;
+                options.backend = Backend::from_name(name)
+                    .ok_or_else(|| format!("unknown backend '{}'", name))?;
+            }
+            "--deny-warnings" => options.deny_warnings = true,
+            "--gc-stress" => options.gc.stress = true,
+            "--gc-growth" => {
+                let factor = args.next().ok_or("expected a value after '--gc-growth'")?;
+                options.gc.growth_factor = factor
+                    .parse()
+                    .ok()
+                    .filter(|factor| *factor > 1.0)
+                    .ok_or_else(|| format!("invalid growth factor '{}'", factor))?;
+            }
+            "--gc-stats" => options.gc_stats = true,
+            "-o" | "--output" => {
+                let output = args.next().ok_or("expected a file after '-o'")?;
+                options.output = Some(output.clone());
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+            _ if path.is_none() => path = Some(arg.clone()),
+            _ if matches!(options.command, Command::Compare) => options.args.push(arg.clone()),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+        // Everything after the file belongs to the program being run
+        if path.is_some() && matches!(options.command, Command::Run) {
+            options.args.extend(args.by_ref().cloned());
+        }
+    }
+    match path {
+        Some(path) => options.path = path,
+        None if matches!(options.command, Command::Compare) => {
+            options.path = String::from("programs");
+        }
+        None => {}
+    }
+
+    Ok(options)
+}
+
+fn parse_source(source: &str) -> Option<Statement> {
+    let mut scanner = Scanner::new(source.to_string());
 
     match scanner.scan_all() {
         Ok(tokens) => {
             let mut parser = Parser::new(tokens);
 
             match parser.parse() {
-                Ok(expr) => println!("{:#?}", expr),
+                Ok(program) => Some(program),
                 Err(e) => {
                     eprintln!("parse error: {}", e);
-                    e.token.range().print_source(source.as_str());
+                    e.token.range().print_source(source);
+                    None
                 }
             }
         }
         Err(e) => {
             eprintln!("scan error: {}", e);
-            e.range().print_source(source.as_str());
+            e.range().print_source(source);
+            None
+        }
+    }
+}
+
+/// Stack of the thread running the compiler. The tree-walking interpreter
+/// recurses on it for every call, so it needs room for `FRAMES_MAX` of them.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+fn main() {
+    let compiler = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_compiler)
+        .expect("cannot spawn the compiler thread");
+    if compiler.join().is_err() {
+        process::exit(101);
+    }
+}
+
+fn run_compiler() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_options(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Command::Compare = options.command {
+        let mut paths = vec![options.path];
+        paths.extend(options.args);
+        if !compare_backends(&paths, options.gc) {
+            process::exit(1);
         }
+        return;
+    }
+
+    if options.path.ends_with(".peb") {
+        run_bytecode_file(&options);
+        return;
+    }
+
+    let source = match fs::read_to_string(&options.path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("cannot read '{}': {}", options.path, e);
+            process::exit(1);
+        }
+    };
+
+    let program = match parse_source(&source) {
+        Some(program) => program,
+        None => process::exit(1),
+    };
+
+    match options.command {
+        Command::Parse => match options.format {
+            OutputFormat::Debug => println!("{:#?}", program),
+            OutputFormat::Json => {
+                println!("{}", serde_json::to_string_pretty(&program).unwrap())
+            }
+            OutputFormat::SExpr => println!("{}", program.sexpr()),
+        },
+        Command::Check => {
+            if compile_program(&program, &source, options.deny_warnings).is_none() {
+                process::exit(1);
+            }
+        }
+        Command::Run => {
+            let script = match compile_program(&program, &source, options.deny_warnings) {
+                Some(script) => script,
+                None => process::exit(1),
+            };
+            let result = match options.backend {
+                Backend::Vm => run_vm(script, &options),
+                Backend::Ast => {
+                    let mut interpreter = Interpreter::new(Box::new(io::stdout()));
+                    interpreter
+                        .run(&program)
+                        .and_then(|_| interpreter.call_main(&options.args))
+                        .map(|value| match value {
+                            interpreter::value::Value::Int(code) => code,
+                            _ => 0,
+                        })
+                }
+            };
+            exit_with(result, Some(&source));
+        }
+        Command::Build => {
+            let script = match compile_program(&program, &source, options.deny_warnings) {
+                Some(script) => script,
+                None => process::exit(1),
+            };
+            let output = match &options.output {
+                Some(output) => output.clone(),
+                None => Path::new(&options.path)
+                    .with_extension("peb")
+                    .to_string_lossy()
+                    .into_owned(),
+            };
+            if let Err(e) = fs::write(&output, peb::write(&script)) {
+                eprintln!("cannot write '{}': {}", output, e);
+                process::exit(1);
+            }
+        }
+        Command::Disasm => match compile_program(&program, &source, options.deny_warnings) {
+            Some(script) => print!("{}", disassemble(&script, &source)),
+            None => process::exit(1),
+        },
+        Command::Compare => unreachable!(),
+    }
+}
+
+/// Runs a program compiled by `build`, or disassembles it.
+fn run_bytecode_file(options: &Options) {
+    let program = fs::read(&options.path)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| peb::read(&bytes));
+    let program = match program {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("cannot load '{}': {}", options.path, e);
+            process::exit(1);
+        }
+    };
+
+    match options.command {
+        Command::Run => match options.backend {
+            Backend::Vm => {
+                if let Err(e) = verifier::verify(&program) {
+                    eprintln!("invalid bytecode in '{}': {}", options.path, e);
+                    process::exit(1);
+                }
+                exit_with(run_vm(program.script(), options), None)
+            }
+            Backend::Ast => {
+                eprintln!("the ast backend needs the source of the program");
+                process::exit(2);
+            }
+        },
+        Command::Disasm => {
+            print!("{}", list_classes(&program));
+            print!("{}", disassemble(&program.script(), ""));
+        }
+        _ => {
+            eprintln!(
+                "'{}' is a bytecode file, it can only be run or disassembled",
+                options.path
+            );
+            process::exit(2);
+        }
+    }
+}
+
+/// Runs the program on the VM and returns the value `main` returns, which
+/// becomes the exit code.
+fn run_vm(script: Rc<Function>, options: &Options) -> Result<i64, RuntimeError> {
+    let mut vm = Vm::new(Box::new(io::stdout()), options.gc);
+    let result = vm
+        .run(script)
+        .and_then(|_| vm.call_main(&options.args))
+        .map(|value| match value {
+            Value::Int(code) => code,
+            _ => 0,
+        });
+    if options.gc_stats {
+        eprintln!("gc: {}", vm.heap.stats);
+    }
+    result
+}
+
+/// Exits with the program's exit code, or reports its runtime error. The
+/// line it happened on is shown when the `source` is at hand.
+fn exit_with(result: Result<i64, RuntimeError>, source: Option<&str>) -> ! {
+    match result {
+        Ok(code) => process::exit(code as i32),
+        Err(e) => {
+            eprintln!("runtime error: {}", e);
+            if let (Some((_, pos)), Some(source)) = (e.trace.first(), source) {
+                PosRange(*pos, *pos).print_source(source);
+            }
+            process::exit(70);
+        }
+    }
+}
+
+/// Runs semantic passes over the program, then compiles it, and reports
+/// their diagnostics.
+/// Returns `None` when any of them reported an error, or a warning when
+/// `deny_warnings` is set.
+fn compile_program(program: &Statement, source: &str, deny_warnings: bool) -> Option<Rc<Function>> {
+    let (resolution, mut diagnostics) = Resolver::new().resolve(program);
+    diagnostics.extend(FlowAnalyzer::new().analyze(program));
+    // Type checking a program with unresolved names only produces noise
+    if !diagnostics.iter().any(Diagnostic::is_error) {
+        diagnostics.extend(Checker::new().check(program));
+        diagnostics.extend(Linter::new(&resolution).lint());
+    }
+    let mut script = None;
+    if !diagnostics.iter().any(Diagnostic::is_error) {
+        let captures = CaptureAnalyzer::new(&resolution).analyze(program);
+        match Compiler::new(&resolution, &captures).compile(program) {
+            Ok(function) => script = Some(function),
+            Err(errors) => diagnostics.extend(errors),
+        }
+    }
+
+    for diagnostic in &diagnostics {
+        eprintln!("{}: {}", diagnostic.severity, diagnostic);
+        diagnostic.range().print_source(source);
+    }
+
+    if diagnostics.is_empty() || !deny_warnings {
+        script
+    } else {
+        None
     }
 }
@@ -1,47 +1,64 @@
 use crate::parser::stmt::{FunctionParameter, Statement};
 use crate::parser::types::TypeReference;
+use crate::scanner::pos::{Pos, PosRange};
 use crate::scanner::token::{Literal, Token};
+use serde::Serialize;
 use std::rc::Rc;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+pub struct ArrayExpression {
+    pub bracket: Token,
+    pub elements: Vec<Rc<Expression>>,
+}
+
+#[derive(Debug, Serialize)]
 pub struct AssignmentExpression {
     pub left: Rc<Expression>,
     pub operator: Token,
     pub right: Rc<Expression>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct BinaryExpression {
     pub left: Rc<Expression>,
     pub operator: Token,
     pub right: Rc<Expression>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct CallExpression {
     pub callee: Rc<Expression>,
     pub arguments: Vec<Rc<Expression>>,
     pub paren: Token,
+    pub optional: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConditionalExpression {
+    pub condition: Rc<Expression>,
+    pub then_branch: Rc<Expression>,
+    pub else_branch: Rc<Expression>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct GroupingExpression {
     pub expression: Rc<Expression>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct IdentifierExpression {
     pub name: Token,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct IndexExpression {
     pub object: Rc<Expression>,
     pub index: Rc<Expression>,
     pub paren: Token,
+    pub optional: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct LambdaFunctionExpression {
     pub keyword: Token,
     pub parameters: Vec<FunctionParameter>,
@@ -49,61 +66,175 @@ pub struct LambdaFunctionExpression {
     pub body: Vec<Rc<Statement>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct LiteralExpression {
+    pub token: Token,
     pub value: Literal,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct LogicalExpression {
     pub left: Rc<Expression>,
     pub operator: Token,
     pub right: Rc<Expression>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+pub struct MapExpression {
+    pub brace: Token,
+    pub entries: Vec<MapEntry>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind")]
+pub enum MapEntry {
+    Pair {
+        key: Rc<Expression>,
+        value: Rc<Expression>,
+    },
+    Spread {
+        expression: Rc<Expression>,
+    },
+}
+
+#[derive(Debug, Serialize)]
 pub struct MemberExpression {
     pub object: Rc<Expression>,
     pub name: Token,
+    pub optional: bool,
+}
+
+/// Boundary of a member/call/index chain that contains at least one `?.`
+/// link. When an optional link finds a null object the whole chain
+/// evaluates to null.
+#[derive(Debug, Serialize)]
+pub struct OptionalChainExpression {
+    pub expression: Rc<Expression>,
+}
+
+/// `...expression` inside an array/tuple literal or a call argument list.
+#[derive(Debug, Serialize)]
+pub struct SpreadExpression {
+    pub operator: Token,
+    pub expression: Rc<Expression>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct SuperExpression {
     pub keyword: Token,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ThisExpression {
     pub keyword: Token,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+pub struct TupleExpression {
+    pub paren: Token,
+    pub elements: Vec<Rc<Expression>>,
+}
+
+#[derive(Debug, Serialize)]
 pub struct UnaryExpression {
     pub operator: Token,
     pub right: Rc<Expression>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct UpdateExpression {
     pub operator: Token,
     pub prefix: bool,
     pub expression: Rc<Expression>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind")]
 pub enum Expression {
+    Array(ArrayExpression),
     Assignment(AssignmentExpression),
     Binary(BinaryExpression),
     Call(CallExpression),
+    Conditional(ConditionalExpression),
     Grouping(GroupingExpression),
     Identifier(IdentifierExpression),
     Index(IndexExpression),
     LambdaFunction(LambdaFunctionExpression),
     Literal(LiteralExpression),
     Logical(LogicalExpression),
+    Map(MapExpression),
     Member(MemberExpression),
+    OptionalChain(OptionalChainExpression),
+    Spread(SpreadExpression),
     Super(SuperExpression),
     This(ThisExpression),
+    Tuple(TupleExpression),
     Unary(UnaryExpression),
     Update(UpdateExpression),
 }
+
+impl Expression {
+    /// Source area covered by the expression, used to point diagnostics at it.
+    pub fn range(&self) -> PosRange {
+        PosRange(self.start_pos(), self.end_pos())
+    }
+
+    pub fn start_pos(&self) -> Pos {
+        match self {
+            Expression::Array(expr) => expr.bracket.start_pos,
+            Expression::Assignment(expr) => expr.left.start_pos(),
+            Expression::Binary(expr) => expr.left.start_pos(),
+            Expression::Call(expr) => expr.callee.start_pos(),
+            Expression::Conditional(expr) => expr.condition.start_pos(),
+            Expression::Grouping(expr) => expr.expression.start_pos(),
+            Expression::Identifier(expr) => expr.name.start_pos,
+            Expression::Index(expr) => expr.object.start_pos(),
+            Expression::LambdaFunction(expr) => expr.keyword.start_pos,
+            Expression::Literal(expr) => expr.token.start_pos,
+            Expression::Logical(expr) => expr.left.start_pos(),
+            Expression::Map(expr) => expr.brace.start_pos,
+            Expression::Member(expr) => expr.object.start_pos(),
+            Expression::OptionalChain(expr) => expr.expression.start_pos(),
+            Expression::Spread(expr) => expr.operator.start_pos,
+            Expression::Super(expr) => expr.keyword.start_pos,
+            Expression::This(expr) => expr.keyword.start_pos,
+            Expression::Tuple(expr) => expr.paren.start_pos,
+            Expression::Unary(expr) => expr.operator.start_pos,
+            Expression::Update(expr) if expr.prefix => expr.operator.start_pos,
+            Expression::Update(expr) => expr.expression.start_pos(),
+        }
+    }
+
+    pub fn end_pos(&self) -> Pos {
+        match self {
+            Expression::Array(expr) => last_end_pos(&expr.elements, &expr.bracket),
+            Expression::Assignment(expr) => expr.right.end_pos(),
+            Expression::Binary(expr) => expr.right.end_pos(),
+            Expression::Call(expr) => expr.paren.end_pos,
+            Expression::Conditional(expr) => expr.else_branch.end_pos(),
+            Expression::Grouping(expr) => expr.expression.end_pos(),
+            Expression::Identifier(expr) => expr.name.end_pos,
+            Expression::Index(expr) => expr.paren.end_pos,
+            Expression::LambdaFunction(expr) => expr.keyword.end_pos,
+            Expression::Literal(expr) => expr.token.end_pos,
+            Expression::Logical(expr) => expr.right.end_pos(),
+            Expression::Map(expr) => expr.brace.end_pos,
+            Expression::Member(expr) => expr.name.end_pos,
+            Expression::OptionalChain(expr) => expr.expression.end_pos(),
+            Expression::Spread(expr) => expr.expression.end_pos(),
+            Expression::Super(expr) => expr.keyword.end_pos,
+            Expression::This(expr) => expr.keyword.end_pos,
+            Expression::Tuple(expr) => last_end_pos(&expr.elements, &expr.paren),
+            Expression::Unary(expr) => expr.right.end_pos(),
+            Expression::Update(expr) if expr.prefix => expr.expression.end_pos(),
+            Expression::Update(expr) => expr.operator.end_pos,
+        }
+    }
+}
+
+fn last_end_pos(elements: &[Rc<Expression>], opening: &Token) -> Pos {
+    match elements.last() {
+        Some(element) => element.end_pos(),
+        None => opening.end_pos,
+    }
+}
@@ -1,12 +1,16 @@
 use crate::parser::expr::*;
+use crate::parser::operators::{InfixKind, Precedence, UnaryKind};
 use crate::parser::stmt::*;
-use crate::parser::types::{TypeName, TypeReference};
+use crate::parser::types::{TypeName, TypeParameter, TypeReference};
 use crate::scanner::token::{Literal, Token};
+use crate::symbol::Symbol;
 use crate::TokenType;
 use std::fmt::{Display, Formatter};
 use std::rc::Rc;
 
 pub mod expr;
+pub mod operators;
+pub mod sexpr;
 pub mod stmt;
 pub mod types;
 
@@ -115,9 +119,10 @@ impl Parser {
     }
 
     fn class_decl(&mut self) -> Result<Statement, ParserError> {
-        let name = self
+        let class_name = self
             .consume(TokenType::Identifier, "Expect class name")?
             .clone();
+        let type_parameters = self.type_parameters()?;
         let extends = if self.match_single(TokenType::Extends) {
             let extends = self.type_reference()?;
             Some(extends)
@@ -134,7 +139,19 @@ impl Parser {
                 .consume(TokenType::Identifier, "Expect member name")?
                 .clone();
 
-            if self.match_single(TokenType::LeftParen) {
+            if name.lexme == class_name.lexme && self.match_single(TokenType::LeftParen) {
+                let parameters = self.parameters()?;
+                self.consume(TokenType::LeftBrace, "Expect '{' before constructor body")?;
+                let body = self.block()?;
+
+                members.push(ClassMember::Constructor {
+                    name,
+                    parameters,
+                    body,
+                })
+            } else if self.check(TokenType::LeftParen) || self.check(TokenType::Less) {
+                let type_parameters = self.type_parameters()?;
+                self.consume(TokenType::LeftParen, "Expect '(' after method name")?;
                 let parameters = self.parameters()?;
                 let return_type = if self.match_single(TokenType::Colon) {
                     let return_type = self.type_reference()?;
@@ -148,6 +165,7 @@ impl Parser {
 
                 members.push(ClassMember::Method {
                     name,
+                    type_parameters,
                     parameters,
                     return_type,
                     body,
@@ -177,7 +195,8 @@ impl Parser {
         self.consume(TokenType::RightBrace, "Expect '}' after class body")?;
 
         Ok(Statement::Class(ClassStatement {
-            name,
+            name: class_name,
+            type_parameters,
             extends,
             members,
         }))
@@ -187,6 +206,7 @@ impl Parser {
         let name = self
             .consume(TokenType::Identifier, "Expect function name")?
             .clone();
+        let type_parameters = self.type_parameters()?;
 
         self.consume(TokenType::LeftParen, "Expect '(' after function name")?;
         let parameters = self.parameters()?;
@@ -202,6 +222,7 @@ impl Parser {
 
         Ok(Statement::Function(FunctionStatement {
             name,
+            type_parameters,
             parameters,
             return_type,
             body,
@@ -209,7 +230,15 @@ impl Parser {
     }
 
     fn var_decl(&mut self) -> Result<Statement, ParserError> {
-        let (name, value_type) = self.typed_var()?;
+        let name = self
+            .consume(TokenType::Identifier, "Expect variable name")?
+            .clone();
+        let value_type = if self.match_single(TokenType::Colon) {
+            let value_type = self.type_reference()?;
+            Some(value_type)
+        } else {
+            None
+        };
 
         let value = if self.match_single(TokenType::Equal) {
             let value = self.expression()?;
@@ -241,6 +270,83 @@ impl Parser {
     }
 
     fn type_reference(&mut self) -> Result<TypeReference, ParserError> {
+        let first = self.postfix_type()?;
+        if !self.check(TokenType::Pipe) {
+            return Ok(first);
+        }
+
+        let mut types = vec![first];
+        while self.match_single(TokenType::Pipe) {
+            types.push(self.postfix_type()?);
+        }
+
+        Ok(TypeName::Union { types }.into_reference())
+    }
+
+    fn postfix_type(&mut self) -> Result<TypeReference, ParserError> {
+        let mut type_reference = if self.match_single(TokenType::LeftParen) {
+            self.function_type()?
+        } else {
+            self.named_type()?
+        };
+
+        loop {
+            if self.match_single(TokenType::LeftSquare) {
+                self.consume(TokenType::RightSquare, "Expect ']' after '[' in array type")?;
+                type_reference = TypeName::Array {
+                    element: Rc::new(type_reference),
+                }
+                .into_reference();
+            } else if self.match_single(TokenType::Question) {
+                type_reference = TypeName::Nullable {
+                    inner: Rc::new(type_reference),
+                }
+                .into_reference();
+            } else {
+                break;
+            }
+        }
+
+        Ok(type_reference)
+    }
+
+    /// Parses either a function type `(A, B) -> C` or a parenthesized type
+    /// `(A | B)` used for grouping.
+    fn function_type(&mut self) -> Result<TypeReference, ParserError> {
+        let paren = self.previous().clone();
+        let mut parameters: Vec<TypeReference> = Vec::new();
+
+        while !self.at_end() && !self.check(TokenType::RightParen) {
+            parameters.push(self.type_reference()?);
+            if !self.match_single(TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after parameter types")?;
+
+        if self.match_single(TokenType::Arrow) {
+            let return_type = self.type_reference()?;
+            return Ok(TypeName::Function {
+                paren,
+                parameters,
+                return_type: Rc::new(return_type),
+            }
+            .into_reference());
+        }
+
+        if parameters.len() == 1 {
+            Ok(parameters.pop().unwrap())
+        } else {
+            Err(self.error(self.peek(), "Expect '->' after function type parameters"))
+        }
+    }
+
+    fn named_type(&mut self) -> Result<TypeReference, ParserError> {
+        // `null` is a keyword but also names the type of the null literal
+        if self.match_single(TokenType::Null) {
+            return Ok(TypeName::new(self.previous().clone()).into_reference());
+        }
+
         let mut type_name: Option<TypeName> = None;
         loop {
             let name = self
@@ -258,7 +364,84 @@ impl Parser {
         }
 
         let type_name = type_name.unwrap();
-        Ok(TypeReference { type_name })
+        let type_arguments = self.type_arguments()?;
+        Ok(TypeReference {
+            type_name,
+            type_arguments,
+        })
+    }
+
+    fn type_arguments(&mut self) -> Result<Vec<TypeReference>, ParserError> {
+        let mut type_arguments: Vec<TypeReference> = Vec::new();
+        if !self.match_single(TokenType::Less) {
+            return Ok(type_arguments);
+        }
+
+        loop {
+            type_arguments.push(self.type_reference()?);
+            if !self.match_single(TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume_closing_angle("Expect '>' after type arguments")?;
+
+        Ok(type_arguments)
+    }
+
+    fn type_parameters(&mut self) -> Result<Vec<TypeParameter>, ParserError> {
+        let mut type_parameters: Vec<TypeParameter> = Vec::new();
+        if !self.match_single(TokenType::Less) {
+            return Ok(type_parameters);
+        }
+
+        loop {
+            let name = self
+                .consume(TokenType::Identifier, "Expect type parameter name")?
+                .clone();
+            let bound = if self.match_single(TokenType::Extends) {
+                let bound = self.type_reference()?;
+                Some(bound)
+            } else {
+                None
+            };
+            type_parameters.push(TypeParameter { name, bound });
+
+            if !self.match_single(TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume_closing_angle("Expect '>' after type parameters")?;
+
+        Ok(type_parameters)
+    }
+
+    /// Consumes a `>` closing a type argument list. Since the scanner is not
+    /// aware of type contexts, `List<List<int>>` ends with a single `>>`
+    /// token which is split here, leaving the remainder as the current token.
+    fn consume_closing_angle(&mut self, error_message: &'static str) -> Result<(), ParserError> {
+        let token = self.peek().clone();
+        let rest = match token.token_type {
+            TokenType::Greater => {
+                self.advance();
+                return Ok(());
+            }
+            TokenType::GreaterGreater => TokenType::Greater,
+            TokenType::GreaterEqual => TokenType::Equal,
+            TokenType::GreaterGreaterEqual => TokenType::GreaterEqual,
+            _ => return Err(self.error(&token, error_message)),
+        };
+
+        let mut start_pos = token.start_pos;
+        start_pos.inc_col();
+        self.tokens[self.current] = Token {
+            token_type: rest,
+            lexme: Symbol::intern(&token.lexme[1..]),
+            start_pos,
+            end_pos: token.end_pos,
+            literal: None,
+        };
+
+        Ok(())
     }
 
     fn statement(&mut self) -> Result<Statement, ParserError> {
@@ -299,7 +482,8 @@ impl Parser {
     }
 
     fn if_stmt(&mut self) -> Result<Statement, ParserError> {
-        self.consume(TokenType::LeftParen, "Expect '(' after 'while'")?;
+        let keyword = self.previous().clone();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after condition")?;
 
@@ -312,6 +496,7 @@ impl Parser {
         };
 
         Ok(Statement::If(IfStatement {
+            keyword,
             condition,
             then_branch: Rc::new(then_branch),
             else_branch,
@@ -319,8 +504,12 @@ impl Parser {
     }
 
     fn block_stmt(&mut self) -> Result<Statement, ParserError> {
+        let brace = self.previous().clone();
         let declarations = self.block()?;
-        Ok(Statement::Block(BlockStatement { declarations }))
+        Ok(Statement::Block(BlockStatement {
+            brace,
+            declarations,
+        }))
     }
 
     fn break_stmt(&mut self) -> Result<Statement, ParserError> {
@@ -361,6 +550,7 @@ impl Parser {
     }
 
     fn while_stmt(&mut self) -> Result<Statement, ParserError> {
+        let keyword = self.previous().clone();
         self.consume(TokenType::LeftParen, "Expect '(' after 'while'")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after condition")?;
@@ -368,12 +558,14 @@ impl Parser {
         let body = self.statement()?;
 
         Ok(Statement::While(WhileStatement {
+            keyword,
             condition,
             body: Rc::new(body),
         }))
     }
 
     fn for_stmt(&mut self) -> Result<Statement, ParserError> {
+        let keyword = self.previous().clone();
         self.consume(TokenType::LeftParen, "Expect '(' after 'for'")?;
 
         let initializer = if self.match_single(TokenType::Semicolon) {
@@ -386,24 +578,26 @@ impl Parser {
             Some(Rc::new(stmt))
         };
 
-        let condition = if self.match_single(TokenType::Semicolon) {
+        let condition = if self.check(TokenType::Semicolon) {
             None
         } else {
             let expr = self.expression()?;
             Some(expr)
         };
+        self.consume(TokenType::Semicolon, "Expect ';' after loop condition")?;
 
-        let update = if self.match_single(TokenType::Semicolon) {
+        let update = if self.check(TokenType::RightParen) {
             None
         } else {
             let expr = self.expression()?;
             Some(expr)
         };
+        self.consume(TokenType::RightParen, "Expect ')' after clauses")?;
 
         let body = self.statement()?;
-        self.consume(TokenType::RightParen, "Expect ')' after clauses")?;
 
         Ok(Statement::For(ForStatement {
+            keyword,
             initializer,
             condition,
             update,
@@ -427,7 +621,7 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> Result<Expression, ParserError> {
-        let expr = self.or()?;
+        let expr = self.conditional()?;
 
         if self.match_any(&[
             TokenType::Equal,
@@ -435,6 +629,14 @@ impl Parser {
             TokenType::PlusEqual,
             TokenType::SlashEqual,
             TokenType::StarEqual,
+            TokenType::StarStarEqual,
+            TokenType::PercentEqual,
+            TokenType::AmpersandEqual,
+            TokenType::PipeEqual,
+            TokenType::CaretEqual,
+            TokenType::LessLessEqual,
+            TokenType::GreaterGreaterEqual,
+            TokenType::QuestionQuestionEqual,
         ]) {
             let operator = self.previous().clone();
             let right = self.assignment()?;
@@ -456,174 +658,147 @@ impl Parser {
         }
     }
 
-    fn or(&mut self) -> Result<Expression, ParserError> {
-        let mut expr = self.and()?;
-
-        while self.match_single(TokenType::Or) {
-            let operator = self.previous().clone();
-            let right = self.and()?;
-            expr = Expression::Logical(LogicalExpression {
-                operator,
-                left: Rc::new(expr),
-                right: Rc::new(right),
-            })
-        }
+    fn conditional(&mut self) -> Result<Expression, ParserError> {
+        let condition = self.binary(operators::binding_power(Precedence::Lowest))?;
 
-        Ok(expr)
-    }
-
-    fn and(&mut self) -> Result<Expression, ParserError> {
-        let mut expr = self.equality()?;
-
-        while self.match_single(TokenType::And) {
-            let operator = self.previous().clone();
-            let right = self.equality()?;
-            expr = Expression::Logical(LogicalExpression {
-                operator,
-                left: Rc::new(expr),
-                right: Rc::new(right),
-            })
-        }
+        if self.match_single(TokenType::Question) {
+            let then_branch = self.expression()?;
+            self.consume(TokenType::Colon, "Expect ':' after then branch of '?'")?;
+            let else_branch = self.assignment()?;
 
-        Ok(expr)
-    }
-
-    fn equality(&mut self) -> Result<Expression, ParserError> {
-        let mut expr = self.comparison()?;
-
-        while self.match_any(&[TokenType::BangEqual, TokenType::EqualEqual]) {
-            let operator = self.previous().clone();
-            let right = self.comparison()?;
-            expr = Expression::Binary(BinaryExpression {
-                operator,
-                left: Rc::new(expr),
-                right: Rc::new(right),
-            })
+            Ok(Expression::Conditional(ConditionalExpression {
+                condition: Rc::new(condition),
+                then_branch: Rc::new(then_branch),
+                else_branch: Rc::new(else_branch),
+            }))
+        } else {
+            Ok(condition)
         }
-
-        Ok(expr)
     }
 
-    fn comparison(&mut self) -> Result<Expression, ParserError> {
-        let mut expr = self.term()?;
+    fn binary(&mut self, min_power: u8) -> Result<Expression, ParserError> {
+        let mut expr = self.prefix()?;
 
-        while self.match_any(&[
-            TokenType::Less,
-            TokenType::LessEqual,
-            TokenType::Greater,
-            TokenType::GreaterEqual,
-        ]) {
-            let operator = self.previous().clone();
-            let right = self.term()?;
-            expr = Expression::Binary(BinaryExpression {
-                operator,
-                left: Rc::new(expr),
-                right: Rc::new(right),
-            })
-        }
+        loop {
+            let token_type = self.peek().token_type;
 
-        Ok(expr)
-    }
+            if let Some(op) = operators::postfix_operator(token_type) {
+                if op.binding_power() < min_power {
+                    break;
+                }
+                let operator = self.advance().clone();
+                expr = Expression::Update(UpdateExpression {
+                    operator,
+                    prefix: false,
+                    expression: Rc::new(expr),
+                });
+                continue;
+            }
 
-    fn term(&mut self) -> Result<Expression, ParserError> {
-        let mut expr = self.factor()?;
+            if let Some(op) = operators::infix_operator(token_type) {
+                let (left_power, right_power) = op.binding_power();
+                if left_power < min_power {
+                    break;
+                }
+                let operator = self.advance().clone();
+                let right = self.binary(right_power)?;
+                expr = match op.kind {
+                    InfixKind::Binary => Expression::Binary(BinaryExpression {
+                        operator,
+                        left: Rc::new(expr),
+                        right: Rc::new(right),
+                    }),
+                    InfixKind::Logical => Expression::Logical(LogicalExpression {
+                        operator,
+                        left: Rc::new(expr),
+                        right: Rc::new(right),
+                    }),
+                };
+                continue;
+            }
 
-        while self.match_any(&[TokenType::Minus, TokenType::Plus]) {
-            let operator = self.previous().clone();
-            let right = self.factor()?;
-            expr = Expression::Binary(BinaryExpression {
-                operator,
-                left: Rc::new(expr),
-                right: Rc::new(right),
-            })
+            break;
         }
 
         Ok(expr)
     }
 
-    fn factor(&mut self) -> Result<Expression, ParserError> {
-        let mut expr = self.unary()?;
-
-        while self.match_any(&[TokenType::Slash, TokenType::Star]) {
-            let operator = self.previous().clone();
-            let right = self.unary()?;
-            expr = Expression::Binary(BinaryExpression {
-                operator,
-                left: Rc::new(expr),
-                right: Rc::new(right),
-            })
-        }
-
-        Ok(expr)
-    }
+    fn prefix(&mut self) -> Result<Expression, ParserError> {
+        let op = match operators::prefix_operator(self.peek().token_type) {
+            Some(op) => op,
+            None => return self.call(),
+        };
 
-    fn unary(&mut self) -> Result<Expression, ParserError> {
-        if self.match_any(&[TokenType::Bang, TokenType::Minus]) {
-            let operator = self.previous().clone();
-            let right = self.unary()?;
+        let operator = self.advance().clone();
+        let right = self.binary(op.binding_power())?;
 
-            Ok(Expression::Unary(UnaryExpression {
+        Ok(match op.kind {
+            UnaryKind::Unary => Expression::Unary(UnaryExpression {
                 operator,
                 right: Rc::new(right),
-            }))
-        } else {
-            self.update()
-        }
-    }
-
-    fn update(&mut self) -> Result<Expression, ParserError> {
-        if self.match_any(&[TokenType::MinusMinus, TokenType::PlusPlus]) {
-            let operator = self.previous().clone();
-            let expr = self.call()?;
-            Ok(Expression::Update(UpdateExpression {
+            }),
+            UnaryKind::Update => Expression::Update(UpdateExpression {
                 operator,
                 prefix: true,
-                expression: Rc::new(expr),
-            }))
-        } else {
-            let expr = self.call()?;
-            if self.match_any(&[TokenType::MinusMinus, TokenType::PlusPlus]) {
-                let operator = self.previous().clone();
-                Ok(Expression::Update(UpdateExpression {
-                    operator,
-                    prefix: false,
-                    expression: Rc::new(expr),
-                }))
-            } else {
-                Ok(expr)
-            }
-        }
+                expression: Rc::new(right),
+            }),
+        })
     }
 
     fn call(&mut self) -> Result<Expression, ParserError> {
         let mut expr = self.primary()?;
+        let mut optional_chain = false;
 
         loop {
             if self.match_single(TokenType::LeftParen) {
-                expr = self.finish_call(expr)?;
+                expr = self.finish_call(expr, false)?;
             } else if self.match_single(TokenType::LeftSquare) {
-                expr = self.finish_index(expr)?;
+                expr = self.finish_index(expr, false)?;
             } else if self.match_single(TokenType::Dot) {
-                expr = self.finish_member(expr)?;
+                expr = self.finish_member(expr, false)?;
+            } else if self.match_single(TokenType::QuestionDot) {
+                optional_chain = true;
+                expr = if self.match_single(TokenType::LeftParen) {
+                    self.finish_call(expr, true)?
+                } else if self.match_single(TokenType::LeftSquare) {
+                    self.finish_index(expr, true)?
+                } else {
+                    self.finish_member(expr, true)?
+                };
             } else {
                 break;
             }
         }
 
+        if optional_chain {
+            expr = Expression::OptionalChain(OptionalChainExpression {
+                expression: Rc::new(expr),
+            });
+        }
+
         Ok(expr)
     }
 
-    fn finish_member(&mut self, callee: Expression) -> Result<Expression, ParserError> {
+    fn finish_member(
+        &mut self,
+        callee: Expression,
+        optional: bool,
+    ) -> Result<Expression, ParserError> {
         let name = self
             .consume(TokenType::Identifier, "Expect property name after '.'")?
             .clone();
         Ok(Expression::Member(MemberExpression {
             object: Rc::new(callee),
             name,
+            optional,
         }))
     }
 
-    fn finish_index(&mut self, callee: Expression) -> Result<Expression, ParserError> {
+    fn finish_index(
+        &mut self,
+        callee: Expression,
+        optional: bool,
+    ) -> Result<Expression, ParserError> {
         let index = self.expression()?;
         let paren = self
             .consume(TokenType::RightSquare, "Expect ']' after index")?
@@ -632,31 +807,22 @@ impl Parser {
             index: Rc::new(index),
             object: Rc::new(callee),
             paren,
+            optional,
         }))
     }
 
-    fn finish_call(&mut self, callee: Expression) -> Result<Expression, ParserError> {
-        let mut arguments: Vec<Rc<Expression>> = Vec::new();
-
-        if !self.check(TokenType::RightParen) {
-            loop {
-                let argument = self.expression()?;
-
-                arguments.push(Rc::new(argument));
-
-                if !self.match_single(TokenType::Comma) {
-                    break;
-                }
-            }
-        }
-
-        let paren = self
-            .consume(TokenType::RightParen, "Expect ')' after arguments")?
-            .clone();
+    fn finish_call(
+        &mut self,
+        callee: Expression,
+        optional: bool,
+    ) -> Result<Expression, ParserError> {
+        let arguments = self.elements(TokenType::RightParen, "Expect ')' after arguments")?;
+        let paren = self.previous().clone();
         Ok(Expression::Call(CallExpression {
             callee: Rc::new(callee),
             arguments,
             paren,
+            optional,
         }))
     }
 
@@ -665,6 +831,10 @@ impl Parser {
         while !self.at_end() && !self.check(TokenType::RightParen) {
             let (name, value_type) = self.typed_var()?;
             parameters.push(FunctionParameter { name, value_type });
+
+            if !self.match_single(TokenType::Comma) {
+                break;
+            }
         }
         self.consume(TokenType::RightParen, "Expect ')' after parameter list")?;
 
@@ -677,7 +847,7 @@ impl Parser {
         self.consume(TokenType::LeftParen, "Expect '(' after function name")?;
 
         let parameters = self.parameters()?;
-        let return_type = if self.check(TokenType::Colon) {
+        let return_type = if self.match_single(TokenType::Colon) {
             let return_type = self.type_reference()?;
             Some(return_type)
         } else {
@@ -700,18 +870,21 @@ impl Parser {
         // "false"
         if self.match_single(TokenType::False) {
             return Ok(Expression::Literal(LiteralExpression {
+                token: self.previous().clone(),
                 value: Literal::Boolean(false),
             }));
         }
         // "true"
         if self.match_single(TokenType::True) {
             return Ok(Expression::Literal(LiteralExpression {
+                token: self.previous().clone(),
                 value: Literal::Boolean(true),
             }));
         }
         // "null"
         if self.match_single(TokenType::Null) {
             return Ok(Expression::Literal(LiteralExpression {
+                token: self.previous().clone(),
                 value: Literal::Null,
             }));
         }
@@ -737,6 +910,7 @@ impl Parser {
         // NUMBER | STRING
         if self.match_any(&[TokenType::Integer, TokenType::Float, TokenType::String]) {
             return Ok(Expression::Literal(LiteralExpression {
+                token: self.previous().clone(),
                 value: self.previous().literal.as_ref().unwrap().clone(),
             }));
         }
@@ -748,17 +922,116 @@ impl Parser {
             }));
         }
 
-        // "(" expression ")"
+        // "(" expression ")" | "(" elements? ")"
         if self.match_single(TokenType::LeftParen) {
-            let expr = self.expression()?;
-            self.consume(TokenType::RightParen, "Expect ')' after expression")?;
+            return self.grouping_or_tuple();
+        }
 
+        // "[" elements? "]"
+        if self.match_single(TokenType::LeftSquare) {
+            let bracket = self.previous().clone();
+            let elements =
+                self.elements(TokenType::RightSquare, "Expect ']' after array elements")?;
+            return Ok(Expression::Array(ArrayExpression { bracket, elements }));
+        }
+
+        // "{" entries? "}"
+        if self.match_single(TokenType::LeftBrace) {
+            return self.map();
+        }
+
+        Err(self.error(self.peek(), "Expect expression"))
+    }
+
+    fn grouping_or_tuple(&mut self) -> Result<Expression, ParserError> {
+        let paren = self.previous().clone();
+        if self.match_single(TokenType::RightParen) {
+            return Ok(Expression::Tuple(TupleExpression {
+                paren,
+                elements: Vec::new(),
+            }));
+        }
+
+        let first = self.element()?;
+        if let Expression::Spread(_) = first {
+        } else if self.match_single(TokenType::RightParen) {
             return Ok(Expression::Grouping(GroupingExpression {
-                expression: Rc::new(expr),
+                expression: Rc::new(first),
             }));
         }
 
-        Err(self.error(self.peek(), "Expect expression"))
+        let mut elements = vec![Rc::new(first)];
+        if self.match_single(TokenType::Comma) {
+            elements.extend(self.elements(TokenType::RightParen, "Expect ')' after tuple")?);
+        } else {
+            self.consume(TokenType::RightParen, "Expect ')' after tuple")?;
+        }
+
+        Ok(Expression::Tuple(TupleExpression { paren, elements }))
+    }
+
+    fn map(&mut self) -> Result<Expression, ParserError> {
+        let brace = self.previous().clone();
+        let mut entries: Vec<MapEntry> = Vec::new();
+
+        while !self.at_end() && !self.check(TokenType::RightBrace) {
+            if self.match_single(TokenType::DotDotDot) {
+                let expression = self.expression()?;
+                entries.push(MapEntry::Spread {
+                    expression: Rc::new(expression),
+                });
+            } else {
+                let key = self.expression()?;
+                self.consume(TokenType::Colon, "Expect ':' after map key")?;
+                let value = self.expression()?;
+                entries.push(MapEntry::Pair {
+                    key: Rc::new(key),
+                    value: Rc::new(value),
+                });
+            }
+
+            if !self.match_single(TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after map entries")?;
+
+        Ok(Expression::Map(MapExpression { brace, entries }))
+    }
+
+    /// Parses a comma separated list of possibly spread expressions up to and
+    /// including the `closing` token. A trailing comma is allowed.
+    fn elements(
+        &mut self,
+        closing: TokenType,
+        error_message: &'static str,
+    ) -> Result<Vec<Rc<Expression>>, ParserError> {
+        let mut elements: Vec<Rc<Expression>> = Vec::new();
+
+        while !self.at_end() && !self.check(closing) {
+            let element = self.element()?;
+            elements.push(Rc::new(element));
+
+            if !self.match_single(TokenType::Comma) {
+                break;
+            }
+        }
+        self.consume(closing, error_message)?;
+
+        Ok(elements)
+    }
+
+    fn element(&mut self) -> Result<Expression, ParserError> {
+        if self.match_single(TokenType::DotDotDot) {
+            let operator = self.previous().clone();
+            let expression = self.expression()?;
+            Ok(Expression::Spread(SpreadExpression {
+                operator,
+                expression: Rc::new(expression),
+            }))
+        } else {
+            self.expression()
+        }
     }
 
     fn consume(
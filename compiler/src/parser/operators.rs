@@ -0,0 +1,207 @@
+use crate::scanner::token::TokenType;
+
+/// Operator precedence levels, from the loosest to the tightest binding.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Precedence {
+    Lowest,
+    Coalesce,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shift,
+    Term,
+    Factor,
+    Prefix,
+    Power,
+    Postfix,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
+/// Syntax tree node an infix operator produces.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum InfixKind {
+    Binary,
+    Logical,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct InfixOperator {
+    pub token_type: TokenType,
+    pub precedence: Precedence,
+    pub associativity: Associativity,
+    pub kind: InfixKind,
+}
+
+impl InfixOperator {
+    /// Returns `(left, right)` binding powers. Left-associative operators bind
+    /// their right operand one level tighter so that `a - b - c` groups as
+    /// `(a - b) - c`, right-associative ones reuse the same level.
+    pub fn binding_power(&self) -> (u8, u8) {
+        let power = binding_power(self.precedence);
+        match self.associativity {
+            Associativity::Left => (power, power + 1),
+            Associativity::Right => (power, power),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct UnaryOperator {
+    pub token_type: TokenType,
+    pub precedence: Precedence,
+    pub kind: UnaryKind,
+}
+
+/// Syntax tree node a prefix or postfix operator produces.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum UnaryKind {
+    Unary,
+    Update,
+}
+
+impl UnaryOperator {
+    pub fn binding_power(&self) -> u8 {
+        binding_power(self.precedence)
+    }
+}
+
+pub fn binding_power(precedence: Precedence) -> u8 {
+    (precedence as u8) * 2
+}
+
+static INFIX_OPERATORS: &[InfixOperator] = &[
+    infix(
+        TokenType::QuestionQuestion,
+        Precedence::Coalesce,
+        InfixKind::Logical,
+    ),
+    infix(TokenType::Or, Precedence::Or, InfixKind::Logical),
+    infix(TokenType::And, Precedence::And, InfixKind::Logical),
+    infix(
+        TokenType::EqualEqual,
+        Precedence::Equality,
+        InfixKind::Binary,
+    ),
+    infix(
+        TokenType::BangEqual,
+        Precedence::Equality,
+        InfixKind::Binary,
+    ),
+    infix(TokenType::Less, Precedence::Comparison, InfixKind::Binary),
+    infix(
+        TokenType::LessEqual,
+        Precedence::Comparison,
+        InfixKind::Binary,
+    ),
+    infix(
+        TokenType::Greater,
+        Precedence::Comparison,
+        InfixKind::Binary,
+    ),
+    infix(
+        TokenType::GreaterEqual,
+        Precedence::Comparison,
+        InfixKind::Binary,
+    ),
+    infix(TokenType::Pipe, Precedence::BitOr, InfixKind::Binary),
+    infix(TokenType::Caret, Precedence::BitXor, InfixKind::Binary),
+    infix(TokenType::Ampersand, Precedence::BitAnd, InfixKind::Binary),
+    infix(TokenType::LessLess, Precedence::Shift, InfixKind::Binary),
+    infix(
+        TokenType::GreaterGreater,
+        Precedence::Shift,
+        InfixKind::Binary,
+    ),
+    infix(TokenType::Plus, Precedence::Term, InfixKind::Binary),
+    infix(TokenType::Minus, Precedence::Term, InfixKind::Binary),
+    infix(TokenType::Star, Precedence::Factor, InfixKind::Binary),
+    infix(TokenType::Slash, Precedence::Factor, InfixKind::Binary),
+    infix(TokenType::Percent, Precedence::Factor, InfixKind::Binary),
+    // `-2 ** 2` is `-(2 ** 2)` and `2 ** 3 ** 2` is `2 ** (3 ** 2)`
+    InfixOperator {
+        token_type: TokenType::StarStar,
+        precedence: Precedence::Power,
+        associativity: Associativity::Right,
+        kind: InfixKind::Binary,
+    },
+];
+
+static PREFIX_OPERATORS: &[UnaryOperator] = &[
+    unary(TokenType::Bang, Precedence::Prefix, UnaryKind::Unary),
+    unary(TokenType::Minus, Precedence::Prefix, UnaryKind::Unary),
+    unary(TokenType::Tilde, Precedence::Prefix, UnaryKind::Unary),
+    unary(TokenType::PlusPlus, Precedence::Prefix, UnaryKind::Update),
+    unary(TokenType::MinusMinus, Precedence::Prefix, UnaryKind::Update),
+];
+
+static POSTFIX_OPERATORS: &[UnaryOperator] = &[
+    unary(TokenType::PlusPlus, Precedence::Postfix, UnaryKind::Update),
+    unary(
+        TokenType::MinusMinus,
+        Precedence::Postfix,
+        UnaryKind::Update,
+    ),
+];
+
+const fn infix(token_type: TokenType, precedence: Precedence, kind: InfixKind) -> InfixOperator {
+    InfixOperator {
+        token_type,
+        precedence,
+        associativity: Associativity::Left,
+        kind,
+    }
+}
+
+const fn unary(token_type: TokenType, precedence: Precedence, kind: UnaryKind) -> UnaryOperator {
+    UnaryOperator {
+        token_type,
+        precedence,
+        kind,
+    }
+}
+
+pub fn infix_operator(token_type: TokenType) -> Option<&'static InfixOperator> {
+    INFIX_OPERATORS
+        .iter()
+        .find(|op| op.token_type == token_type)
+}
+
+pub fn prefix_operator(token_type: TokenType) -> Option<&'static UnaryOperator> {
+    PREFIX_OPERATORS
+        .iter()
+        .find(|op| op.token_type == token_type)
+}
+
+pub fn postfix_operator(token_type: TokenType) -> Option<&'static UnaryOperator> {
+    POSTFIX_OPERATORS
+        .iter()
+        .find(|op| op.token_type == token_type)
+}
+
+/// Binary operator applied by a compound assignment, e.g. `+` for `+=`.
+pub fn compound_operator(token_type: TokenType) -> Option<TokenType> {
+    match token_type {
+        TokenType::PlusEqual => Some(TokenType::Plus),
+        TokenType::MinusEqual => Some(TokenType::Minus),
+        TokenType::StarEqual => Some(TokenType::Star),
+        TokenType::SlashEqual => Some(TokenType::Slash),
+        TokenType::PercentEqual => Some(TokenType::Percent),
+        TokenType::StarStarEqual => Some(TokenType::StarStar),
+        TokenType::AmpersandEqual => Some(TokenType::Ampersand),
+        TokenType::PipeEqual => Some(TokenType::Pipe),
+        TokenType::CaretEqual => Some(TokenType::Caret),
+        TokenType::LessLessEqual => Some(TokenType::LessLess),
+        TokenType::GreaterGreaterEqual => Some(TokenType::GreaterGreater),
+        TokenType::QuestionQuestionEqual => Some(TokenType::QuestionQuestion),
+        _ => None,
+    }
+}
@@ -0,0 +1,416 @@
+use crate::parser::expr::*;
+use crate::parser::stmt::*;
+use crate::parser::types::{TypeName, TypeParameter, TypeReference};
+use crate::scanner::token::Literal;
+use std::fmt::{Display, Formatter, Result};
+use std::rc::Rc;
+
+/// Compact S-expression rendering of syntax tree nodes, mostly useful for
+/// comparing parser output in tests.
+pub trait SExpr {
+    fn write_sexpr(&self, f: &mut Formatter<'_>) -> Result;
+
+    fn sexpr(&self) -> SExprDisplay<'_, Self>
+    where
+        Self: Sized,
+    {
+        SExprDisplay(self)
+    }
+}
+
+pub struct SExprDisplay<'a, T: SExpr>(&'a T);
+
+impl<T: SExpr> Display for SExprDisplay<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        self.0.write_sexpr(f)
+    }
+}
+
+impl<T: SExpr> SExpr for Rc<T> {
+    fn write_sexpr(&self, f: &mut Formatter<'_>) -> Result {
+        self.as_ref().write_sexpr(f)
+    }
+}
+
+impl<T: SExpr> SExpr for Option<T> {
+    fn write_sexpr(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Some(value) => value.write_sexpr(f),
+            None => write!(f, "_"),
+        }
+    }
+}
+
+fn write_list<T: SExpr>(f: &mut Formatter<'_>, items: &[T]) -> Result {
+    for item in items {
+        write!(f, " ")?;
+        item.write_sexpr(f)?;
+    }
+    Ok(())
+}
+
+fn write_parameters(f: &mut Formatter<'_>, parameters: &[FunctionParameter]) -> Result {
+    write!(f, "(")?;
+    for (i, parameter) in parameters.iter().enumerate() {
+        if i > 0 {
+            write!(f, " ")?;
+        }
+        write!(f, "({} ", parameter.name.lexme)?;
+        parameter.value_type.write_sexpr(f)?;
+        write!(f, ")")?;
+    }
+    write!(f, ")")
+}
+
+fn write_function(
+    f: &mut Formatter<'_>,
+    parameters: &[FunctionParameter],
+    return_type: &Option<TypeReference>,
+    body: &[Rc<Statement>],
+) -> Result {
+    write!(f, " ")?;
+    write_parameters(f, parameters)?;
+    write!(f, " ")?;
+    return_type.write_sexpr(f)?;
+    write!(f, " (block")?;
+    write_list(f, body)?;
+    write!(f, ")")
+}
+
+impl SExpr for Literal {
+    fn write_sexpr(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Literal::Null => write!(f, "null"),
+            Literal::Boolean(value) => write!(f, "{}", value),
+            Literal::Integer(value) => write!(f, "{}", value),
+            Literal::Float(value) => write!(f, "{:?}", value),
+            Literal::String(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+impl SExpr for TypeName {
+    fn write_sexpr(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            TypeName::Identifier { name } => write!(f, "{}", name.lexme),
+            TypeName::QualifiedName { left, right } => {
+                left.write_sexpr(f)?;
+                write!(f, ".{}", right.lexme)
+            }
+            TypeName::Function {
+                parameters,
+                return_type,
+                ..
+            } => {
+                write!(f, "(-> (")?;
+                for (i, parameter) in parameters.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    parameter.write_sexpr(f)?;
+                }
+                write!(f, ") ")?;
+                return_type.write_sexpr(f)?;
+                write!(f, ")")
+            }
+            TypeName::Array { element } => {
+                element.write_sexpr(f)?;
+                write!(f, "[]")
+            }
+            TypeName::Nullable { inner } => {
+                inner.write_sexpr(f)?;
+                write!(f, "?")
+            }
+            TypeName::Union { types } => {
+                write!(f, "(|")?;
+                write_list(f, types)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl SExpr for TypeReference {
+    fn write_sexpr(&self, f: &mut Formatter<'_>) -> Result {
+        self.type_name.write_sexpr(f)?;
+        if !self.type_arguments.is_empty() {
+            write!(f, "<")?;
+            for (i, argument) in self.type_arguments.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                argument.write_sexpr(f)?;
+            }
+            write!(f, ">")?;
+        }
+        Ok(())
+    }
+}
+
+impl SExpr for TypeParameter {
+    fn write_sexpr(&self, f: &mut Formatter<'_>) -> Result {
+        match &self.bound {
+            Some(bound) => {
+                write!(f, "({} ", self.name.lexme)?;
+                bound.write_sexpr(f)?;
+                write!(f, ")")
+            }
+            None => write!(f, "{}", self.name.lexme),
+        }
+    }
+}
+
+fn write_type_parameters(f: &mut Formatter<'_>, type_parameters: &[TypeParameter]) -> Result {
+    if !type_parameters.is_empty() {
+        write!(f, " (<>")?;
+        write_list(f, type_parameters)?;
+        write!(f, ")")?;
+    }
+    Ok(())
+}
+
+impl SExpr for ClassMember {
+    fn write_sexpr(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            ClassMember::Field {
+                name,
+                value_type,
+                value,
+            } => {
+                write!(f, "(field {} ", name.lexme)?;
+                value_type.write_sexpr(f)?;
+                if let Some(value) = value {
+                    write!(f, " ")?;
+                    value.write_sexpr(f)?;
+                }
+                write!(f, ")")
+            }
+            ClassMember::Method {
+                name,
+                type_parameters,
+                parameters,
+                return_type,
+                body,
+            } => {
+                write!(f, "(method {}", name.lexme)?;
+                write_type_parameters(f, type_parameters)?;
+                write_function(f, parameters, return_type, body)?;
+                write!(f, ")")
+            }
+            ClassMember::Constructor {
+                parameters, body, ..
+            } => {
+                write!(f, "(constructor")?;
+                write_function(f, parameters, &None, body)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl SExpr for Statement {
+    fn write_sexpr(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Statement::Block(stmt) => {
+                write!(f, "(block")?;
+                write_list(f, &stmt.declarations)?;
+                write!(f, ")")
+            }
+            Statement::Break(_) => write!(f, "(break)"),
+            Statement::Class(stmt) => {
+                write!(f, "(class {}", stmt.name.lexme)?;
+                write_type_parameters(f, &stmt.type_parameters)?;
+                if let Some(extends) = &stmt.extends {
+                    write!(f, " (extends ")?;
+                    extends.write_sexpr(f)?;
+                    write!(f, ")")?;
+                }
+                write_list(f, &stmt.members)?;
+                write!(f, ")")
+            }
+            Statement::Continue(_) => write!(f, "(continue)"),
+            Statement::Expression(stmt) => stmt.expression.write_sexpr(f),
+            Statement::For(stmt) => {
+                write!(f, "(for ")?;
+                stmt.initializer.write_sexpr(f)?;
+                write!(f, " ")?;
+                stmt.condition.write_sexpr(f)?;
+                write!(f, " ")?;
+                stmt.update.write_sexpr(f)?;
+                write!(f, " ")?;
+                stmt.body.write_sexpr(f)?;
+                write!(f, ")")
+            }
+            Statement::Function(stmt) => {
+                write!(f, "(fun {}", stmt.name.lexme)?;
+                write_type_parameters(f, &stmt.type_parameters)?;
+                write_function(f, &stmt.parameters, &stmt.return_type, &stmt.body)?;
+                write!(f, ")")
+            }
+            Statement::If(stmt) => {
+                write!(f, "(if ")?;
+                stmt.condition.write_sexpr(f)?;
+                write!(f, " ")?;
+                stmt.then_branch.write_sexpr(f)?;
+                if let Some(else_branch) = &stmt.else_branch {
+                    write!(f, " ")?;
+                    else_branch.write_sexpr(f)?;
+                }
+                write!(f, ")")
+            }
+            Statement::Program(stmt) => {
+                write!(f, "(program")?;
+                write_list(f, &stmt.declarations)?;
+                write!(f, ")")
+            }
+            Statement::Return(stmt) => {
+                write!(f, "(return")?;
+                if let Some(value) = &stmt.value {
+                    write!(f, " ")?;
+                    value.write_sexpr(f)?;
+                }
+                write!(f, ")")
+            }
+            Statement::Var(stmt) => {
+                write!(f, "(var {} ", stmt.name.lexme)?;
+                stmt.value_type.write_sexpr(f)?;
+                if let Some(value) = &stmt.value {
+                    write!(f, " ")?;
+                    value.write_sexpr(f)?;
+                }
+                write!(f, ")")
+            }
+            Statement::While(stmt) => {
+                write!(f, "(while ")?;
+                stmt.condition.write_sexpr(f)?;
+                write!(f, " ")?;
+                stmt.body.write_sexpr(f)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl SExpr for MapEntry {
+    fn write_sexpr(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            MapEntry::Pair { key, value } => {
+                write!(f, "(")?;
+                key.write_sexpr(f)?;
+                write!(f, " ")?;
+                value.write_sexpr(f)?;
+                write!(f, ")")
+            }
+            MapEntry::Spread { expression } => {
+                write!(f, "(... ")?;
+                expression.write_sexpr(f)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl SExpr for Expression {
+    fn write_sexpr(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Expression::Array(expr) => {
+                write!(f, "(array")?;
+                write_list(f, &expr.elements)?;
+                write!(f, ")")
+            }
+            Expression::Assignment(expr) => {
+                write!(f, "({} ", expr.operator.lexme)?;
+                expr.left.write_sexpr(f)?;
+                write!(f, " ")?;
+                expr.right.write_sexpr(f)?;
+                write!(f, ")")
+            }
+            Expression::Binary(expr) => {
+                write!(f, "({} ", expr.operator.lexme)?;
+                expr.left.write_sexpr(f)?;
+                write!(f, " ")?;
+                expr.right.write_sexpr(f)?;
+                write!(f, ")")
+            }
+            Expression::Call(expr) => {
+                write!(f, "({} ", if expr.optional { "?call" } else { "call" })?;
+                expr.callee.write_sexpr(f)?;
+                write_list(f, &expr.arguments)?;
+                write!(f, ")")
+            }
+            Expression::Conditional(expr) => {
+                write!(f, "(? ")?;
+                expr.condition.write_sexpr(f)?;
+                write!(f, " ")?;
+                expr.then_branch.write_sexpr(f)?;
+                write!(f, " ")?;
+                expr.else_branch.write_sexpr(f)?;
+                write!(f, ")")
+            }
+            Expression::Grouping(expr) => {
+                write!(f, "(group ")?;
+                expr.expression.write_sexpr(f)?;
+                write!(f, ")")
+            }
+            Expression::Identifier(expr) => write!(f, "{}", expr.name.lexme),
+            Expression::Index(expr) => {
+                write!(f, "({} ", if expr.optional { "?index" } else { "index" })?;
+                expr.object.write_sexpr(f)?;
+                write!(f, " ")?;
+                expr.index.write_sexpr(f)?;
+                write!(f, ")")
+            }
+            Expression::LambdaFunction(expr) => {
+                write!(f, "(lambda")?;
+                write_function(f, &expr.parameters, &expr.return_type, &expr.body)?;
+                write!(f, ")")
+            }
+            Expression::Literal(expr) => expr.value.write_sexpr(f),
+            Expression::Logical(expr) => {
+                write!(f, "({} ", expr.operator.lexme)?;
+                expr.left.write_sexpr(f)?;
+                write!(f, " ")?;
+                expr.right.write_sexpr(f)?;
+                write!(f, ")")
+            }
+            Expression::Map(expr) => {
+                write!(f, "(map")?;
+                write_list(f, &expr.entries)?;
+                write!(f, ")")
+            }
+            Expression::Member(expr) => {
+                write!(f, "({} ", if expr.optional { "?." } else { "." })?;
+                expr.object.write_sexpr(f)?;
+                write!(f, " {})", expr.name.lexme)
+            }
+            Expression::OptionalChain(expr) => {
+                write!(f, "(chain ")?;
+                expr.expression.write_sexpr(f)?;
+                write!(f, ")")
+            }
+            Expression::Spread(expr) => {
+                write!(f, "(... ")?;
+                expr.expression.write_sexpr(f)?;
+                write!(f, ")")
+            }
+            Expression::Super(_) => write!(f, "super"),
+            Expression::This(_) => write!(f, "this"),
+            Expression::Tuple(expr) => {
+                write!(f, "(tuple")?;
+                write_list(f, &expr.elements)?;
+                write!(f, ")")
+            }
+            Expression::Unary(expr) => {
+                write!(f, "({} ", expr.operator.lexme)?;
+                expr.right.write_sexpr(f)?;
+                write!(f, ")")
+            }
+            Expression::Update(expr) => {
+                let fixity = if expr.prefix { "prefix" } else { "postfix" };
+                write!(f, "({} {} ", fixity, expr.operator.lexme)?;
+                expr.expression.write_sexpr(f)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
@@ -1,83 +1,146 @@
 use crate::parser::expr::Expression;
-use crate::parser::types::TypeReference;
+use crate::parser::types::{TypeName, TypeParameter, TypeReference};
+use crate::scanner::pos::{Pos, PosRange};
 use crate::scanner::token::Token;
+use serde::Serialize;
+use std::collections::HashMap;
 use std::rc::Rc;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct BlockStatement {
+    pub brace: Token,
     pub declarations: Vec<Rc<Statement>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct BreakStatement {
     pub keyword: Token,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ClassStatement {
     pub name: Token,
+    pub type_parameters: Vec<TypeParameter>,
     pub extends: Option<TypeReference>,
     pub members: Vec<ClassMember>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ContinueStatement {
     pub keyword: Token,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ExpressionStatement {
     pub expression: Expression,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ForStatement {
+    pub keyword: Token,
     pub initializer: Option<Rc<Statement>>,
     pub condition: Option<Expression>,
     pub update: Option<Expression>,
     pub body: Rc<Statement>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct FunctionStatement {
     pub name: Token,
+    pub type_parameters: Vec<TypeParameter>,
     pub parameters: Vec<FunctionParameter>,
     pub return_type: Option<TypeReference>,
     pub body: Vec<Rc<Statement>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct IfStatement {
+    pub keyword: Token,
     pub condition: Expression,
     pub then_branch: Rc<Statement>,
     pub else_branch: Option<Rc<Statement>>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ProgramStatement {
     pub declarations: Vec<Rc<Statement>>,
 }
 
-#[derive(Debug)]
+impl ProgramStatement {
+    /// Top level declarations in the order they run. Functions and classes
+    /// are hoisted above the other statements, each class after its
+    /// superclass since creating it reads the superclass.
+    pub fn execution_order(&self) -> Vec<&Rc<Statement>> {
+        let classes: HashMap<_, _> = self
+            .declarations
+            .iter()
+            .filter_map(|declaration| match declaration.as_ref() {
+                Statement::Class(class) => Some((class.name.lexme, declaration)),
+                _ => None,
+            })
+            .collect();
+
+        let mut order = Vec::new();
+        let mut rest = Vec::new();
+        for declaration in &self.declarations {
+            match declaration.as_ref() {
+                Statement::Function(_) => order.push(declaration),
+                Statement::Class(_) => {}
+                _ => rest.push(declaration),
+            }
+        }
+        for declaration in &self.declarations {
+            let mut chain = Vec::new();
+            let mut current = Some(declaration);
+            // The resolver rejects inheritance cycles, the check only keeps
+            // them from looping here
+            while let Some(declaration) = current {
+                let class = match declaration.as_ref() {
+                    Statement::Class(class) => class,
+                    _ => break,
+                };
+                if order
+                    .iter()
+                    .chain(&chain)
+                    .any(|seen| Rc::ptr_eq(seen, declaration))
+                {
+                    break;
+                }
+                chain.push(declaration);
+                current = match class.extends.as_ref().map(|extends| &extends.type_name) {
+                    Some(TypeName::Identifier { name }) => classes.get(&name.lexme).copied(),
+                    _ => None,
+                };
+            }
+            order.extend(chain.into_iter().rev());
+        }
+        order.extend(rest);
+        order
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct ReturnStatement {
     pub keyword: Token,
     pub value: Option<Expression>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct VarStatement {
     pub name: Token,
     pub value: Option<Expression>,
-    pub value_type: TypeReference,
+    pub value_type: Option<TypeReference>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct WhileStatement {
+    pub keyword: Token,
     pub condition: Expression,
     pub body: Rc<Statement>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind")]
 #[allow(dead_code)]
 pub enum Statement {
     Block(BlockStatement),
@@ -94,13 +157,39 @@ pub enum Statement {
     While(WhileStatement),
 }
 
-#[derive(Debug)]
+impl Statement {
+    /// Source area identifying the statement: its keyword, declared name or
+    /// expression.
+    pub fn range(&self) -> PosRange {
+        match self {
+            Statement::Block(stmt) => stmt.brace.range(),
+            Statement::Break(stmt) => stmt.keyword.range(),
+            Statement::Class(stmt) => stmt.name.range(),
+            Statement::Continue(stmt) => stmt.keyword.range(),
+            Statement::Expression(stmt) => stmt.expression.range(),
+            Statement::For(stmt) => stmt.keyword.range(),
+            Statement::Function(stmt) => stmt.name.range(),
+            Statement::If(stmt) => stmt.keyword.range(),
+            Statement::Program(stmt) => match stmt.declarations.first() {
+                Some(declaration) => declaration.range(),
+                None => PosRange(Pos::initial(), Pos::initial()),
+            },
+            Statement::Return(stmt) => stmt.keyword.range(),
+            Statement::Var(stmt) => stmt.name.range(),
+            Statement::While(stmt) => stmt.keyword.range(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct FunctionParameter {
     pub name: Token,
     pub value_type: TypeReference,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind")]
+#[allow(clippy::large_enum_variant)]
 pub enum ClassMember {
     Field {
         name: Token,
@@ -109,8 +198,16 @@ pub enum ClassMember {
     },
     Method {
         name: Token,
+        type_parameters: Vec<TypeParameter>,
         parameters: Vec<FunctionParameter>,
         return_type: Option<TypeReference>,
         body: Vec<Rc<Statement>>,
     },
+    /// Member named after its class, run on every new instance once the
+    /// fields are initialized.
+    Constructor {
+        name: Token,
+        parameters: Vec<FunctionParameter>,
+        body: Vec<Rc<Statement>>,
+    },
 }
@@ -1,15 +1,49 @@
 use crate::scanner::token::Token;
+use serde::Serialize;
 use std::rc::Rc;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TypeReference {
     pub type_name: TypeName,
+    pub type_arguments: Vec<TypeReference>,
 }
 
-#[derive(Debug, Clone)]
+/// Generic parameter of a class or function declaration, e.g. `T` or
+/// `T extends Comparable`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TypeParameter {
+    pub name: Token,
+    pub bound: Option<TypeReference>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind")]
 pub enum TypeName {
-    Identifier { name: Token },
-    QualifiedName { left: Rc<TypeName>, right: Token },
+    Identifier {
+        name: Token,
+    },
+    QualifiedName {
+        left: Rc<TypeName>,
+        right: Token,
+    },
+    /// `(int, string) -> bool`
+    Function {
+        paren: Token,
+        parameters: Vec<TypeReference>,
+        return_type: Rc<TypeReference>,
+    },
+    /// `int[]`
+    Array {
+        element: Rc<TypeReference>,
+    },
+    /// `string?`
+    Nullable {
+        inner: Rc<TypeReference>,
+    },
+    /// `int | string`
+    Union {
+        types: Vec<TypeReference>,
+    },
 }
 
 impl TypeName {
@@ -17,6 +51,13 @@ impl TypeName {
         TypeName::Identifier { name }
     }
 
+    pub fn into_reference(self) -> TypeReference {
+        TypeReference {
+            type_name: self,
+            type_arguments: Vec::new(),
+        }
+    }
+
     pub fn push(&self, name: Token) -> Self {
         TypeName::QualifiedName {
             left: Rc::new(self.clone()),
@@ -0,0 +1,166 @@
+use crate::parser::stmt::{ClassMember, ClassStatement};
+use crate::parser::types::TypeName;
+use crate::scanner::token::Token;
+use crate::symbol::Symbol;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MemberKind {
+    Field,
+    Method,
+}
+
+#[derive(Debug)]
+pub struct ClassMembers {
+    pub name: Token,
+    pub superclass: Option<Token>,
+    pub members: Vec<(Symbol, MemberKind)>,
+}
+
+/// Member names of every class in the program, used to resolve `this.x`
+/// and `super.x` accesses.
+#[derive(Debug, Default)]
+pub struct ClassTable {
+    classes: HashMap<Symbol, ClassMembers>,
+}
+
+impl ClassTable {
+    pub fn declare(&mut self, stmt: &ClassStatement) {
+        let superclass = stmt
+            .extends
+            .as_ref()
+            .and_then(|extends| match &extends.type_name {
+                TypeName::Identifier { name } => Some(name.clone()),
+                _ => None,
+            });
+        let members = stmt
+            .members
+            .iter()
+            .filter_map(|member| match member {
+                ClassMember::Field { name, .. } => Some((name.lexme, MemberKind::Field)),
+                ClassMember::Method { name, .. } => Some((name.lexme, MemberKind::Method)),
+                ClassMember::Constructor { .. } => None,
+            })
+            .collect();
+
+        self.classes.insert(
+            stmt.name.lexme,
+            ClassMembers {
+                name: stmt.name.clone(),
+                superclass,
+                members,
+            },
+        );
+    }
+
+    pub fn get(&self, class: Symbol) -> Option<&ClassMembers> {
+        self.classes.get(&class)
+    }
+
+    pub fn superclass(&self, class: Symbol) -> Option<Symbol> {
+        let superclass = self.classes.get(&class)?.superclass.as_ref()?;
+        Some(superclass.lexme)
+    }
+
+    /// Classes from `class` up to the root of its hierarchy. Stops at the
+    /// first unknown class or when the chain loops back on itself.
+    pub fn ancestors(&self, class: Symbol) -> Vec<&ClassMembers> {
+        let mut chain: Vec<&ClassMembers> = Vec::new();
+        let mut current = self.classes.get(&class);
+        while let Some(members) = current {
+            if chain
+                .iter()
+                .any(|visited| visited.name.lexme == members.name.lexme)
+            {
+                break;
+            }
+            chain.push(members);
+            current = members
+                .superclass
+                .as_ref()
+                .and_then(|superclass| self.classes.get(&superclass.lexme));
+        }
+        chain
+    }
+
+    /// Kind of the member `name` of a class or one of its superclasses.
+    pub fn find_member(&self, class: Symbol, name: Symbol) -> Option<MemberKind> {
+        self.ancestors(class).into_iter().find_map(|members| {
+            members
+                .members
+                .iter()
+                .find(|(member, _)| *member == name)
+                .map(|(_, kind)| *kind)
+        })
+    }
+
+    /// Closest member name to a misspelled `name`.
+    pub fn suggest(&self, class: Symbol, name: &str) -> Option<&'static str> {
+        let candidates = self
+            .ancestors(class)
+            .into_iter()
+            .flat_map(|members| members.members.iter().map(|(member, _)| member.as_str()));
+        closest(name, candidates)
+    }
+}
+
+/// Names shorter than this are too short to tell a typo from another name.
+const MIN_SUGGESTION_LENGTH: usize = 3;
+
+/// Picks the candidate with the smallest edit distance to `name`, as long as
+/// it is close enough to plausibly be a typo.
+pub fn closest<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let length = name.chars().count();
+    if length < MIN_SUGGESTION_LENGTH {
+        return None;
+    }
+    let threshold = (length / 3).max(1);
+    candidates
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Optimal string alignment distance between two strings: the Levenshtein
+/// distance where swapping two adjacent characters, the most common typo,
+/// counts as a single edit.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // distances[i][j] is the distance between the first i characters of a
+    // and the first j characters of b
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut distance = (distances[i - 1][j - 1] + cost)
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+/// Formats the "unknown member" message shared by the resolver and checker.
+pub fn unknown_member_message(class: &str, name: &str, suggestion: Option<&str>) -> String {
+    match suggestion {
+        Some(suggestion) => format!(
+            "Unknown member '{}' on class '{}', did you mean '{}'?",
+            name, class, suggestion
+        ),
+        None => format!("Unknown member '{}' on class '{}'", name, class),
+    }
+}
@@ -0,0 +1,670 @@
+use crate::diagnostic::Diagnostic;
+use crate::parser::expr::*;
+use crate::parser::stmt::*;
+use crate::parser::types::TypeName;
+use crate::resolver::members::{unknown_member_message, ClassTable, MemberKind};
+use crate::scanner::token::{Token, TokenType};
+use crate::symbol::Symbol;
+use crate::vm::natives;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+pub mod members;
+
+/// Identity of a syntax tree node, derived from its address. Nodes never move
+/// once the parser has built the tree, so it is stable for a parsed program.
+pub type NodeId = usize;
+
+pub fn node_id<T>(node: &T) -> NodeId {
+    node as *const T as usize
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DeclarationKind {
+    Variable,
+    Parameter,
+    Function,
+    Class,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Location {
+    /// Top level declaration, looked up by name at runtime.
+    Global,
+    /// Stack slot in the frame of the declaring function. Slot 0 of every
+    /// frame is reserved for the callee (or `this` inside methods).
+    Local { slot: usize },
+}
+
+#[derive(Debug)]
+pub struct Declaration {
+    pub name: Token,
+    pub kind: DeclarationKind,
+    pub location: Location,
+    /// Number of functions the declaration is nested in, 0 for top level code.
+    pub function_depth: usize,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// Links a use of a name to the declaration it refers to.
+#[derive(Debug, Clone, Copy)]
+pub struct Binding {
+    pub declaration: usize,
+    pub access: Access,
+    /// Number of function boundaries between the use and the declaration.
+    pub hops: usize,
+}
+
+/// Result of name resolution consumed by later passes.
+#[derive(Debug, Default)]
+pub struct Resolution {
+    pub declarations: Vec<Declaration>,
+    /// Declaring nodes (`VarStatement`, `FunctionParameter`,
+    /// `FunctionStatement`, `ClassStatement`) to their declaration index.
+    pub declared: HashMap<NodeId, usize>,
+    /// `IdentifierExpression` nodes, and `TypeReference` nodes naming a
+    /// superclass, to the declaration they refer to.
+    pub bindings: HashMap<NodeId, Binding>,
+    /// Declarations hiding a declaration with the same name in an enclosing
+    /// scope, to the hidden declaration.
+    pub shadowed: HashMap<usize, usize>,
+    pub classes: ClassTable,
+}
+
+struct Scope {
+    names: HashMap<Symbol, usize>,
+    /// Names declared later in the same block, reading them is an error.
+    pending: HashSet<Symbol>,
+    locals: usize,
+}
+
+impl Scope {
+    fn new() -> Self {
+        Scope {
+            names: HashMap::new(),
+            pending: HashSet::new(),
+            locals: 0,
+        }
+    }
+}
+
+/// Binds every identifier to its declaration and assigns stack slots to
+/// local variables.
+///
+/// Top level functions and classes are visible to the whole program. Every
+/// other name is only visible after its declaration, reading a variable
+/// declared later in the same block is reported instead of silently falling
+/// back to an outer declaration with the same name.
+pub struct Resolver {
+    resolution: Resolution,
+    scopes: Vec<Scope>,
+    /// Next free slot for each function being resolved, the first entry
+    /// belongs to top level code.
+    slots: Vec<usize>,
+    /// Reads of top level variables from function bodies preceding the
+    /// variable declaration, bound once the whole program is resolved.
+    deferred: Vec<(NodeId, Token, Access)>,
+    /// Classes lexically enclosing the code being resolved.
+    enclosing_classes: Vec<Symbol>,
+    /// Whether the innermost function is a constructor, the only place
+    /// `super(...)` can be called.
+    in_constructor: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver {
+            resolution: Resolution::default(),
+            scopes: vec![Scope::new()],
+            slots: vec![1],
+            deferred: Vec::new(),
+            enclosing_classes: Vec::new(),
+            in_constructor: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn resolve(mut self, program: &Statement) -> (Resolution, Vec<Diagnostic>) {
+        if let Statement::Program(program) = program {
+            self.hoist(&program.declarations);
+            self.statements(&program.declarations);
+        } else {
+            self.statement(program);
+        }
+
+        for (node, name, access) in std::mem::take(&mut self.deferred) {
+            match self.scopes[0].names.get(&name.lexme) {
+                Some(index) => {
+                    let binding = Binding {
+                        declaration: *index,
+                        access,
+                        hops: 0,
+                    };
+                    self.resolution.bindings.insert(node, binding);
+                }
+                None => self.error(format!("Undefined name '{}'", name.lexme), &name),
+            }
+        }
+
+        (self.resolution, self.diagnostics)
+    }
+
+    /// Declares top level functions and classes up front and marks the rest
+    /// of the top level names as pending.
+    fn hoist(&mut self, declarations: &[Rc<Statement>]) {
+        for declaration in declarations {
+            match declaration.as_ref() {
+                Statement::Function(stmt) => {
+                    self.declare(node_id(stmt), &stmt.name, DeclarationKind::Function)
+                }
+                Statement::Class(stmt) => {
+                    self.declare(node_id(stmt), &stmt.name, DeclarationKind::Class);
+                    self.resolution.classes.declare(stmt);
+                }
+                Statement::Var(stmt) => {
+                    self.scope().pending.insert(stmt.name.lexme);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn begin_scope(&mut self, declarations: &[Rc<Statement>]) {
+        let mut scope = Scope::new();
+        for declaration in declarations {
+            let name = match declaration.as_ref() {
+                Statement::Var(stmt) => &stmt.name,
+                Statement::Function(stmt) => &stmt.name,
+                Statement::Class(stmt) => &stmt.name,
+                _ => continue,
+            };
+            scope.pending.insert(name.lexme);
+        }
+        self.scopes.push(scope);
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        *self.slots.last_mut().unwrap() -= scope.locals;
+    }
+
+    fn begin_function(&mut self) {
+        self.slots.push(1);
+        self.scopes.push(Scope::new());
+    }
+
+    fn end_function(&mut self) {
+        self.scopes.pop();
+        self.slots.pop();
+    }
+
+    fn declare(&mut self, node: NodeId, name: &Token, kind: DeclarationKind) {
+        if self.scope().names.contains_key(&name.lexme) {
+            self.error(
+                format!("'{}' is already declared in this scope", name.lexme),
+                name,
+            );
+            return;
+        }
+
+        let shadowed = self
+            .scopes
+            .iter()
+            .rev()
+            .skip(1)
+            .find_map(|scope| scope.names.get(&name.lexme))
+            .copied();
+
+        let location = if self.scopes.len() == 1 {
+            Location::Global
+        } else {
+            let slot = self.slots.last_mut().unwrap();
+            let location = Location::Local { slot: *slot };
+            *slot += 1;
+            self.scope().locals += 1;
+            location
+        };
+
+        let index = self.resolution.declarations.len();
+        self.resolution.declarations.push(Declaration {
+            name: name.clone(),
+            kind,
+            location,
+            function_depth: self.slots.len() - 1,
+        });
+        self.resolution.declared.insert(node, index);
+        if let Some(shadowed) = shadowed {
+            self.resolution.shadowed.insert(index, shadowed);
+        }
+
+        let scope = self.scope();
+        scope.pending.remove(&name.lexme);
+        scope.names.insert(name.lexme, index);
+    }
+
+    fn error(&mut self, message: String, token: &Token) {
+        self.diagnostics.push(Diagnostic::error(message, token));
+    }
+
+    fn bind(&mut self, expr: &IdentifierExpression, access: Access) {
+        self.bind_name(node_id(expr), &expr.name, access);
+    }
+
+    fn bind_name(&mut self, node: NodeId, name: &Token, access: Access) {
+        let in_function = self.slots.len() > 1;
+
+        for depth in (0..self.scopes.len()).rev() {
+            let scope = &self.scopes[depth];
+            if let Some(index) = scope.names.get(&name.lexme) {
+                let declaration = &self.resolution.declarations[*index];
+                let hops = match declaration.location {
+                    Location::Global => 0,
+                    Location::Local { .. } => self.slots.len() - 1 - declaration.function_depth,
+                };
+                let binding = Binding {
+                    declaration: *index,
+                    access,
+                    hops,
+                };
+                self.resolution.bindings.insert(node, binding);
+                return;
+            }
+            if scope.pending.contains(&name.lexme) {
+                // Globals are looked up when the function runs, so a function
+                // body may refer to a top level variable declared after it.
+                if depth == 0 && in_function {
+                    self.deferred.push((node, name.clone(), access));
+                } else {
+                    self.error(
+                        format!("'{}' is used before its declaration", name.lexme),
+                        name,
+                    );
+                }
+                return;
+            }
+        }
+
+        // Natives are runtime globals without a declaration
+        if natives::lookup(&name.lexme).is_none() {
+            self.error(format!("Undefined name '{}'", name.lexme), name);
+        }
+    }
+
+    fn statements(&mut self, statements: &[Rc<Statement>]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Block(stmt) => {
+                self.begin_scope(&stmt.declarations);
+                self.statements(&stmt.declarations);
+                self.end_scope();
+            }
+            Statement::Break(_) | Statement::Continue(_) => {}
+            Statement::Class(stmt) => self.class(stmt),
+            Statement::Expression(stmt) => self.expression(&stmt.expression),
+            Statement::For(stmt) => {
+                self.begin_scope(&[]);
+                if let Some(initializer) = &stmt.initializer {
+                    self.statement(initializer);
+                }
+                if let Some(condition) = &stmt.condition {
+                    self.expression(condition);
+                }
+                if let Some(update) = &stmt.update {
+                    self.expression(update);
+                }
+                self.statement(&stmt.body);
+                self.end_scope();
+            }
+            Statement::Function(stmt) => {
+                if self.scopes.len() > 1 {
+                    self.declare(node_id(stmt), &stmt.name, DeclarationKind::Function);
+                }
+                self.function(&stmt.parameters, &stmt.body, false);
+            }
+            Statement::If(stmt) => {
+                self.expression(&stmt.condition);
+                self.statement(&stmt.then_branch);
+                if let Some(else_branch) = &stmt.else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Statement::Program(stmt) => self.statements(&stmt.declarations),
+            Statement::Return(stmt) => {
+                if let Some(value) = &stmt.value {
+                    self.expression(value);
+                }
+            }
+            Statement::Var(stmt) => {
+                // The variable isn't visible in its own initializer
+                if let Some(value) = &stmt.value {
+                    self.expression(value);
+                }
+                self.declare(node_id(stmt), &stmt.name, DeclarationKind::Variable);
+            }
+            Statement::While(stmt) => {
+                self.expression(&stmt.condition);
+                self.statement(&stmt.body);
+            }
+        }
+    }
+
+    fn class(&mut self, stmt: &ClassStatement) {
+        if self.scopes.len() > 1 {
+            self.declare(node_id(stmt), &stmt.name, DeclarationKind::Class);
+            self.resolution.classes.declare(stmt);
+        }
+        self.superclass(stmt);
+
+        self.enclosing_classes.push(stmt.name.lexme);
+
+        let mut constructor = None;
+        let mut names = HashSet::new();
+        for member in &stmt.members {
+            match member {
+                ClassMember::Field { name, value, .. } => {
+                    self.class_member(&mut names, &stmt.name, name);
+                    if let Some(value) = value {
+                        self.begin_function();
+                        self.expression(value);
+                        self.end_function();
+                    }
+                }
+                ClassMember::Method {
+                    name,
+                    parameters,
+                    body,
+                    ..
+                } => {
+                    self.class_member(&mut names, &stmt.name, name);
+                    self.function(parameters, body, false);
+                }
+                ClassMember::Constructor {
+                    name,
+                    parameters,
+                    body,
+                } => {
+                    if constructor.replace(name).is_some() {
+                        self.error(
+                            format!("Class '{}' can only have one constructor", name.lexme),
+                            name,
+                        );
+                    }
+                    self.function(parameters, body, true);
+                }
+            }
+        }
+
+        self.enclosing_classes.pop();
+    }
+
+    /// Reports a field or method of `class` declared twice. Fields and
+    /// methods share one namespace, an instance field would hide a method
+    /// with the same name.
+    fn class_member(&mut self, names: &mut HashSet<Symbol>, class: &Token, name: &Token) {
+        if !names.insert(name.lexme) {
+            self.error(
+                format!(
+                    "'{}' is already declared in class '{}'",
+                    name.lexme, class.lexme
+                ),
+                name,
+            );
+        }
+    }
+
+    fn superclass(&mut self, stmt: &ClassStatement) {
+        let extends = match &stmt.extends {
+            Some(extends) => extends,
+            None => return,
+        };
+        let superclass = match &extends.type_name {
+            TypeName::Identifier { name } => name,
+            _ => {
+                self.error(
+                    format!("Class '{}' can only extend a class", stmt.name.lexme),
+                    &stmt.name,
+                );
+                return;
+            }
+        };
+
+        let classes = &self.resolution.classes;
+        if superclass.lexme == stmt.name.lexme {
+            self.error(
+                format!("Class '{}' can't extend itself", stmt.name.lexme),
+                superclass,
+            );
+        } else if classes.get(superclass.lexme).is_none() {
+            self.error(
+                format!("Unknown superclass '{}'", superclass.lexme),
+                superclass,
+            );
+        } else if classes
+            .ancestors(superclass.lexme)
+            .iter()
+            .any(|ancestor| ancestor.name.lexme == stmt.name.lexme)
+        {
+            self.error(
+                format!(
+                    "Class '{}' inherits from itself through '{}'",
+                    stmt.name.lexme, superclass.lexme
+                ),
+                superclass,
+            );
+        } else {
+            // The superclass is loaded like a variable when the class is
+            // created
+            self.bind_name(node_id(extends), superclass, Access::Read);
+        }
+    }
+
+    /// Resolves `this.name` and `super.name` against the enclosing class.
+    fn member(&mut self, expr: &MemberExpression) {
+        let class = match expr.object.as_ref() {
+            Expression::This(this) => match self.this_class(&this.keyword) {
+                Some(class) => class,
+                None => return,
+            },
+            Expression::Super(keyword) => match self.super_class(&keyword.keyword) {
+                Some(class) => class,
+                None => return,
+            },
+            object => {
+                self.expression(object);
+                return;
+            }
+        };
+
+        let classes = &self.resolution.classes;
+        match classes.find_member(class, expr.name.lexme) {
+            // Fields live on the instance, `super` only reaches methods
+            Some(MemberKind::Field) if matches!(expr.object.as_ref(), Expression::Super(_)) => {
+                self.error(
+                    format!(
+                        "Can't access field '{}' through 'super', use 'this' instead",
+                        expr.name.lexme
+                    ),
+                    &expr.name,
+                );
+            }
+            Some(_) => {}
+            None => {
+                let suggestion = classes.suggest(class, &expr.name.lexme);
+                let message = unknown_member_message(&class, &expr.name.lexme, suggestion);
+                self.error(message, &expr.name);
+            }
+        }
+    }
+
+    /// Checks a `super(...)` call of the superclass constructor.
+    fn super_call(&mut self, callee: &SuperExpression) {
+        if self.super_class(&callee.keyword).is_some() && !self.in_constructor {
+            self.error(
+                String::from("Can't call the superclass constructor outside of a constructor"),
+                &callee.keyword,
+            );
+        }
+    }
+
+    fn this_class(&mut self, keyword: &Token) -> Option<Symbol> {
+        let class = self.enclosing_classes.last().cloned();
+        if class.is_none() {
+            self.error(
+                String::from("Can't use 'this' outside of a method"),
+                keyword,
+            );
+        }
+        class
+    }
+
+    fn super_class(&mut self, keyword: &Token) -> Option<Symbol> {
+        let class = match self.enclosing_classes.last() {
+            Some(class) => *class,
+            None => {
+                self.error(
+                    String::from("Can't use 'super' outside of a method"),
+                    keyword,
+                );
+                return None;
+            }
+        };
+
+        match self.resolution.classes.superclass(class) {
+            Some(superclass) => Some(superclass),
+            None => {
+                self.error(
+                    format!(
+                        "Can't use 'super' in class '{}' without a superclass",
+                        class
+                    ),
+                    keyword,
+                );
+                None
+            }
+        }
+    }
+
+    fn function(
+        &mut self,
+        parameters: &[FunctionParameter],
+        body: &[Rc<Statement>],
+        constructor: bool,
+    ) {
+        let in_constructor = std::mem::replace(&mut self.in_constructor, constructor);
+        self.begin_function();
+        for parameter in parameters {
+            self.declare(
+                node_id(parameter),
+                &parameter.name,
+                DeclarationKind::Parameter,
+            );
+        }
+
+        self.begin_scope(body);
+        self.statements(body);
+        self.end_scope();
+        self.end_function();
+        self.in_constructor = in_constructor;
+    }
+
+    fn expressions(&mut self, expressions: &[Rc<Expression>]) {
+        for expression in expressions {
+            self.expression(expression);
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Array(expr) => self.expressions(&expr.elements),
+            Expression::Assignment(expr) => {
+                self.expression(&expr.right);
+                match expr.left.as_ref() {
+                    Expression::Identifier(target) => {
+                        let access = if expr.operator.token_type == TokenType::Equal {
+                            Access::Write
+                        } else {
+                            Access::ReadWrite
+                        };
+                        self.bind(target, access);
+                    }
+                    target => self.expression(target),
+                }
+            }
+            Expression::Binary(expr) => {
+                self.expression(&expr.left);
+                self.expression(&expr.right);
+            }
+            Expression::Call(expr) => {
+                match expr.callee.as_ref() {
+                    Expression::Super(callee) => self.super_call(callee),
+                    callee => self.expression(callee),
+                }
+                self.expressions(&expr.arguments);
+            }
+            Expression::Conditional(expr) => {
+                self.expression(&expr.condition);
+                self.expression(&expr.then_branch);
+                self.expression(&expr.else_branch);
+            }
+            Expression::Grouping(expr) => self.expression(&expr.expression),
+            Expression::Identifier(expr) => self.bind(expr, Access::Read),
+            Expression::Index(expr) => {
+                self.expression(&expr.object);
+                self.expression(&expr.index);
+            }
+            Expression::LambdaFunction(expr) => self.function(&expr.parameters, &expr.body, false),
+            Expression::Literal(_) => {}
+            Expression::Logical(expr) => {
+                self.expression(&expr.left);
+                self.expression(&expr.right);
+            }
+            Expression::Map(expr) => {
+                for entry in &expr.entries {
+                    match entry {
+                        MapEntry::Pair { key, value } => {
+                            self.expression(key);
+                            self.expression(value);
+                        }
+                        MapEntry::Spread { expression } => self.expression(expression),
+                    }
+                }
+            }
+            Expression::Member(expr) => self.member(expr),
+            Expression::OptionalChain(expr) => self.expression(&expr.expression),
+            Expression::Spread(expr) => self.expression(&expr.expression),
+            Expression::Super(expr) => {
+                if self.super_class(&expr.keyword).is_some() {
+                    self.error(
+                        String::from("Expect '.' and a member name after 'super'"),
+                        &expr.keyword,
+                    );
+                }
+            }
+            Expression::This(expr) => {
+                self.this_class(&expr.keyword);
+            }
+            Expression::Tuple(expr) => self.expressions(&expr.elements),
+            Expression::Unary(expr) => self.expression(&expr.right),
+            Expression::Update(expr) => match expr.expression.as_ref() {
+                Expression::Identifier(target) => self.bind(target, Access::ReadWrite),
+                target => self.expression(target),
+            },
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::scanner::pos::{Pos, PosRange};
 use crate::scanner::token::*;
+use crate::symbol::Symbol;
 use std::borrow::Borrow;
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
@@ -91,7 +92,16 @@ impl Scanner {
             ':' => Ok(self.make_token(TokenType::Colon)),
             ';' => Ok(self.make_token(TokenType::Semicolon)),
             ',' => Ok(self.make_token(TokenType::Comma)),
-            '.' => Ok(self.make_token(TokenType::Dot)),
+            '.' => {
+                if self.peek() == '.' && self.peek_next() == '.' {
+                    self.advance();
+                    self.advance();
+                    Ok(self.make_token(TokenType::DotDotDot))
+                } else {
+                    Ok(self.make_token(TokenType::Dot))
+                }
+            }
+            '~' => Ok(self.make_token(TokenType::Tilde)),
 
             '!' => {
                 if self.matches('=') {
@@ -108,14 +118,26 @@ impl Scanner {
                 }
             }
             '<' => {
-                if self.matches('=') {
+                if self.matches('<') {
+                    if self.matches('=') {
+                        Ok(self.make_token(TokenType::LessLessEqual))
+                    } else {
+                        Ok(self.make_token(TokenType::LessLess))
+                    }
+                } else if self.matches('=') {
                     Ok(self.make_token(TokenType::LessEqual))
                 } else {
                     Ok(self.make_token(TokenType::Less))
                 }
             }
             '>' => {
-                if self.matches('=') {
+                if self.matches('>') {
+                    if self.matches('=') {
+                        Ok(self.make_token(TokenType::GreaterGreaterEqual))
+                    } else {
+                        Ok(self.make_token(TokenType::GreaterGreater))
+                    }
+                } else if self.matches('=') {
                     Ok(self.make_token(TokenType::GreaterEqual))
                 } else {
                     Ok(self.make_token(TokenType::Greater))
@@ -127,6 +149,8 @@ impl Scanner {
                     Ok(self.make_token(TokenType::MinusMinus))
                 } else if self.matches('=') {
                     Ok(self.make_token(TokenType::MinusEqual))
+                } else if self.matches('>') {
+                    Ok(self.make_token(TokenType::Arrow))
                 } else {
                     Ok(self.make_token(TokenType::Minus))
                 }
@@ -141,19 +165,66 @@ impl Scanner {
                 }
             }
             '/' => {
-                if self.matches('/') {
+                if self.matches('=') {
                     Ok(self.make_token(TokenType::SlashEqual))
                 } else {
                     Ok(self.make_token(TokenType::Slash))
                 }
             }
             '*' => {
-                if self.matches('/') {
+                if self.matches('*') {
+                    if self.matches('=') {
+                        Ok(self.make_token(TokenType::StarStarEqual))
+                    } else {
+                        Ok(self.make_token(TokenType::StarStar))
+                    }
+                } else if self.matches('=') {
                     Ok(self.make_token(TokenType::StarEqual))
                 } else {
                     Ok(self.make_token(TokenType::Star))
                 }
             }
+            '?' => {
+                if self.matches('?') {
+                    if self.matches('=') {
+                        Ok(self.make_token(TokenType::QuestionQuestionEqual))
+                    } else {
+                        Ok(self.make_token(TokenType::QuestionQuestion))
+                    }
+                } else if self.matches('.') {
+                    Ok(self.make_token(TokenType::QuestionDot))
+                } else {
+                    Ok(self.make_token(TokenType::Question))
+                }
+            }
+            '%' => {
+                if self.matches('=') {
+                    Ok(self.make_token(TokenType::PercentEqual))
+                } else {
+                    Ok(self.make_token(TokenType::Percent))
+                }
+            }
+            '&' => {
+                if self.matches('=') {
+                    Ok(self.make_token(TokenType::AmpersandEqual))
+                } else {
+                    Ok(self.make_token(TokenType::Ampersand))
+                }
+            }
+            '|' => {
+                if self.matches('=') {
+                    Ok(self.make_token(TokenType::PipeEqual))
+                } else {
+                    Ok(self.make_token(TokenType::Pipe))
+                }
+            }
+            '^' => {
+                if self.matches('=') {
+                    Ok(self.make_token(TokenType::CaretEqual))
+                } else {
+                    Ok(self.make_token(TokenType::Caret))
+                }
+            }
 
             '"' => self.string(),
 
@@ -184,7 +255,7 @@ impl Scanner {
             self.advance();
         }
 
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance();
 
             while self.peek().is_ascii_digit() {
@@ -242,11 +313,9 @@ impl Scanner {
                             '\n' => {
                                 self.new_line();
                             }
-                            '*' => {
-                                if self.peek() == '/' {
-                                    self.advance();
-                                    break;
-                                }
+                            '*' if self.peek() == '/' => {
+                                self.advance();
+                                break;
                             }
                             _ => {}
                         }
@@ -313,7 +382,7 @@ impl Scanner {
     fn make_token_int(&self, token_type: TokenType, literal: Option<Literal>) -> Token {
         Token {
             token_type,
-            lexme: String::from(self.current_lexme()),
+            lexme: Symbol::intern(self.current_lexme()),
             start_pos: self.start_pos,
             end_pos: self.current_pos,
             literal,
@@ -1,6 +1,7 @@
+use serde::Serialize;
 use std::fmt::{Display, Formatter};
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
 pub struct Pos {
     pub row: usize,
     pub col: usize,
@@ -33,6 +34,7 @@ impl Display for Pos {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct PosRange(pub(crate) Pos, pub(crate) Pos);
 
 impl PosRange {
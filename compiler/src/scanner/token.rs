@@ -1,7 +1,9 @@
 use crate::scanner::pos::*;
+use crate::symbol::Symbol;
 use phf::phf_map;
+use serde::Serialize;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub enum Literal {
     Null,
     Boolean(bool),
@@ -10,10 +12,10 @@ pub enum Literal {
     String(String),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Token {
     pub token_type: TokenType,
-    pub lexme: String,
+    pub lexme: Symbol,
     pub start_pos: Pos,
     pub end_pos: Pos,
     pub literal: Option<Literal>,
@@ -25,7 +27,8 @@ impl Token {
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Serialize)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum TokenType {
     // Single-character tokens.
     LeftParen = 1,
@@ -36,28 +39,49 @@ pub enum TokenType {
     RightSquare,
     Comma,
     Dot,
+    DotDotDot,
     Colon,
     Semicolon,
+    Tilde,
+    Arrow,
+    QuestionDot,
 
-    // One or two character tokens.
+    // One, two or three character tokens.
+    Ampersand,
+    AmpersandEqual,
     Bang,
     BangEqual,
+    Caret,
+    CaretEqual,
     Equal,
     EqualEqual,
     Greater,
     GreaterEqual,
+    GreaterGreater,
+    GreaterGreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    LessLessEqual,
     Minus,
     MinusEqual,
     MinusMinus,
+    Percent,
+    PercentEqual,
+    Pipe,
+    PipeEqual,
     Plus,
     PlusEqual,
     PlusPlus,
+    Question,
+    QuestionQuestion,
+    QuestionQuestionEqual,
     Slash,
     SlashEqual,
     Star,
     StarEqual,
+    StarStar,
+    StarStarEqual,
 
     // Literals.
     Identifier,
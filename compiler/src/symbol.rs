@@ -0,0 +1,83 @@
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::ops::Deref;
+use std::sync::{LazyLock, Mutex};
+
+/// Interned string. Symbols of equal strings are equal, so comparing and
+/// hashing them doesn't look at the text.
+#[derive(Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Symbol(u32);
+
+/// Strings interned so far. They live as long as the process, symbols
+/// index into `strings`.
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+static INTERNER: LazyLock<Mutex<Interner>> = LazyLock::new(Mutex::default);
+
+impl Symbol {
+    pub fn intern(string: &str) -> Symbol {
+        let mut interner = INTERNER.lock().unwrap();
+        if let Some(symbol) = interner.symbols.get(string) {
+            return *symbol;
+        }
+        let string: &'static str = Box::leak(string.into());
+        let symbol = Symbol(interner.strings.len() as u32);
+        interner.strings.push(string);
+        interner.symbols.insert(string, symbol);
+        symbol
+    }
+
+    pub fn as_str(self) -> &'static str {
+        INTERNER.lock().unwrap().strings[self.0 as usize]
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(string: &str) -> Self {
+        Symbol::intern(string)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self.as_str(), f)
+    }
+}
+
+/// Shows the quoted text, like a `String` does.
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
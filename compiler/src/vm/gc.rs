@@ -0,0 +1,171 @@
+use crate::symbol::Symbol;
+use crate::vm::heap::Heap;
+use crate::vm::value::{ObjRef, Object, Upvalue, Value};
+use std::fmt::{Display, Formatter};
+use std::mem::size_of;
+use std::time::{Duration, Instant};
+
+/// Heap size below which the collector never runs.
+const MIN_HEAP: usize = 1024 * 1024;
+
+/// Tuning of the garbage collector.
+#[derive(Debug, Clone, Copy)]
+pub struct GcConfig {
+    /// The next collection happens once the heap grows to this many times
+    /// the size that survived the last one.
+    pub growth_factor: f64,
+    /// Collects before every instruction following an allocation, to flush
+    /// out objects the VM forgets to keep reachable.
+    pub stress: bool,
+}
+
+impl GcConfig {
+    /// Heap size triggering the collection after one that left `live`
+    /// bytes.
+    pub fn threshold(&self, live: usize) -> usize {
+        if self.stress {
+            live
+        } else {
+            MIN_HEAP.max((live as f64 * self.growth_factor) as usize)
+        }
+    }
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig {
+            growth_factor: 2.0,
+            stress: false,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GcStats {
+    pub collections: usize,
+    pub objects_freed: usize,
+    pub bytes_freed: usize,
+    /// Largest the heap got, in bytes.
+    pub peak_bytes: usize,
+    pub time: Duration,
+}
+
+impl Display for GcStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} collection(s) in {:.3}ms, {} object(s) and {} byte(s) freed, peak heap {} byte(s)",
+            self.collections,
+            self.time.as_secs_f64() * 1000.0,
+            self.objects_freed,
+            self.bytes_freed,
+            self.peak_bytes
+        )
+    }
+}
+
+impl Object {
+    /// Bytes the object takes up, counting what it owns but not the objects
+    /// it refers to.
+    pub fn size(&self) -> usize {
+        let owned = match self {
+            Object::String(string) => string.len(),
+            Object::Array(elements) | Object::Tuple(elements) => {
+                elements.len() * size_of::<Value>()
+            }
+            Object::Map(entries) => entries.len() * size_of::<(Value, Value)>(),
+            Object::Closure(closure) => closure.upvalues.len() * size_of::<ObjRef>(),
+            Object::Class(class) => {
+                class.name.len()
+                    + class.fields.len() * size_of::<(Symbol, Value)>()
+                    + class.methods.len() * size_of::<(Symbol, ObjRef)>()
+            }
+            Object::Instance(instance) => instance.fields.len() * size_of::<(Symbol, Value)>(),
+            Object::Upvalue(_) | Object::Native(_) | Object::BoundMethod(_) => 0,
+        };
+        size_of::<Object>() + owned
+    }
+
+    /// Objects this one keeps alive.
+    fn references(&self, references: &mut Vec<ObjRef>) {
+        match self {
+            Object::String(_) | Object::Native(_) | Object::Upvalue(Upvalue::Open(_)) => {}
+            Object::Array(elements) | Object::Tuple(elements) => {
+                references.extend(elements.iter().filter_map(|value| value.as_object()));
+            }
+            Object::Map(entries) => {
+                let values = entries.iter().flat_map(|(key, value)| [*key, *value]);
+                references.extend(values.filter_map(Value::as_object));
+            }
+            Object::Upvalue(Upvalue::Closed(value)) => references.extend(value.as_object()),
+            Object::Closure(closure) => {
+                references.extend(&closure.upvalues);
+                references.extend(closure.class);
+            }
+            Object::Class(class) => {
+                references.extend(class.superclass);
+                references.extend(class.methods.values());
+                references.extend(class.constructor);
+                references.extend(
+                    class
+                        .fields
+                        .iter()
+                        .filter_map(|(_, value)| value.as_object()),
+                );
+            }
+            Object::Instance(instance) => {
+                references.push(instance.class);
+                references.extend(
+                    instance
+                        .fields
+                        .values()
+                        .filter_map(|value| value.as_object()),
+                );
+            }
+            Object::BoundMethod(bound) => {
+                references.push(bound.method);
+                references.extend(bound.receiver.as_object());
+            }
+        }
+    }
+}
+
+impl Heap {
+    /// Whether the heap grew enough since the last collection to collect
+    /// again.
+    pub fn should_collect(&self) -> bool {
+        self.bytes_allocated > self.next_gc
+    }
+
+    /// Frees every object not reachable from `roots`. Freed handles get
+    /// reused, so the caller must drop any handle it keeps outside of them.
+    pub fn collect(&mut self, roots: impl IntoIterator<Item = ObjRef>) {
+        let start = Instant::now();
+        let mut marked = vec![false; self.objects.len()];
+        let mut gray: Vec<ObjRef> = roots.into_iter().collect();
+        while let Some(object) = gray.pop() {
+            let mark = &mut marked[object.0 as usize];
+            if !*mark {
+                *mark = true;
+                self.get(object).references(&mut gray);
+            }
+        }
+
+        let mut live = 0;
+        for (index, slot) in self.objects.iter_mut().enumerate() {
+            let Some(object) = slot else { continue };
+            if marked[index] {
+                live += object.size();
+            } else {
+                self.stats.objects_freed += 1;
+                *slot = None;
+                self.free.push(index as u32);
+            }
+        }
+        self.stats.bytes_freed += self.bytes_allocated.saturating_sub(live);
+        self.stats.collections += 1;
+        self.stats.time += start.elapsed();
+        self.bytes_allocated = live;
+        self.next_gc = self.gc.threshold(live);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::vm::gc::{GcConfig, GcStats};
+use crate::vm::value::{Class, Closure, ObjRef, Object, Value};
+
+/// Storage of every object the VM allocates. Objects are referred to by
+/// handle, freed entries are reused by later allocations.
+#[derive(Debug)]
+pub struct Heap {
+    pub(super) objects: Vec<Option<Object>>,
+    pub(super) free: Vec<u32>,
+    pub(super) gc: GcConfig,
+    pub stats: GcStats,
+    /// Size of the objects allocated, as of the last collection for the
+    /// ones that survived it.
+    pub(super) bytes_allocated: usize,
+    pub(super) next_gc: usize,
+}
+
+impl Heap {
+    pub fn new(gc: GcConfig) -> Self {
+        Heap {
+            objects: Vec::new(),
+            free: Vec::new(),
+            gc,
+            stats: GcStats::default(),
+            bytes_allocated: 0,
+            next_gc: gc.threshold(0),
+        }
+    }
+
+    pub fn alloc(&mut self, object: Object) -> ObjRef {
+        self.bytes_allocated += object.size();
+        self.stats.peak_bytes = self.stats.peak_bytes.max(self.bytes_allocated);
+        match self.free.pop() {
+            Some(index) => {
+                self.objects[index as usize] = Some(object);
+                ObjRef(index)
+            }
+            None => {
+                self.objects.push(Some(object));
+                ObjRef(self.objects.len() as u32 - 1)
+            }
+        }
+    }
+
+    pub fn get(&self, object: ObjRef) -> &Object {
+        self.objects[object.0 as usize]
+            .as_ref()
+            .expect("use of a freed object")
+    }
+
+    pub fn get_mut(&mut self, object: ObjRef) -> &mut Object {
+        self.objects[object.0 as usize]
+            .as_mut()
+            .expect("use of a freed object")
+    }
+
+    pub fn string(&self, value: Value) -> Option<&str> {
+        match value {
+            Value::Object(object) => match self.get(object) {
+                Object::String(string) => Some(string),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Name of the value's type in error messages, instances go by the
+    /// name of their class.
+    pub fn type_name(&self, value: Value) -> &str {
+        match value {
+            Value::Null => "null",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Object(object) => match self.get(object) {
+                Object::Instance(instance) => self.class(instance.class).name.as_str(),
+                object => object.type_name(),
+            },
+        }
+    }
+
+    pub fn class(&self, class: ObjRef) -> &Class {
+        match self.get(class) {
+            Object::Class(class) => class,
+            object => panic!("expected a class, got {:?}", object),
+        }
+    }
+
+    pub fn closure(&self, closure: ObjRef) -> &Closure {
+        match self.get(closure) {
+            Object::Closure(closure) => closure,
+            object => panic!("expected a closure, got {:?}", object),
+        }
+    }
+
+    /// Strings and numbers compare by value, other objects by identity.
+    pub fn values_equal(&self, a: Value, b: Value) -> bool {
+        match (a, b) {
+            (Value::Null, Value::Null) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::Int(a), Value::Float(b)) | (Value::Float(b), Value::Int(a)) => a as f64 == b,
+            (Value::Object(x), Value::Object(y)) => {
+                x == y
+                    || matches!(
+                        (self.get(x), self.get(y)),
+                        (Object::String(a), Object::String(b)) if a == b
+                    )
+            }
+            _ => false,
+        }
+    }
+
+    /// Text `print` and string concatenation produce for a value.
+    pub fn display(&self, value: Value) -> String {
+        let mut text = String::new();
+        self.write_value(&mut text, value, false, &mut Vec::new());
+        text
+    }
+
+    /// Writes `value` into `text`. Strings nested in collections are quoted,
+    /// `enclosing` holds the collections being written to cut cycles short.
+    fn write_value(
+        &self,
+        text: &mut String,
+        value: Value,
+        quoted: bool,
+        enclosing: &mut Vec<ObjRef>,
+    ) {
+        let object = match value {
+            Value::Null => return text.push_str("null"),
+            Value::Bool(value) => return text.push_str(&value.to_string()),
+            Value::Int(value) => return text.push_str(&value.to_string()),
+            Value::Float(value) => return text.push_str(&format!("{:?}", value)),
+            Value::Object(object) => object,
+        };
+
+        let (open, close, elements) = match self.get(object) {
+            Object::String(string) if quoted => return text.push_str(&format!("{:?}", string)),
+            Object::String(string) => return text.push_str(string),
+            Object::Closure(closure) => {
+                return text.push_str(&format!("<fun {}>", closure.function.name))
+            }
+            Object::Native(native) => return text.push_str(&format!("<native {}>", native.name)),
+            Object::Class(class) => return text.push_str(&format!("<class {}>", class.name)),
+            Object::Upvalue(_) => return text.push_str("<upvalue>"),
+            Object::Instance(instance) => {
+                let class = &self.class(instance.class).name;
+                return text.push_str(&format!("<{} instance>", class));
+            }
+            Object::BoundMethod(bound) => {
+                let name = &self.closure(bound.method).function.name;
+                return text.push_str(&format!("<fun {}>", name));
+            }
+            Object::Array(elements) => ("[", "]", elements),
+            Object::Tuple(elements) => ("(", ")", elements),
+            Object::Map(entries) => {
+                if enclosing.contains(&object) {
+                    return text.push_str("{...}");
+                }
+                enclosing.push(object);
+                text.push('{');
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        text.push_str(", ");
+                    }
+                    self.write_value(text, *key, true, enclosing);
+                    text.push_str(": ");
+                    self.write_value(text, *value, true, enclosing);
+                }
+                text.push('}');
+                enclosing.pop();
+                return;
+            }
+        };
+
+        if enclosing.contains(&object) {
+            text.push_str(open);
+            text.push_str("...");
+            text.push_str(close);
+            return;
+        }
+        enclosing.push(object);
+        text.push_str(open);
+        for (i, element) in elements.iter().enumerate() {
+            if i > 0 {
+                text.push_str(", ");
+            }
+            self.write_value(text, *element, true, enclosing);
+        }
+        text.push_str(close);
+        enclosing.pop();
+    }
+}
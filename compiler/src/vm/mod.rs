@@ -0,0 +1,888 @@
+use crate::bytecode::chunk::{Constant, Function, OpCode, UpvalueKind};
+use crate::scanner::pos::Pos;
+use crate::symbol::Symbol;
+use crate::vm::gc::GcConfig;
+use crate::vm::heap::Heap;
+use crate::vm::natives::NATIVES;
+use crate::vm::value::{BoundMethod, Class, Closure, Instance, ObjRef, Object, Upvalue, Value};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::rc::Rc;
+
+pub mod gc;
+pub mod heap;
+pub mod natives;
+pub mod ops;
+pub mod value;
+
+/// Maximum call depth, deeper calls stop the program with a stack overflow.
+pub(crate) const FRAMES_MAX: usize = 1024;
+
+/// Error stopping the program.
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub message: String,
+    /// Function name and source position of every active call, innermost
+    /// first.
+    pub trace: Vec<(String, Pos)>,
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        // Runaway recursion repeats the same frame up to the frame limit
+        for frames in self.trace.chunk_by(|a, b| a == b) {
+            let (function, pos) = &frames[0];
+            write!(f, "\n    in {} at {}", function, pos)?;
+            if frames.len() > 1 {
+                write!(f, "\n    ... repeated {} more times", frames.len() - 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct CallFrame {
+    closure: ObjRef,
+    function: Rc<Function>,
+    ip: usize,
+    /// Stack index of slot 0 of the frame.
+    base: usize,
+    /// Field of `this` a field initializer frame stores its result into.
+    field: Option<Symbol>,
+}
+
+/// Stack based virtual machine executing compiled chunks.
+pub struct Vm {
+    pub heap: Heap,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<Symbol, Value>,
+    /// String constants the program loaded. Loading one again reuses its
+    /// object, so equal constants compare by handle.
+    strings: HashMap<Symbol, ObjRef>,
+    /// Upvalues still pointing into the stack, ordered by slot.
+    open_upvalues: Vec<ObjRef>,
+    /// Class and method found by the last `Invoke` of every call site, keyed
+    /// by function and instruction offset. Calls on instances of the same
+    /// class skip the lookup.
+    method_cache: HashMap<(*const Function, usize), (ObjRef, ObjRef)>,
+    /// Where `print` writes to.
+    pub output: Box<dyn Write>,
+}
+
+impl Vm {
+    pub fn new(output: Box<dyn Write>, gc: GcConfig) -> Self {
+        let mut vm = Vm {
+            heap: Heap::new(gc),
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: HashMap::new(),
+            strings: HashMap::new(),
+            open_upvalues: Vec::new(),
+            method_cache: HashMap::new(),
+            output,
+        };
+        for native in NATIVES {
+            let value = Value::Object(vm.heap.alloc(Object::Native(native)));
+            vm.globals.insert(Symbol::intern(native.name), value);
+        }
+        vm
+    }
+
+    /// Runs the top level code of a compiled program.
+    pub fn run(&mut self, script: Rc<Function>) -> Result<(), RuntimeError> {
+        let closure = self.heap.alloc(Object::Closure(Closure {
+            function: script,
+            upvalues: Vec::new(),
+            class: None,
+        }));
+        self.call_value(Value::Object(closure), &[]).map(|_| ())
+    }
+
+    /// Calls the program's `main` function, if it declares one. A `main`
+    /// taking a parameter receives the command line arguments joined by
+    /// spaces.
+    pub fn call_main(&mut self, args: &[String]) -> Result<Value, RuntimeError> {
+        let main = match self.globals.get(&Symbol::from("main")) {
+            Some(main) => *main,
+            None => return Ok(Value::Null),
+        };
+        let takes_args = match main.as_object().map(|main| self.heap.get(main)) {
+            Some(Object::Closure(closure)) => closure.function.arity > 0,
+            _ => false,
+        };
+        if takes_args {
+            let args = self.heap.alloc(Object::String(args.join(" ")));
+            self.call_value(main, &[Value::Object(args)])
+        } else {
+            self.call_value(main, &[])
+        }
+    }
+
+    /// Calls `callee` and runs until it returns.
+    fn call_value(&mut self, callee: Value, arguments: &[Value]) -> Result<Value, RuntimeError> {
+        let depth = self.frames.len();
+        self.push(callee);
+        self.stack.extend_from_slice(arguments);
+        if let Err(message) = self.call(arguments.len()) {
+            return Err(self.error(message));
+        }
+        // Natives complete without a frame
+        if self.frames.len() == depth {
+            return Ok(self.pop());
+        }
+        self.execute(depth)
+    }
+
+    /// Collects the objects unreachable from the stack, the globals, the
+    /// string constants, the running closures and the open upvalues.
+    fn collect_garbage(&mut self) {
+        let stack = self.stack.iter().filter_map(|value| value.as_object());
+        let globals = self.globals.values().filter_map(|value| value.as_object());
+        let frames = self.frames.iter().map(|frame| frame.closure);
+        let strings = self.strings.values().copied();
+        let roots: Vec<ObjRef> = stack
+            .chain(globals)
+            .chain(strings)
+            .chain(frames)
+            .chain(self.open_upvalues.iter().copied())
+            .collect();
+        self.heap.collect(roots);
+        // Freed handles get reused by other classes and methods
+        self.method_cache.clear();
+    }
+
+    /// Builds the error for the current instruction and unwinds the stack.
+    fn error(&mut self, message: String) -> RuntimeError {
+        let trace = self
+            .frames
+            .iter()
+            .rev()
+            .map(|frame| {
+                let pos = frame.function.chunk.pos_at(frame.ip.saturating_sub(1));
+                (frame.function.name.clone(), pos)
+            })
+            .collect();
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+        RuntimeError { message, trace }
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("stack underflow")
+    }
+
+    fn peek(&self, distance: usize) -> Value {
+        self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn frame(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame();
+        let byte = frame.function.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let frame = self.frame();
+        let value = frame.function.chunk.read_u16(frame.ip);
+        frame.ip += 2;
+        value
+    }
+
+    fn read_constant(&mut self) -> Constant {
+        let index = self.read_u16() as usize;
+        self.frame().function.chunk.constants[index].clone()
+    }
+
+    fn read_name(&mut self) -> Symbol {
+        let index = self.read_u16();
+        self.name_at(index)
+    }
+
+    fn name_at(&mut self, index: u16) -> Symbol {
+        match &self.frame().function.chunk.constants[index as usize] {
+            Constant::String(name) => *name,
+            constant => panic!("expected a name constant, got {:?}", constant),
+        }
+    }
+
+    fn execute(&mut self, depth: usize) -> Result<Value, RuntimeError> {
+        loop {
+            // Between instructions every live object is reachable from the
+            // roots
+            if self.heap.should_collect() {
+                self.collect_garbage();
+            }
+            match self.step(depth) {
+                Ok(Some(result)) => return Ok(result),
+                Ok(None) => {}
+                Err(message) => return Err(self.error(message)),
+            }
+        }
+    }
+
+    /// Executes one instruction. Returns the result once the frame at
+    /// `depth` returns.
+    fn step(&mut self, depth: usize) -> Result<Option<Value>, String> {
+        let byte = self.read_byte();
+        let op = OpCode::from_byte(byte).ok_or_else(|| format!("Unknown opcode {}", byte))?;
+
+        match op {
+            OpCode::Constant => {
+                let value = match self.read_constant() {
+                    Constant::Int(value) => Value::Int(value),
+                    Constant::Float(value) => Value::Float(value),
+                    Constant::String(value) => Value::Object(self.string_constant(value)),
+                    Constant::Function(function) => {
+                        let closure = Closure {
+                            function,
+                            upvalues: Vec::new(),
+                            class: None,
+                        };
+                        Value::Object(self.heap.alloc(Object::Closure(closure)))
+                    }
+                };
+                self.push(value);
+            }
+            OpCode::Null => self.push(Value::Null),
+            OpCode::True => self.push(Value::Bool(true)),
+            OpCode::False => self.push(Value::Bool(false)),
+            OpCode::Pop => {
+                self.pop();
+            }
+            OpCode::Dup => self.push(self.peek(0)),
+            OpCode::Dup2 => {
+                self.push(self.peek(1));
+                self.push(self.peek(1));
+            }
+            OpCode::Swap => {
+                let top = self.stack.len() - 1;
+                self.stack.swap(top, top - 1);
+            }
+            OpCode::GetLocal => {
+                let slot = self.read_byte() as usize;
+                let base = self.frame().base;
+                self.push(self.stack[base + slot]);
+            }
+            OpCode::SetLocal => {
+                let slot = self.read_byte() as usize;
+                let base = self.frame().base;
+                self.stack[base + slot] = self.peek(0);
+            }
+            OpCode::GetGlobal => {
+                let name = self.read_name();
+                match self.globals.get(&name) {
+                    Some(value) => self.push(*value),
+                    None => return Err(format!("Undefined variable '{}'", name)),
+                }
+            }
+            OpCode::SetGlobal => {
+                let name = self.read_name();
+                let value = self.peek(0);
+                match self.globals.get_mut(&name) {
+                    Some(global) => *global = value,
+                    None => return Err(format!("Undefined variable '{}'", name)),
+                }
+            }
+            OpCode::DefineGlobal => {
+                let name = self.read_name();
+                let value = self.pop();
+                self.globals.insert(name, value);
+            }
+            OpCode::GetUpvalue => {
+                let index = self.read_byte() as usize;
+                let value = match self.heap.get(self.upvalue(index)) {
+                    Object::Upvalue(Upvalue::Open(slot)) => self.stack[*slot],
+                    Object::Upvalue(Upvalue::Closed(value)) => *value,
+                    object => panic!("expected an upvalue, got {:?}", object),
+                };
+                self.push(value);
+            }
+            OpCode::SetUpvalue => {
+                let index = self.read_byte() as usize;
+                let value = self.peek(0);
+                match self.heap.get_mut(self.upvalue(index)) {
+                    Object::Upvalue(Upvalue::Open(slot)) => {
+                        let slot = *slot;
+                        self.stack[slot] = value;
+                    }
+                    Object::Upvalue(upvalue) => *upvalue = Upvalue::Closed(value),
+                    object => panic!("expected an upvalue, got {:?}", object),
+                }
+            }
+            OpCode::CloseUpvalue => {
+                self.close_upvalues(self.stack.len() - 1);
+                self.pop();
+            }
+            OpCode::GetProperty => {
+                let name = self.read_name();
+                let object = self.pop();
+                let value = self.property(object, name)?;
+                self.push(value);
+            }
+            OpCode::SetProperty => {
+                let name = self.read_name();
+                let value = self.pop();
+                let object = self.pop();
+                self.set_property(object, name, value)?;
+                self.push(value);
+            }
+            OpCode::GetSuper => {
+                let name = self.read_name();
+                let receiver = self.pop();
+                let method = self.super_method(name)?;
+                let bound = self
+                    .heap
+                    .alloc(Object::BoundMethod(BoundMethod { receiver, method }));
+                self.push(Value::Object(bound));
+            }
+            OpCode::GetIndex => {
+                let index = self.pop();
+                let object = self.pop();
+                let value = ops::get_index(&mut self.heap, object, index)?;
+                self.push(value);
+            }
+            OpCode::SetIndex => {
+                let value = self.pop();
+                let index = self.pop();
+                let object = self.pop();
+                ops::set_index(&mut self.heap, object, index, value)?;
+                self.push(value);
+            }
+            OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Modulo
+            | OpCode::Power
+            | OpCode::BitAnd
+            | OpCode::BitOr
+            | OpCode::BitXor
+            | OpCode::ShiftLeft
+            | OpCode::ShiftRight => {
+                let b = self.pop();
+                let a = self.pop();
+                let value = ops::binary(&mut self.heap, op, a, b)?;
+                self.push(value);
+            }
+            OpCode::Negate | OpCode::Not | OpCode::BitNot => {
+                let value = self.pop();
+                self.push(ops::unary(&self.heap, op, value)?);
+            }
+            OpCode::Jump => {
+                let offset = self.read_u16() as usize;
+                self.frame().ip += offset;
+            }
+            OpCode::JumpIfFalse | OpCode::JumpIfNull | OpCode::JumpIfNotNull => {
+                let offset = self.read_u16() as usize;
+                let value = self.peek(0);
+                let jump = match op {
+                    OpCode::JumpIfFalse => value.is_falsey(),
+                    OpCode::JumpIfNull => matches!(value, Value::Null),
+                    _ => !matches!(value, Value::Null),
+                };
+                if jump {
+                    self.frame().ip += offset;
+                }
+            }
+            OpCode::Loop => {
+                let offset = self.read_u16() as usize;
+                self.frame().ip -= offset;
+            }
+            OpCode::Call => {
+                let count = self.read_byte() as usize;
+                self.call(count)?;
+            }
+            OpCode::CallSpread => {
+                let arguments = self.pop();
+                let arguments = ops::spread_elements(&self.heap, arguments)?;
+                let count = arguments.len();
+                self.stack.extend(arguments);
+                self.call(count)?;
+            }
+            OpCode::Invoke => {
+                let site = self.frame().ip - 1;
+                let name = self.read_u16();
+                let count = self.read_byte() as usize;
+                self.invoke(site, name, count)?;
+            }
+            OpCode::SuperInvoke => {
+                let name = self.read_name();
+                let count = self.read_byte() as usize;
+                let method = self.super_method(name)?;
+                self.call_closure(method, count, None)?;
+            }
+            OpCode::SuperCall => {
+                let count = self.read_byte() as usize;
+                let superclass = self.superclass()?;
+                // Without a constructor `this` stays in place as the result
+                match self.heap.class(superclass).constructor {
+                    Some(constructor) => self.call_closure(constructor, count, None)?,
+                    None => check_arity(0, count)?,
+                }
+            }
+            OpCode::Closure => {
+                let function = match self.read_constant() {
+                    Constant::Function(function) => function,
+                    constant => panic!("expected a function constant, got {:?}", constant),
+                };
+                let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                for _ in 0..function.upvalue_count {
+                    let kind = self.read_byte();
+                    let index = self.read_byte() as usize;
+                    let upvalue = match UpvalueKind::from_byte(kind) {
+                        Some(UpvalueKind::Enclosing) => self.upvalue(index),
+                        Some(UpvalueKind::Local) => {
+                            let slot = self.frame().base + index;
+                            self.capture_upvalue(slot)
+                        }
+                        Some(UpvalueKind::Copy) => {
+                            let slot = self.frame().base + index;
+                            let value = self.stack[slot];
+                            self.heap.alloc(Object::Upvalue(Upvalue::Closed(value)))
+                        }
+                        None => panic!("invalid upvalue kind {}", kind),
+                    };
+                    upvalues.push(upvalue);
+                }
+                // Closures created by methods keep seeing their class
+                let class = self.current_class();
+                let closure = self.heap.alloc(Object::Closure(Closure {
+                    function,
+                    upvalues,
+                    class,
+                }));
+                self.push(Value::Object(closure));
+            }
+            OpCode::Return => {
+                let result = self.pop();
+                let frame = self.frames.pop().unwrap();
+                self.close_upvalues(frame.base);
+                if let Some(field) = frame.field {
+                    // Field initializers run before the constructor, the
+                    // last one to finish leaves the instance as the result
+                    let instance = self.stack[frame.base];
+                    self.set_property(instance, field, result)?;
+                    self.stack.truncate(frame.base);
+                    if self.frames.len() == depth {
+                        return Ok(Some(self.pop()));
+                    }
+                    return Ok(None);
+                }
+                self.stack.truncate(frame.base);
+                if self.frames.len() == depth {
+                    return Ok(Some(result));
+                }
+                self.push(result);
+            }
+            OpCode::Array | OpCode::Tuple => {
+                let length = self.read_u16() as usize;
+                let elements = self.stack.split_off(self.stack.len() - length);
+                let object = match op {
+                    OpCode::Array => Object::Array(elements),
+                    _ => Object::Tuple(elements),
+                };
+                let object = self.heap.alloc(object);
+                self.push(Value::Object(object));
+            }
+            OpCode::ArrayPush => {
+                let value = self.pop();
+                self.array_mut().push(value);
+            }
+            OpCode::ArrayExtend => {
+                let source = self.pop();
+                let elements = ops::spread_elements(&self.heap, source)?;
+                self.array_mut().extend(elements);
+            }
+            OpCode::Map => {
+                let length = self.read_u16() as usize;
+                let values = self.stack.split_off(self.stack.len() - length * 2);
+                let map = self.heap.alloc(Object::Map(Vec::new()));
+                for entry in values.chunks(2) {
+                    ops::map_insert(&mut self.heap, map, entry[0], entry[1]);
+                }
+                self.push(Value::Object(map));
+            }
+            OpCode::MapInsert => {
+                let value = self.pop();
+                let key = self.pop();
+                let map = self.peek(0).as_object().unwrap();
+                ops::map_insert(&mut self.heap, map, key, value);
+            }
+            OpCode::MapExtend => {
+                let source = self.pop();
+                let entries = ops::spread_entries(&self.heap, source)?;
+                let map = self.peek(0).as_object().unwrap();
+                for (key, value) in entries {
+                    ops::map_insert(&mut self.heap, map, key, value);
+                }
+            }
+            OpCode::Class => {
+                let name = self.read_name();
+                let class = self.heap.alloc(Object::Class(Class {
+                    name: name.to_string(),
+                    superclass: None,
+                    fields: Vec::new(),
+                    methods: HashMap::new(),
+                    constructor: None,
+                }));
+                self.push(Value::Object(class));
+            }
+            OpCode::Inherit => {
+                let superclass = self.pop();
+                let superclass = match superclass.as_object() {
+                    Some(object) if matches!(self.heap.get(object), Object::Class(_)) => object,
+                    _ => {
+                        return Err(format!(
+                            "Cannot inherit from a value of type '{}'",
+                            self.heap.type_name(superclass)
+                        ))
+                    }
+                };
+                // Members are copied down, the subclass overrides them later
+                let inherited = self.heap.class(superclass);
+                let fields = inherited.fields.clone();
+                let methods = inherited.methods.clone();
+                let constructor = inherited.constructor;
+                let class = self.class_mut();
+                class.superclass = Some(superclass);
+                class.fields = fields;
+                class.methods = methods;
+                class.constructor = constructor;
+            }
+            OpCode::Method => {
+                let name = self.read_name();
+                let method = self.member_closure();
+                self.class_mut().methods.insert(name, method);
+            }
+            OpCode::Constructor => {
+                let constructor = self.member_closure();
+                self.class_mut().constructor = Some(constructor);
+            }
+            OpCode::Field => {
+                let name = self.read_name();
+                let initializer = match self.peek(0) {
+                    Value::Null => self.pop(),
+                    _ => Value::Object(self.member_closure()),
+                };
+                let fields = &mut self.class_mut().fields;
+                match fields.iter_mut().find(|(field, _)| *field == name) {
+                    Some(field) => field.1 = initializer,
+                    None => fields.push((name, initializer)),
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Pops the closure of a member of the class below and makes it belong
+    /// to the class.
+    fn member_closure(&mut self) -> ObjRef {
+        let closure = self.pop().as_object().unwrap();
+        let class = self.peek(0).as_object().unwrap();
+        if let Object::Closure(member) = self.heap.get_mut(closure) {
+            member.class = Some(class);
+        }
+        closure
+    }
+
+    /// Class under construction on top of the stack.
+    fn class_mut(&mut self) -> &mut Class {
+        let class = self.peek(0).as_object().unwrap();
+        match self.heap.get_mut(class) {
+            Object::Class(class) => class,
+            object => panic!("expected a class, got {:?}", object),
+        }
+    }
+
+    /// Reads a field of an instance, or binds one of its methods. Other
+    /// values only have the builtin properties.
+    fn property(&mut self, object: Value, name: Symbol) -> Result<Value, String> {
+        if let Some(Object::Instance(instance)) = object.as_object().map(|o| self.heap.get(o)) {
+            if let Some(value) = instance.fields.get(&name) {
+                return Ok(*value);
+            }
+            if let Some(method) = self.heap.class(instance.class).methods.get(&name) {
+                let bound = BoundMethod {
+                    receiver: object,
+                    method: *method,
+                };
+                return Ok(Value::Object(self.heap.alloc(Object::BoundMethod(bound))));
+            }
+        }
+        ops::property(&self.heap, object, name)
+    }
+
+    /// Assigns a field of an instance. Fields are all created with the
+    /// instance, there is no adding new ones.
+    fn set_property(&mut self, object: Value, name: Symbol, value: Value) -> Result<(), String> {
+        if let Some(Object::Instance(instance)) = object.as_object().map(|o| self.heap.get_mut(o)) {
+            if let Some(field) = instance.fields.get_mut(&name) {
+                *field = value;
+                return Ok(());
+            }
+        }
+        Err(format!(
+            "Cannot assign property '{}' of a value of type '{}'",
+            name,
+            self.heap.type_name(object)
+        ))
+    }
+
+    /// Calls the method `name` of the receiver below `count` arguments. A
+    /// field holding a function is called without a receiver.
+    fn invoke(&mut self, site: usize, name: u16, count: usize) -> Result<(), String> {
+        let receiver = self.peek(count);
+        let class = match receiver.as_object().map(|o| self.heap.get(o)) {
+            Some(Object::Instance(instance)) => instance.class,
+            _ => {
+                let name = self.name_at(name);
+                let callee = ops::property(&self.heap, receiver, name)?;
+                let slot = self.stack.len() - count - 1;
+                self.stack[slot] = callee;
+                return self.call(count);
+            }
+        };
+
+        // Instances of a class all have the same fields, a cached method is
+        // never shadowed by one
+        let key = (Rc::as_ptr(&self.frame().function), site);
+        if let Some((cached, method)) = self.method_cache.get(&key) {
+            if *cached == class {
+                return self.call_closure(*method, count, None);
+            }
+        }
+
+        let name = self.name_at(name);
+        let instance = receiver.as_object().unwrap();
+        let field = match self.heap.get(instance) {
+            Object::Instance(instance) => instance.fields.contains_key(&name),
+            _ => false,
+        };
+        if let (false, Some(method)) = (field, self.heap.class(class).methods.get(&name)) {
+            let method = *method;
+            self.method_cache.insert(key, (class, method));
+            return self.call_closure(method, count, None);
+        }
+        let callee = self.property(receiver, name)?;
+        let slot = self.stack.len() - count - 1;
+        self.stack[slot] = callee;
+        self.call(count)
+    }
+
+    /// Object of a string constant, allocated the first time it's loaded.
+    fn string_constant(&mut self, string: Symbol) -> ObjRef {
+        match self.strings.get(&string) {
+            Some(object) => *object,
+            None => {
+                let object = self.heap.alloc(Object::String(string.to_string()));
+                self.strings.insert(string, object);
+                object
+            }
+        }
+    }
+
+    /// Upvalue `index` of the running closure.
+    fn upvalue(&self, index: usize) -> ObjRef {
+        let frame = self.frames.last().unwrap();
+        self.heap.closure(frame.closure).upvalues[index]
+    }
+
+    /// Returns the open upvalue of a stack slot, creating it unless a
+    /// closure already captured the slot.
+    fn capture_upvalue(&mut self, slot: usize) -> ObjRef {
+        let position = self
+            .open_upvalues
+            .binary_search_by_key(&slot, |upvalue| self.open_slot(*upvalue));
+        match position {
+            Ok(position) => self.open_upvalues[position],
+            Err(position) => {
+                let upvalue = self.heap.alloc(Object::Upvalue(Upvalue::Open(slot)));
+                self.open_upvalues.insert(position, upvalue);
+                upvalue
+            }
+        }
+    }
+
+    fn open_slot(&self, upvalue: ObjRef) -> usize {
+        match self.heap.get(upvalue) {
+            Object::Upvalue(Upvalue::Open(slot)) => *slot,
+            object => panic!("expected an open upvalue, got {:?}", object),
+        }
+    }
+
+    /// Moves the variables of slots from `start` up off the stack into
+    /// their upvalues.
+    fn close_upvalues(&mut self, start: usize) {
+        while let Some(&upvalue) = self.open_upvalues.last() {
+            let slot = self.open_slot(upvalue);
+            if slot < start {
+                break;
+            }
+            let value = self.stack.get(slot).copied().unwrap_or(Value::Null);
+            *self.heap.get_mut(upvalue) = Object::Upvalue(Upvalue::Closed(value));
+            self.open_upvalues.pop();
+        }
+    }
+
+    /// Class whose method, constructor or field initializer is running.
+    fn current_class(&self) -> Option<ObjRef> {
+        let frame = self.frames.last().unwrap();
+        self.heap.closure(frame.closure).class
+    }
+
+    /// Superclass of the class whose code is running.
+    fn superclass(&self) -> Result<ObjRef, String> {
+        self.current_class()
+            .and_then(|class| self.heap.class(class).superclass)
+            .ok_or_else(|| String::from("Can't use 'super' in a class without a superclass"))
+    }
+
+    fn super_method(&self, name: Symbol) -> Result<ObjRef, String> {
+        let superclass = self.superclass()?;
+        let superclass = self.heap.class(superclass);
+        superclass
+            .methods
+            .get(&name)
+            .copied()
+            .ok_or_else(|| format!("Superclass '{}' has no method '{}'", superclass.name, name))
+    }
+
+    /// Array under construction on top of the stack.
+    fn array_mut(&mut self) -> &mut Vec<Value> {
+        let array = self.peek(0).as_object().unwrap();
+        match self.heap.get_mut(array) {
+            Object::Array(elements) => elements,
+            object => panic!("expected an array, got {:?}", object),
+        }
+    }
+
+    /// Calls the callee below `count` arguments on the stack. Functions get
+    /// a new frame, natives run right away and leave their result in place
+    /// of the callee.
+    fn call(&mut self, count: usize) -> Result<(), String> {
+        let callee = self.peek(count);
+        let object = match callee {
+            Value::Object(object) => self.heap.get(object),
+            _ => {
+                return Err(format!(
+                    "Value of type '{}' is not callable",
+                    self.heap.type_name(callee)
+                ))
+            }
+        };
+
+        match object {
+            Object::Closure(_) => {
+                self.call_closure(callee.as_object().unwrap(), count, None)?;
+            }
+            Object::BoundMethod(bound) => {
+                let method = bound.method;
+                let slot = self.stack.len() - count - 1;
+                self.stack[slot] = bound.receiver;
+                self.call_closure(method, count, None)?;
+            }
+            Object::Class(_) => self.instantiate(callee.as_object().unwrap(), count)?,
+            Object::Native(native) => {
+                let native = *native;
+                check_arity(native.parameters.len(), count)?;
+                let start = self.stack.len() - count;
+                let arguments = self.stack.split_off(start);
+                let result = (native.function)(self, &arguments)?;
+                self.pop();
+                self.push(result);
+            }
+            _ => {
+                return Err(format!(
+                    "Value of type '{}' is not callable",
+                    self.heap.type_name(callee)
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Pushes a frame running `closure` on the `count` arguments on top of
+    /// the stack.
+    fn call_closure(
+        &mut self,
+        closure: ObjRef,
+        count: usize,
+        field: Option<Symbol>,
+    ) -> Result<(), String> {
+        let function = self.heap.closure(closure).function.clone();
+        check_arity(function.arity, count)?;
+        if self.frames.len() == FRAMES_MAX {
+            return Err(String::from("Stack overflow"));
+        }
+        self.frames.push(CallFrame {
+            closure,
+            function,
+            ip: 0,
+            base: self.stack.len() - count - 1,
+            field,
+        });
+        Ok(())
+    }
+
+    /// Creates an instance of `class` in place of the class below `count`
+    /// arguments. Field initializers run first, from the root of the class
+    /// hierarchy down, then the constructor receives the arguments.
+    fn instantiate(&mut self, class: ObjRef, count: usize) -> Result<(), String> {
+        let Class {
+            fields,
+            constructor,
+            ..
+        } = self.heap.class(class);
+        let initializers = fields.clone();
+        let constructor = *constructor;
+        let fields = initializers
+            .iter()
+            .map(|(name, _)| (*name, Value::Null))
+            .collect();
+        let instance = self
+            .heap
+            .alloc(Object::Instance(Instance { class, fields }));
+        let slot = self.stack.len() - count - 1;
+        self.stack[slot] = Value::Object(instance);
+
+        match constructor {
+            Some(constructor) => self.call_closure(constructor, count, None)?,
+            None => check_arity(0, count)?,
+        }
+        // Frames run in reverse order, the first field is initialized first
+        for (name, initializer) in initializers.into_iter().rev() {
+            if let Some(initializer) = initializer.as_object() {
+                self.push(Value::Object(instance));
+                self.call_closure(initializer, 0, Some(name))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_arity(arity: usize, count: usize) -> Result<(), String> {
+    if arity == count {
+        Ok(())
+    } else {
+        Err(format!("Expected {} argument(s) but got {}", arity, count))
+    }
+}
@@ -0,0 +1,47 @@
+use crate::vm::value::Value;
+use crate::vm::Vm;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Function implemented by the runtime, visible to programs as a global.
+/// The signature uses builtin type names so the checker can type calls.
+#[derive(Debug)]
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub parameters: &'static [&'static str],
+    pub return_type: &'static str,
+    pub function: fn(&mut Vm, &[Value]) -> Result<Value, String>,
+}
+
+pub static NATIVES: &[NativeFunction] = &[
+    NativeFunction {
+        name: "print",
+        parameters: &["any"],
+        return_type: "void",
+        function: print,
+    },
+    NativeFunction {
+        name: "clock",
+        parameters: &[],
+        return_type: "float",
+        function: clock,
+    },
+];
+
+pub fn lookup(name: &str) -> Option<&'static NativeFunction> {
+    NATIVES.iter().find(|native| native.name == name)
+}
+
+fn print(vm: &mut Vm, arguments: &[Value]) -> Result<Value, String> {
+    let text = vm.heap.display(arguments[0]);
+    writeln!(vm.output, "{}", text).map_err(|e| e.to_string())?;
+    Ok(Value::Null)
+}
+
+/// Seconds since the Unix epoch.
+fn clock(_vm: &mut Vm, _arguments: &[Value]) -> Result<Value, String> {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?;
+    Ok(Value::Float(elapsed.as_secs_f64()))
+}
@@ -0,0 +1,295 @@
+use crate::bytecode::chunk::OpCode;
+use crate::symbol::Symbol;
+use crate::vm::heap::Heap;
+use crate::vm::value::{ObjRef, Object, Value};
+
+/// Source symbol of an operator instruction, for error messages.
+fn symbol(op: OpCode) -> &'static str {
+    match op {
+        OpCode::Equal => "==",
+        OpCode::NotEqual => "!=",
+        OpCode::Less => "<",
+        OpCode::LessEqual => "<=",
+        OpCode::Greater => ">",
+        OpCode::GreaterEqual => ">=",
+        OpCode::Add => "+",
+        OpCode::Subtract | OpCode::Negate => "-",
+        OpCode::Multiply => "*",
+        OpCode::Divide => "/",
+        OpCode::Modulo => "%",
+        OpCode::Power => "**",
+        OpCode::BitAnd => "&",
+        OpCode::BitOr => "|",
+        OpCode::BitXor => "^",
+        OpCode::ShiftLeft => "<<",
+        OpCode::ShiftRight => ">>",
+        OpCode::Not => "!",
+        OpCode::BitNot => "~",
+        _ => "?",
+    }
+}
+
+/// Applies a comparison, arithmetic or bitwise operator instruction.
+///
+/// Integer arithmetic reports overflow and division by zero instead of
+/// wrapping. Mixing ints and floats produces a float, and `+` concatenates
+/// as soon as one side is a string.
+pub fn binary(heap: &mut Heap, op: OpCode, a: Value, b: Value) -> Result<Value, String> {
+    match op {
+        OpCode::Equal => return Ok(Value::Bool(heap.values_equal(a, b))),
+        OpCode::NotEqual => return Ok(Value::Bool(!heap.values_equal(a, b))),
+        OpCode::Add if heap.string(a).is_some() || heap.string(b).is_some() => {
+            let text = heap.display(a) + &heap.display(b);
+            return Ok(Value::Object(heap.alloc(Object::String(text))));
+        }
+        _ => {}
+    }
+
+    let result = match (a, b) {
+        (Value::Int(a), Value::Int(b)) => return int_binary(op, a, b),
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            float_binary(op, as_float(a), as_float(b))
+        }
+        _ => match (heap.string(a), heap.string(b)) {
+            (Some(a), Some(b)) => compare(op, a, b),
+            _ => None,
+        },
+    };
+    result.ok_or_else(|| {
+        format!(
+            "Operator '{}' cannot be applied to values of type '{}' and '{}'",
+            symbol(op),
+            heap.type_name(a),
+            heap.type_name(b)
+        )
+    })
+}
+
+pub fn unary(heap: &Heap, op: OpCode, value: Value) -> Result<Value, String> {
+    match (op, value) {
+        (OpCode::Not, _) => Ok(Value::Bool(value.is_falsey())),
+        (OpCode::Negate, Value::Int(value)) => value
+            .checked_neg()
+            .map(Value::Int)
+            .ok_or_else(|| String::from("Integer overflow")),
+        (OpCode::Negate, Value::Float(value)) => Ok(Value::Float(-value)),
+        (OpCode::BitNot, Value::Int(value)) => Ok(Value::Int(!value)),
+        _ => Err(format!(
+            "Operator '{}' cannot be applied to a value of type '{}'",
+            symbol(op),
+            heap.type_name(value)
+        )),
+    }
+}
+
+fn as_float(value: Value) -> f64 {
+    match value {
+        Value::Int(value) => value as f64,
+        Value::Float(value) => value,
+        _ => f64::NAN,
+    }
+}
+
+fn compare<T: PartialOrd + ?Sized>(op: OpCode, a: &T, b: &T) -> Option<Value> {
+    let result = match op {
+        OpCode::Less => a < b,
+        OpCode::LessEqual => a <= b,
+        OpCode::Greater => a > b,
+        OpCode::GreaterEqual => a >= b,
+        _ => return None,
+    };
+    Some(Value::Bool(result))
+}
+
+fn int_binary(op: OpCode, a: i64, b: i64) -> Result<Value, String> {
+    if let Some(result) = compare(op, &a, &b) {
+        return Ok(result);
+    }
+    let overflow = || String::from("Integer overflow");
+    let value = match op {
+        OpCode::Add => a.checked_add(b).ok_or_else(overflow)?,
+        OpCode::Subtract => a.checked_sub(b).ok_or_else(overflow)?,
+        OpCode::Multiply => a.checked_mul(b).ok_or_else(overflow)?,
+        OpCode::Divide | OpCode::Modulo if b == 0 => {
+            return Err(String::from("Division by zero"));
+        }
+        OpCode::Divide => a.checked_div(b).ok_or_else(overflow)?,
+        OpCode::Modulo => a.checked_rem(b).ok_or_else(overflow)?,
+        OpCode::Power if b < 0 => return Err(String::from("Negative integer exponent")),
+        OpCode::Power => u32::try_from(b)
+            .ok()
+            .and_then(|b| a.checked_pow(b))
+            .ok_or_else(overflow)?,
+        OpCode::BitAnd => a & b,
+        OpCode::BitOr => a | b,
+        OpCode::BitXor => a ^ b,
+        OpCode::ShiftLeft | OpCode::ShiftRight if !(0..64).contains(&b) => {
+            return Err(format!("Shift amount {} is out of range", b));
+        }
+        OpCode::ShiftLeft => a << b,
+        OpCode::ShiftRight => a >> b,
+        _ => {
+            return Err(format!(
+                "Operator '{}' cannot be applied to ints",
+                symbol(op)
+            ))
+        }
+    };
+    Ok(Value::Int(value))
+}
+
+fn float_binary(op: OpCode, a: f64, b: f64) -> Option<Value> {
+    if let Some(result) = compare(op, &a, &b) {
+        return Some(result);
+    }
+    let value = match op {
+        OpCode::Add => a + b,
+        OpCode::Subtract => a - b,
+        OpCode::Multiply => a * b,
+        OpCode::Divide => a / b,
+        OpCode::Modulo => a % b,
+        OpCode::Power => a.powf(b),
+        _ => return None,
+    };
+    Some(Value::Float(value))
+}
+
+/// Value of the property `name`. Strings and collections only have a
+/// `length`.
+pub fn property(heap: &Heap, object: Value, name: Symbol) -> Result<Value, String> {
+    if let (Value::Object(object), "length") = (object, name.as_str()) {
+        let length = match heap.get(object) {
+            Object::String(string) => Some(string.chars().count()),
+            Object::Array(elements) | Object::Tuple(elements) => Some(elements.len()),
+            Object::Map(entries) => Some(entries.len()),
+            _ => None,
+        };
+        if let Some(length) = length {
+            return Ok(Value::Int(length as i64));
+        }
+    }
+    Err(format!(
+        "Value of type '{}' has no property '{}'",
+        heap.type_name(object),
+        name
+    ))
+}
+
+/// Checks that `index` is an int within `0..length`.
+fn element_index(heap: &Heap, index: Value, length: usize) -> Result<usize, String> {
+    match index {
+        Value::Int(i) if i >= 0 && (i as usize) < length => Ok(i as usize),
+        Value::Int(i) => Err(format!(
+            "Index {} is out of bounds for length {}",
+            i, length
+        )),
+        _ => Err(format!(
+            "Index must be an int but got '{}'",
+            heap.type_name(index)
+        )),
+    }
+}
+
+/// `object[index]`. Reading a missing map key produces null.
+pub fn get_index(heap: &mut Heap, object: Value, index: Value) -> Result<Value, String> {
+    let reference = match object {
+        Value::Object(reference) => reference,
+        _ => {
+            return Err(format!(
+                "Type '{}' cannot be indexed",
+                heap.type_name(object)
+            ))
+        }
+    };
+    match heap.get(reference) {
+        Object::Array(elements) | Object::Tuple(elements) => {
+            let i = element_index(heap, index, elements.len())?;
+            Ok(elements[i])
+        }
+        Object::Map(entries) => Ok(entries
+            .iter()
+            .find(|(key, _)| heap.values_equal(*key, index))
+            .map_or(Value::Null, |(_, value)| *value)),
+        Object::String(string) => {
+            let i = element_index(heap, index, string.chars().count())?;
+            let character = string.chars().nth(i).unwrap().to_string();
+            Ok(Value::Object(heap.alloc(Object::String(character))))
+        }
+        _ => Err(format!(
+            "Type '{}' cannot be indexed",
+            heap.type_name(object)
+        )),
+    }
+}
+
+/// `object[index] = value` on an array or a map.
+pub fn set_index(heap: &mut Heap, object: Value, index: Value, value: Value) -> Result<(), String> {
+    let reference = match object {
+        Value::Object(reference) => reference,
+        _ => {
+            return Err(format!(
+                "Type '{}' cannot be indexed",
+                heap.type_name(object)
+            ))
+        }
+    };
+    match heap.get(reference) {
+        Object::Array(elements) => {
+            let i = element_index(heap, index, elements.len())?;
+            if let Object::Array(elements) = heap.get_mut(reference) {
+                elements[i] = value;
+            }
+            Ok(())
+        }
+        Object::Map(_) => {
+            map_insert(heap, reference, index, value);
+            Ok(())
+        }
+        _ => Err(format!(
+            "Elements of type '{}' cannot be assigned",
+            heap.type_name(object)
+        )),
+    }
+}
+
+/// Inserts an entry into `map`, replacing the value of an equal key.
+pub fn map_insert(heap: &mut Heap, map: ObjRef, key: Value, value: Value) {
+    let position = match heap.get(map) {
+        Object::Map(entries) => entries
+            .iter()
+            .position(|(existing, _)| heap.values_equal(*existing, key)),
+        _ => return,
+    };
+    if let Object::Map(entries) = heap.get_mut(map) {
+        match position {
+            Some(i) => entries[i].1 = value,
+            None => entries.push((key, value)),
+        }
+    }
+}
+
+/// Elements a spread expands to.
+pub fn spread_elements(heap: &Heap, value: Value) -> Result<Vec<Value>, String> {
+    if let Value::Object(object) = value {
+        if let Object::Array(elements) | Object::Tuple(elements) = heap.get(object) {
+            return Ok(elements.clone());
+        }
+    }
+    Err(format!(
+        "Cannot spread a value of type '{}'",
+        heap.type_name(value)
+    ))
+}
+
+/// Entries a spread inside a map literal expands to.
+pub fn spread_entries(heap: &Heap, value: Value) -> Result<Vec<(Value, Value)>, String> {
+    if let Value::Object(object) = value {
+        if let Object::Map(entries) = heap.get(object) {
+            return Ok(entries.clone());
+        }
+    }
+    Err(format!(
+        "Cannot spread a value of type '{}' into a map",
+        heap.type_name(value)
+    ))
+}
@@ -0,0 +1,112 @@
+use crate::bytecode::chunk::Function;
+use crate::symbol::Symbol;
+use crate::vm::natives::NativeFunction;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Handle of an object living on the VM heap.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct ObjRef(pub(crate) u32);
+
+/// Runtime value. Primitives are stored inline, everything else is an
+/// object on the heap.
+#[derive(Debug, Clone, Copy)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Object(ObjRef),
+}
+
+impl Value {
+    /// `null` and `false` are the only falsey values.
+    pub fn is_falsey(self) -> bool {
+        matches!(self, Value::Null | Value::Bool(false))
+    }
+
+    pub fn as_object(self) -> Option<ObjRef> {
+        match self {
+            Value::Object(object) => Some(object),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Closure {
+    pub function: Rc<Function>,
+    /// `Upvalue` objects of the variables the function captures.
+    pub upvalues: Vec<ObjRef>,
+    /// Class the closure is a method, constructor or field initializer of,
+    /// or whose method created it. `super` starts looking up members from
+    /// its superclass.
+    pub class: Option<ObjRef>,
+}
+
+/// Variable captured by a closure. It stays on the stack while the
+/// declaring function uses it and moves into the upvalue once it goes out
+/// of scope, closures sharing the upvalue see each other's writes.
+#[derive(Debug)]
+pub enum Upvalue {
+    /// Index of the stack slot holding the variable.
+    Open(usize),
+    Closed(Value),
+}
+
+#[derive(Debug)]
+pub struct Class {
+    pub name: String,
+    pub superclass: Option<ObjRef>,
+    /// Field names with their initializer closure, or null when they start
+    /// out null. Inherited fields come first.
+    pub fields: Vec<(Symbol, Value)>,
+    /// Methods closures, including inherited ones.
+    pub methods: HashMap<Symbol, ObjRef>,
+    /// Constructor closure, inherited when the class doesn't declare one.
+    pub constructor: Option<ObjRef>,
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub class: ObjRef,
+    pub fields: HashMap<Symbol, Value>,
+}
+
+/// Method read off an instance as a value, calling it passes the receiver
+/// as `this`.
+#[derive(Debug)]
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: ObjRef,
+}
+
+#[derive(Debug)]
+pub enum Object {
+    String(String),
+    Array(Vec<Value>),
+    /// Entries in insertion order.
+    Map(Vec<(Value, Value)>),
+    Tuple(Vec<Value>),
+    Closure(Closure),
+    Upvalue(Upvalue),
+    Native(&'static NativeFunction),
+    Class(Class),
+    Instance(Instance),
+    BoundMethod(BoundMethod),
+}
+
+impl Object {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::String(_) => "string",
+            Object::Array(_) => "array",
+            Object::Map(_) => "map",
+            Object::Tuple(_) => "tuple",
+            Object::Closure(_) | Object::Native(_) | Object::BoundMethod(_) => "function",
+            Object::Class(_) => "class",
+            Object::Upvalue(_) => "upvalue",
+            Object::Instance(_) => "instance",
+        }
+    }
+}